@@ -0,0 +1,531 @@
+// A two pass 6502 assembler for the vasm oldstyle syntax with -dotdir, which the BE6502 videos use:
+//
+//     PORTB = $6000          ; constants
+//       .org $8000           ; directives start with a dot
+//     reset:                 ; labels end with a colon
+//       lda #%11111111
+//       sta PORTB + 2
+//       jmp reset
+//       .org $fffc
+//       .word reset
+//
+// Mnemonics, directives and index registers do not care about case, symbols do. Operands which fit
+// in a byte use zero page addressing when the instruction has it, unless they refer to a symbol only
+// defined further down.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::components::cpu6502::{CPU6502, IAM, IAMSubMode};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    // Line in the source, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.message);
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+// Bytes assembled to consecutive addresses.
+#[derive(Debug)]
+struct Segment {
+    line: usize,
+    address: u16,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Program {
+    segments: Vec<Segment>,
+}
+
+impl Program {
+    // The memory from `start` on as a flat image of `size` bytes, like vasm's -Fbin output. Gaps are
+    // filled with `fill`, code outside the image is an error.
+    pub fn image(&self, start: u16, size: usize, fill: u8) -> Result<Vec<u8>, AssembleError> {
+        let mut image = vec![fill; size];
+        for segment in &self.segments {
+            let offset = (segment.address as usize).checked_sub(start as usize)
+                .filter(|offset| offset + segment.bytes.len() <= size)
+                .ok_or_else(|| AssembleError {
+                    line: segment.line,
+                    message: format!("${:04X} is outside the image ${:04X}-${:04X}", segment.address, start,
+                        start as usize + size - 1),
+                })?;
+            image[offset..offset + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+        return Ok(image);
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Program, AssembleError> {
+    let mut assembler = Assembler::new();
+    assembler.pass(source, false)?;
+    let segments = assembler.pass(source, true)?;
+    return Ok(Program { segments });
+}
+
+struct Assembler {
+    // Opcodes by lower case mnemonic and addressing mode, taken from the CPU so both always agree.
+    opcodes: HashMap<(String, IAM), u8>,
+    symbols: HashMap<String, i64>,
+    // Lines whose operand could not be resolved in the first pass, they keep absolute addressing so
+    // the addresses of the first pass stay valid.
+    wide: HashSet<usize>,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        let cpu = CPU6502::new();
+        let mut opcodes = HashMap::new();
+        // Only the documented instructions, the undocumented opcodes include more NOPs and another SBC.
+        for byte in 0x00..=0xFF {
+            if let Some(opcode) = cpu.decode(byte).filter(|opcode| !opcode.undocumented()) {
+                opcodes.insert((format!("{:?}", opcode.instruction()).to_lowercase(), opcode.mode()), byte);
+            }
+        }
+        return Assembler { opcodes, symbols: HashMap::new(), wide: HashSet::new() };
+    }
+
+    // In the first pass symbols may still be unknown, the final pass has to resolve everything.
+    fn pass(&mut self, source: &str, last: bool) -> Result<Vec<Segment>, AssembleError> {
+        let mut output = Output { segments: Vec::new(), address: 0x0000, line: 0 };
+        for (index, text) in source.lines().enumerate() {
+            output.line = index + 1;
+            self.line(strip_comment(text), &mut output, last)
+                .map_err(|message| AssembleError { line: output.line, message })?;
+        }
+        return Ok(output.segments);
+    }
+
+    fn line(&mut self, text: &str, output: &mut Output, last: bool) -> Result<(), String> {
+        let mut text = text.trim();
+
+        // NAME = value
+        if let Some((name, value)) = text.split_once('=') {
+            let name = name.trim();
+            if is_symbol(name) {
+                if let Some(value) = self.evaluate(value, output.address, last)? {
+                    self.symbols.insert(name.to_string(), value);
+                }
+                return Ok(());
+            }
+        }
+
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if is_symbol(label) {
+                self.define(label, output.address as i64, last)?;
+                text = rest.trim();
+            }
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let (word, operand) = match text.find(char::is_whitespace) {
+            Some(position) => (&text[..position], text[position..].trim()),
+            None => (text, ""),
+        };
+        let word = word.to_lowercase();
+        if let Some(directive) = word.strip_prefix('.') {
+            return self.directive(directive, operand, output, last);
+        }
+        return self.instruction(&word, operand, output, last);
+    }
+
+    fn define(&mut self, label: &str, address: i64, last: bool) -> Result<(), String> {
+        if !last && self.symbols.contains_key(label) {
+            return Err(format!("{} is defined twice", label));
+        }
+        self.symbols.insert(label.to_string(), address);
+        return Ok(());
+    }
+
+    fn directive(&mut self, directive: &str, operand: &str, output: &mut Output, last: bool) -> Result<(), String> {
+        match directive {
+            "org" => {
+                let address = self.evaluate(operand, output.address, true)?.unwrap_or_default();
+                output.address = u16::try_from(address).map_err(|_| format!("${:X} is not an address", address))?;
+            }
+            "byte" | "db" | "ascii" | "asciiz" => {
+                let mut bytes = Vec::new();
+                for item in split_list(operand) {
+                    if let Some(string) = item.strip_prefix('"') {
+                        let string = string.strip_suffix('"').ok_or_else(|| format!("unterminated string {}", item))?;
+                        bytes.extend_from_slice(string.as_bytes());
+                    } else {
+                        let value = self.evaluate(item, output.address, last)?.unwrap_or_default();
+                        bytes.push(byte(value)?);
+                    }
+                }
+                if directive == "asciiz" {
+                    bytes.push(0x00);
+                }
+                output.emit(&bytes)?;
+            }
+            "word" | "dw" => {
+                for item in split_list(operand) {
+                    let value = self.evaluate(item, output.address, last)?.unwrap_or_default();
+                    output.emit(&word(value)?.to_le_bytes())?;
+                }
+            }
+            _ => return Err(format!("unknown directive .{}", directive)),
+        }
+        return Ok(());
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operand: &str, output: &mut Output, last: bool) -> Result<(), String> {
+        let has = |mode: IAM| self.opcodes.contains_key(&(mnemonic.to_string(), mode));
+        if !self.opcodes.keys().any(|(known, _)| known == mnemonic) {
+            return Err(format!("unknown instruction {}", mnemonic));
+        }
+
+        if operand.is_empty() || operand.eq_ignore_ascii_case("a") {
+            let mode = if has(IAM::Accumulator) { IAM::Accumulator } else { IAM::Implied };
+            return self.emit(mnemonic, mode, &[], output);
+        }
+
+        if let Some(value) = operand.strip_prefix('#') {
+            let value = self.evaluate(value, output.address, last)?.unwrap_or_default();
+            return self.emit(mnemonic, IAM::Immediate, &[byte(value)?], output);
+        }
+
+        if has(IAM::Relative) {
+            let target = self.evaluate(operand, output.address, last)?;
+            let offset = match target {
+                Some(target) => target - (output.address as i64 + 2),
+                None => 0,
+            };
+            if !(-128..=127).contains(&offset) {
+                return Err(format!("branch target {} is {} bytes away", operand, offset));
+            }
+            return self.emit(mnemonic, IAM::Relative, &[offset as u8], output);
+        }
+
+        // (zp,X), (zp),Y and JMP (abs). Anything else in parentheses is an expression.
+        let lower = operand.to_lowercase();
+        if operand.starts_with('(') {
+            if let Some(pointer) = lower.strip_suffix(",x)") {
+                let value = self.evaluate(&operand[1..pointer.len()], output.address, last)?.unwrap_or_default();
+                return self.emit(mnemonic, IAM::Indirect(IAMSubMode::X), &[byte(value)?], output);
+            }
+            if let Some(pointer) = lower.strip_suffix("),y") {
+                let value = self.evaluate(&operand[1..pointer.len()], output.address, last)?.unwrap_or_default();
+                return self.emit(mnemonic, IAM::Indirect(IAMSubMode::Y), &[byte(value)?], output);
+            }
+            if has(IAM::Indirect(IAMSubMode::N)) && operand.ends_with(')') {
+                let value = self.evaluate(&operand[1..operand.len() - 1], output.address, last)?.unwrap_or_default();
+                return self.emit(mnemonic, IAM::Indirect(IAMSubMode::N), &word(value)?.to_le_bytes(), output);
+            }
+        }
+
+        let (expression, index) = if lower.ends_with(",x") {
+            (&operand[..operand.len() - 2], IAMSubMode::X)
+        } else if lower.ends_with(",y") {
+            (&operand[..operand.len() - 2], IAMSubMode::Y)
+        } else {
+            (operand, IAMSubMode::N)
+        };
+        let value = self.evaluate(expression, output.address, last)?;
+        if value.is_none() {
+            self.wide.insert(output.line);
+        }
+        let zero_page = value.is_some_and(|value| (0x00..=0xFF).contains(&value)) && !self.wide.contains(&output.line);
+        let value = value.unwrap_or_default();
+
+        if has(IAM::ZeroPage(index)) && (zero_page || !has(IAM::Absolute(index))) {
+            return self.emit(mnemonic, IAM::ZeroPage(index), &[byte(value)?], output);
+        }
+        if has(IAM::Absolute(index)) {
+            return self.emit(mnemonic, IAM::Absolute(index), &word(value)?.to_le_bytes(), output);
+        }
+        return Err(format!("{} does not take {}", mnemonic, operand));
+    }
+
+    fn emit(&self, mnemonic: &str, mode: IAM, operand: &[u8], output: &mut Output) -> Result<(), String> {
+        let opcode = self.opcodes.get(&(mnemonic.to_string(), mode))
+            .ok_or_else(|| format!("{} does not support {:?} addressing", mnemonic, mode))?;
+        output.emit(&[*opcode])?;
+        return output.emit(operand);
+    }
+
+    // None while a symbol is still unknown, before the last pass.
+    fn evaluate(&self, expression: &str, address: u16, last: bool) -> Result<Option<i64>, String> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser { tokens: &tokens, position: 0, symbols: &self.symbols, address, last };
+        let value = parser.expression(0)?;
+        if parser.position != tokens.len() {
+            return Err(format!("invalid expression {}", expression.trim()));
+        }
+        return Ok(value);
+    }
+}
+
+struct Output {
+    segments: Vec<Segment>,
+    address: u16,
+    line: usize,
+}
+
+impl Output {
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        if self.address as usize + bytes.len() > 0x10000 {
+            return Err(String::from("code runs past $FFFF"));
+        }
+        match self.segments.last_mut() {
+            Some(segment) if segment.address as usize + segment.bytes.len() == self.address as usize => {
+                segment.bytes.extend_from_slice(bytes);
+            }
+            _ => self.segments.push(Segment { line: self.line, address: self.address, bytes: bytes.to_vec() }),
+        }
+        self.address = self.address.wrapping_add(bytes.len() as u16);
+        return Ok(());
+    }
+}
+
+fn byte(value: i64) -> Result<u8, String> {
+    if !(-0x80..=0xFF).contains(&value) {
+        return Err(format!("${:X} does not fit in a byte", value));
+    }
+    return Ok(value as u8);
+}
+
+fn word(value: i64) -> Result<u16, String> {
+    if !(-0x8000..=0xFFFF).contains(&value) {
+        return Err(format!("${:X} does not fit in a word", value));
+    }
+    return Ok(value as u16);
+}
+
+fn is_symbol(text: &str) -> bool {
+    let mut characters = text.chars();
+    return characters.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_');
+}
+
+// Everything up to a semicolon which is not part of a string or character.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (position, character) in line.char_indices() {
+        match (quote, character) {
+            (None, ';') => return &line[..position],
+            (None, '"' | '\'') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            _ => {}
+        }
+    }
+    return line;
+}
+
+// Splits at the commas outside of strings.
+fn split_list(operand: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (position, character) in operand.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push(operand[start..position].trim());
+                start = position + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(operand[start..].trim());
+    return items.into_iter().filter(|item| !item.is_empty()).collect();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Operator(char),
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let characters: Vec<char> = expression.chars().collect();
+    let mut position = 0;
+    while position < characters.len() {
+        let character = characters[position];
+        let start = position;
+        position += 1;
+        let radix = match character {
+            _ if character.is_whitespace() => continue,
+            '$' => 16,
+            // % is binary in front of a digit, there is no modulo operator.
+            '%' => 2,
+            '0'..='9' => {
+                position -= 1;
+                10
+            }
+            '\'' => {
+                let value = characters.get(position).ok_or_else(|| String::from("unterminated character"))?;
+                if characters.get(position + 1) != Some(&'\'') {
+                    return Err(String::from("unterminated character"));
+                }
+                position += 2;
+                tokens.push(Token::Number(*value as i64));
+                continue;
+            }
+            _ if character.is_ascii_alphabetic() || character == '_' => {
+                while characters.get(position).is_some_and(|next| next.is_ascii_alphanumeric() || *next == '_') {
+                    position += 1;
+                }
+                tokens.push(Token::Symbol(characters[start..position].iter().collect()));
+                continue;
+            }
+            '+' | '-' | '*' | '/' | '&' | '|' | '^' | '<' | '>' | '(' | ')' | '~' => {
+                tokens.push(Token::Operator(character));
+                continue;
+            }
+            _ => return Err(format!("unexpected {} in {}", character, expression.trim())),
+        };
+        let digits_start = position;
+        while characters.get(position).is_some_and(|next| next.is_digit(radix)) {
+            position += 1;
+        }
+        let digits: String = characters[digits_start..position].iter().collect();
+        let value = i64::from_str_radix(&digits, radix)
+            .map_err(|_| format!("invalid number {}", characters[start..position].iter().collect::<String>()))?;
+        tokens.push(Token::Number(value));
+    }
+    return Ok(tokens);
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    symbols: &'a HashMap<String, i64>,
+    address: u16,
+    last: bool,
+}
+
+impl Parser<'_> {
+    // Binary operators from the loosest binding on, the same precedence as in C.
+    fn expression(&mut self, level: usize) -> Result<Option<i64>, String> {
+        const LEVELS: [&[char]; 5] = [&['|'], &['^'], &['&'], &['+', '-'], &['*', '/']];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut value = self.expression(level + 1)?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            if !LEVELS[level].contains(operator) {
+                break;
+            }
+            let operator = *operator;
+            self.position += 1;
+            let right = self.expression(level + 1)?;
+            value = match (value, right) {
+                (Some(left), Some(right)) => Some(match operator {
+                    '|' => left | right,
+                    '^' => left ^ right,
+                    '&' => left & right,
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    _ => left.checked_div(right).ok_or_else(|| String::from("division by zero"))?,
+                }),
+                _ => None,
+            };
+        }
+        return Ok(value);
+    }
+
+    // <value is the low byte, >value the high byte, * on its own the current address.
+    fn unary(&mut self) -> Result<Option<i64>, String> {
+        let token = self.tokens.get(self.position).cloned().ok_or_else(|| String::from("missing value"))?;
+        self.position += 1;
+        return match token {
+            Token::Number(value) => Ok(Some(value)),
+            Token::Symbol(name) => match self.symbols.get(&name) {
+                Some(value) => Ok(Some(*value)),
+                None if self.last => Err(format!("undefined symbol {}", name)),
+                None => Ok(None),
+            },
+            Token::Operator('*') => Ok(Some(self.address as i64)),
+            Token::Operator('(') => {
+                let value = self.expression(0)?;
+                if self.tokens.get(self.position) != Some(&Token::Operator(')')) {
+                    return Err(String::from("missing )"));
+                }
+                self.position += 1;
+                Ok(value)
+            }
+            Token::Operator(operator) => {
+                let value = self.unary()?;
+                Ok(match operator {
+                    '-' => value.map(|value| -value),
+                    '~' => value.map(|value| !value),
+                    '<' => value.map(|value| value & 0xFF),
+                    '>' => value.map(|value| (value >> 8) & 0xFF),
+                    _ => return Err(format!("unexpected {}", operator)),
+                })
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble() {
+        let source = "
+            ZP = $10
+            PORTB = $6000       ; a constant
+              .org $8000
+            reset:  ldx #$ff
+              txs
+              lda #%10100101 & $FF
+              sta PORTB
+              sta ZP,x
+              lda (ZP),y
+              lda (ZP,X)
+              asl
+              asl a
+              lda later         ; forward, stays absolute
+              bne reset
+              jmp (vector)
+            later: .byte 1, \"a;b\", <reset, >reset
+            vector: .word reset, * + 1
+              .asciiz \"hi\"
+              .org $fffc
+              .word reset
+        ";
+        let image = assemble(source).unwrap().image(0x8000, 0x8000, 0xEA).unwrap();
+        assert_eq!(image[..0x26], [
+            0xA2, 0xFF, 0x9A, 0xA9, 0xA5, 0x8D, 0x00, 0x60, 0x95, 0x10, 0xB1, 0x10, 0xA1, 0x10, 0x0A, 0x0A,
+            0xAD, 0x18, 0x80, 0xD0, 0xEB, 0x6C, 0x1E, 0x80, 0x01, b'a', b';', b'b', 0x00, 0x80, 0x00, 0x80,
+            0x21, 0x80, b'h', b'i', 0x00, 0xEA,
+        ]);
+        assert_eq!(image[0x7FFC..], [0x00, 0x80, 0xEA, 0xEA]);
+
+        let error = |source: &str| assemble(source).unwrap_err();
+        assert_eq!(error("  .org $8000\n  bne far\n  .org $9000\nfar: nop"),
+            AssembleError { line: 2, message: String::from("branch target far is 4094 bytes away") });
+        assert_eq!(error("  lda nowhere"), AssembleError { line: 1, message: String::from("undefined symbol nowhere") });
+        assert_eq!(error("  lda #$100"), AssembleError { line: 1, message: String::from("$100 does not fit in a byte") });
+        assert_eq!(error("  foo"), AssembleError { line: 1, message: String::from("unknown instruction foo") });
+        assert_eq!(error("  stx $1234,x"), AssembleError { line: 1, message: String::from("stx does not take $1234,x") });
+        assert_eq!(error("a:\na: nop"), AssembleError { line: 2, message: String::from("a is defined twice") });
+
+        let program = assemble("  .org $7000\n  nop").unwrap();
+        assert_eq!(program.image(0x8000, 0x8000, 0x00).unwrap_err(),
+            AssembleError { line: 2, message: String::from("$7000 is outside the image $8000-$FFFF") });
+    }
+}
@@ -0,0 +1,299 @@
+use crate::components::device::{Addressable, Clocked};
+use crate::components::interrupt::InterruptSource;
+use crate::components::serial::SerialBackend;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+const DATA: u16 = 0x0;
+const STATUS: u16 = 0x1;
+const COMMAND: u16 = 0x2;
+const CONTROL: u16 = 0x3;
+
+// Status register bits.
+const OVERRUN: u8 = 0x04;
+const RECEIVER_FULL: u8 = 0x08;
+const TRANSMITTER_EMPTY: u8 = 0x10;
+const INTERRUPT: u8 = 0x80;
+
+// Command register bits.
+const DATA_TERMINAL_READY: u8 = 0x01;
+const RECEIVER_INTERRUPT_DISABLE: u8 = 0x02;
+const ECHO: u8 = 0x10;
+const PARITY_ENABLE: u8 = 0x20;
+
+// Baud rates selected by the low nibble of the control register, in hundredths of a baud. Rate 0 is
+// the external 16x clock, which is assumed to be the usual 1.8432 MHz crystal.
+const BAUD_RATES: [u64; 16] = [
+    11520000, 5000, 7500, 10992, 13458, 15000, 30000, 60000,
+    120000, 180000, 240000, 360000, 480000, 720000, 960000, 1920000,
+];
+
+// MOS 6551 Asynchronous Communications Interface Adapter. Bytes take as many emulated cycles as the
+// programmed baud rate and frame format need, relative to the CPU clock given at construction.
+pub struct Acia6551 {
+    address: u16,
+    irq: InterruptSource,
+    backend: Box<dyn SerialBackend>,
+    cpu_clock: u64,
+    receive_data: u8,
+    // Written while a byte is being sent, it waits here until the transmitter is free. Another write
+    // before then replaces it, programs are expected to wait for the transmitter to be empty.
+    transmit_data: u8,
+    queued: bool,
+    // Byte being sent.
+    shift: u8,
+    status: u8,
+    command: u8,
+    control: u8,
+    // Cycles until the byte being sent is out, 0 while the transmitter is idle.
+    transmitting: u64,
+    // Cycles until the receiver can take the next frame.
+    receiving: u64,
+}
+
+impl Acia6551 {
+    // The CPU clock is in Hz, e.g. 1_000_000 for a 1 MHz machine.
+    pub fn new(address: u16, irq: InterruptSource, backend: Box<dyn SerialBackend>, cpu_clock: u64) -> Acia6551 {
+        return Acia6551 {
+            address,
+            irq,
+            backend,
+            cpu_clock,
+            receive_data: 0,
+            transmit_data: 0,
+            queued: false,
+            shift: 0,
+            status: TRANSMITTER_EMPTY,
+            command: 0,
+            control: 0,
+            transmitting: 0,
+            receiving: 0,
+        };
+    }
+
+    // Cycles one frame takes: start bit, data bits, optional parity and stop bits.
+    fn frame_cycles(&self) -> u64 {
+        let data_bits = 8 - ((self.control >> 5) & 0x03) as u64;
+        let parity_bits = if self.command & PARITY_ENABLE != 0 { 1 } else { 0 };
+        // Two stop bits, except 1 for 8 data bits with parity and 1.5 (counted as 1) for 5 without.
+        let stop_bits = match (self.control & 0x80 != 0, data_bits, parity_bits) {
+            (false, _, _) | (true, 8, 1) | (true, 5, 0) => 1,
+            (true, _, _) => 2,
+        };
+        let bits = 1 + data_bits + parity_bits + stop_bits;
+        let cycles = self.cpu_clock * bits * 100 / BAUD_RATES[(self.control & 0x0F) as usize];
+        return cycles.max(1);
+    }
+
+    fn transmit_interrupt_enabled(&self) -> bool {
+        return (self.command >> 2) & 0x03 == 0x01;
+    }
+
+    fn receive_interrupt_enabled(&self) -> bool {
+        return self.command & (DATA_TERMINAL_READY | RECEIVER_INTERRUPT_DISABLE) == DATA_TERMINAL_READY;
+    }
+
+    fn interrupt(&mut self) {
+        self.status |= INTERRUPT;
+        self.irq.set_irq(true);
+    }
+
+    fn receive(&mut self) {
+        if self.command & DATA_TERMINAL_READY == 0 {
+            return;
+        }
+        let byte = match self.backend.receive() {
+            Some(byte) => byte,
+            None => return,
+        };
+
+        // A byte arriving before the previous one was read is lost.
+        if self.status & RECEIVER_FULL != 0 {
+            self.status |= OVERRUN;
+        } else {
+            self.receive_data = byte;
+            self.status |= RECEIVER_FULL;
+        }
+        if self.command & ECHO != 0 && (self.command >> 2) & 0x03 == 0 {
+            self.backend.transmit(byte);
+        }
+        if self.receive_interrupt_enabled() {
+            self.interrupt();
+        }
+    }
+}
+
+impl Addressable for Acia6551 {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (self.address, self.address + 0x03);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        return match address & 0x03 {
+            DATA => self.receive_data,
+            STATUS => self.status,
+            COMMAND => self.command,
+            _ => self.control,
+        };
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.peek(address);
+        match address & 0x03 {
+            DATA => self.status &= !(RECEIVER_FULL | OVERRUN),
+            STATUS => {
+                self.status &= !INTERRUPT;
+                self.irq.set_irq(false);
+            }
+            _ => {}
+        }
+        return data;
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        match address & 0x03 {
+            DATA => {
+                self.status &= !TRANSMITTER_EMPTY;
+                if self.transmitting == 0 {
+                    self.shift = data;
+                    self.transmitting = self.frame_cycles();
+                } else {
+                    self.transmit_data = data;
+                    self.queued = true;
+                }
+            }
+            // Programmed reset, the parity settings survive.
+            STATUS => {
+                self.command &= 0xE0;
+                self.status &= !OVERRUN;
+            }
+            COMMAND => self.command = data,
+            CONTROL => self.control = data,
+            _ => unreachable!(),
+        }
+    }
+
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        return Some(self);
+    }
+
+    fn name(&self) -> &str {
+        return "ACIA";
+    }
+}
+
+impl Clocked for Acia6551 {
+    fn tick(&mut self, cycles: u64) {
+        let mut remaining = cycles;
+        while self.transmitting > 0 {
+            if remaining < self.transmitting {
+                self.transmitting -= remaining;
+                break;
+            }
+            remaining -= self.transmitting;
+            self.transmitting = 0;
+            self.backend.transmit(self.shift);
+            if self.queued {
+                self.queued = false;
+                self.shift = self.transmit_data;
+                self.transmitting = self.frame_cycles();
+            } else {
+                self.status |= TRANSMITTER_EMPTY;
+                if self.transmit_interrupt_enabled() {
+                    self.interrupt();
+                }
+            }
+        }
+
+        // At most one frame can arrive per frame time, however long the device was left alone.
+        if cycles >= self.receiving {
+            self.receive();
+            self.receiving = self.frame_cycles();
+        } else {
+            self.receiving -= cycles;
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        let receiving = if self.command & DATA_TERMINAL_READY != 0 { Some(self.receiving.max(1)) } else { None };
+        return match (self.transmitting, receiving) {
+            (0, receiving) => receiving,
+            (transmitting, Some(receiving)) => Some(transmitting.min(receiving)),
+            (transmitting, None) => Some(transmitting),
+        };
+    }
+}
+
+impl Snapshot for Acia6551 {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        for byte in [self.receive_data, self.transmit_data, self.shift, self.status, self.command, self.control] {
+            writer.write_u8(byte);
+        }
+        writer.write_bool(self.queued);
+        writer.write_u64(self.transmitting);
+        writer.write_u64(self.receiving);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.receive_data = reader.read_u8()?;
+        self.transmit_data = reader.read_u8()?;
+        self.shift = reader.read_u8()?;
+        self.status = reader.read_u8()?;
+        self.command = reader.read_u8()?;
+        self.control = reader.read_u8()?;
+        self.queued = reader.read_bool()?;
+        self.transmitting = reader.read_u64()?;
+        self.receiving = reader.read_u64()?;
+        self.irq.set_irq(self.status & INTERRUPT != 0);
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::BusBuilder;
+    use crate::components::serial::BufferBackend;
+
+    #[test]
+    fn test_acia6551() {
+        let builder = BusBuilder::new();
+        let serial = BufferBackend::new();
+        let mut acia = Acia6551::new(0x5000, builder.interrupts().source("ACIA"), Box::new(serial.clone()), 1_000_000);
+        acia.write(0x3, 0x1F); // 19200 baud, 8N1: 520 cycles per byte at 1 MHz
+        acia.write(0x2, 0x09); // DTR, receiver interrupts
+
+        acia.write(0x0, b'A');
+        assert_eq!(acia.peek(0x1) & 0x10, 0x00);
+        acia.tick(519);
+        assert!(serial.take_output().is_empty());
+        acia.tick(1);
+        assert_eq!(serial.take_output(), b"A");
+        assert_eq!(acia.peek(0x1) & 0x10, 0x10);
+
+        // The receiver takes one frame per frame time.
+        serial.send(b"hi");
+        acia.tick(518);
+        assert!(!builder.interrupts().irq());
+        acia.tick(1);
+        assert!(builder.interrupts().irq());
+        assert_eq!(acia.read(0x1) & 0x88, 0x88);
+        assert!(!builder.interrupts().irq());
+        assert_eq!(acia.read(0x0), b'h');
+        acia.tick(519);
+        assert_eq!(acia.peek(0x1) & 0x08, 0x00);
+        acia.tick(1);
+        assert_eq!(acia.read(0x0), b'i');
+        assert_eq!(acia.next_event(), Some(520));
+
+        // A byte written while another is going out waits for it.
+        acia.write(0x0, b'B');
+        acia.tick(100);
+        acia.write(0x0, b'C');
+        acia.tick(420);
+        assert_eq!(serial.take_output(), b"B");
+        assert_eq!(acia.peek(0x1) & 0x10, 0x00);
+        acia.tick(520);
+        assert_eq!(serial.take_output(), b"C");
+        assert_eq!(acia.peek(0x1) & 0x10, 0x10);
+    }
+}
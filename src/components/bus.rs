@@ -1,36 +1,1039 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+use crate::components::device::{Addressable, SharedMemory};
+use crate::components::interrupt::InterruptLines;
 use crate::components::memory::RandomAccessMemory;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::components::watchpoint::{Access, Watchpoint, WatchpointHit};
+
+// An inclusive range of bus addresses answered by a device. Devices see addresses relative to where they
+// are mapped, translated as `origin + (address - start) % length`. That makes plain mappings (origin 0,
+// length = size of the range) and mirrors (device offset and length of the mirrored range) the same thing.
+#[derive(Debug, Copy, Clone)]
+struct Mapping {
+    start: u16,
+    end: u16,
+    device: usize,
+    origin: u16,
+    length: u32,
+    priority: u8,
+    // Bus addresses of the mirrored range.
+    mirror: Option<(u16, u16)>,
+}
+
+impl Mapping {
+    fn contains(&self, address: u16) -> bool {
+        return self.start <= address && address <= self.end;
+    }
+
+    fn translate(&self, address: u16) -> u16 {
+        return (self.origin as u32 + (address - self.start) as u32 % self.length) as u16;
+    }
+
+    fn overlaps(&self, other: &Mapping) -> bool {
+        return self.start <= other.end && other.start <= self.end;
+    }
+}
+
+// Which device answers for a 256 byte page, looked up on every access instead of scanning the mappings.
+#[derive(Clone)]
+enum Page {
+    Unmapped,
+    // The whole page belongs to a single device, device address = bus address + delta.
+    Device(usize, u16),
+    // The whole page belongs to plain memory, accessed directly through its shared storage.
+    Memory(SharedMemory, u16),
+    // The page is only partially mapped, shared between mappings or wraps around a mirror.
+    Shared,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MapError {
+    InvalidRange { start: u16, end: u16 },
+    Overlap { first: (u16, u16), second: (u16, u16) },
+    // A mirror has to lie within a single mapped device.
+    InvalidMirrorSource { start: u16, end: u16 },
+    // The device answers for fewer addresses than the range it is mapped at.
+    DeviceTooSmall { start: u16, end: u16, size: u32 },
+    // A clock ratio needs a non-zero multiplier and divider and a clocked device attached before it.
+    InvalidClockRatio { multiplier: u32, divider: u32 },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            MapError::InvalidRange { start, end } => write!(f, "invalid range ${:04X}-${:04X}", start, end),
+            MapError::Overlap { first, second } => write!(f, "${:04X}-${:04X} overlaps ${:04X}-${:04X} with the same priority",
+                second.0, second.1, first.0, first.1),
+            MapError::InvalidMirrorSource { start, end } => write!(f, "mirrored range ${:04X}-${:04X} is not within a single device",
+                start, end),
+            MapError::DeviceTooSmall { start, end, size } => write!(f, "device of {:#x} bytes cannot be mapped at ${:04X}-${:04X}",
+                size, start, end),
+            MapError::InvalidClockRatio { multiplier, divider } => write!(f, "invalid clock ratio {}/{}", multiplier, divider),
+        };
+    }
+}
+
+impl std::error::Error for MapError {}
+
+// What a read from an address no device answers for returns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnmappedRead {
+    // The value last driven on the data bus, which is what NMOS systems see as nothing pulls the lines.
+    OpenBus,
+    // Always the same value, e.g. 0xFF for a bus with pull-ups.
+    Pattern(u8),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ClockMode {
+    // Every device is advanced once per instruction by the cycles it took.
+    Instruction,
+    // Devices are advanced one CPU cycle at a time, so devices depending on each other stay in step.
+    // Instructions still execute as a whole.
+    Cycle,
+    // Devices are only advanced when their next event is due or right before the CPU accesses them, see
+    // Clocked::next_event. Much faster with many devices which are idle most of the time.
+    Scheduled,
+}
+
+// Clock of a device relative to the CPU, e.g. 2/1 for a 2 MHz device next to a 1 MHz CPU.
+#[derive(Debug, Clone)]
+struct Clock {
+    device: usize,
+    multiplier: u32,
+    divider: u32,
+    // Device cycles owed so far, in units of 1/divider. Cells, as peek catches devices up through &self.
+    remainder: Cell<u64>,
+    // CPU cycle the device has been advanced to, only kept up to date in scheduled mode.
+    last: Cell<u64>,
+    // CPU cycle of the next event of the device in scheduled mode.
+    due: Option<u64>,
+}
+
+impl Clock {
+    fn new(device: usize, multiplier: u32, divider: u32) -> Clock {
+        return Clock { device, multiplier, divider, remainder: Cell::new(0), last: Cell::new(0), due: None };
+    }
+
+    // Device cycles the given CPU cycles amount to.
+    fn advance(&self, cycles: u64) -> u64 {
+        let owed = self.remainder.get() + cycles * self.multiplier as u64;
+        self.remainder.set(owed % self.divider as u64);
+        return owed / self.divider as u64;
+    }
+
+    // CPU cycles until the device has run the given number of its own cycles, at least one.
+    fn until(&self, device_cycles: u64) -> u64 {
+        let owed = device_cycles.saturating_mul(self.divider as u64).saturating_sub(self.remainder.get());
+        return owed.div_ceil(self.multiplier as u64).max(1);
+    }
+}
+
+// Accesses to unmapped addresses within one 256 byte page.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnmappedAccesses {
+    pub start: u16,
+    pub end: u16,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+// Describes the memory map of a machine. Mappings may only overlap when they have different priorities,
+// the higher priority answers for the shared addresses.
+//
+//     let bus = BusBuilder::new()
+//         .map(0x0000, 0x07FF, Box::new(ram))
+//         .mirror(0x0800, 0x1FFF, 0x0000, 0x07FF)
+//         .build()?;
+#[derive(Default)]
+pub struct BusBuilder {
+    devices: Vec<Box<dyn Addressable>>,
+    mappings: Vec<Mapping>,
+    mirrors: Vec<(u16, u16, u16, u16, u8)>,
+    errors: Vec<MapError>,
+    unmapped_read: Option<UnmappedRead>,
+    interrupts: InterruptLines,
+    // (device, multiplier, divider) set by clock_ratio.
+    ratios: Vec<(usize, u32, u32)>,
+}
+
+impl BusBuilder {
+    pub fn new() -> BusBuilder {
+        return BusBuilder::default();
+    }
+
+    // Maps a device at the address space it reports itself.
+    pub fn attach(self, device: Box<dyn Addressable>) -> BusBuilder {
+        let (start, end) = device.get_address_space();
+        return self.map(start, end, device);
+    }
+
+    pub fn map(self, start: u16, end: u16, device: Box<dyn Addressable>) -> BusBuilder {
+        return self.map_with_priority(start, end, device, 0);
+    }
+
+    pub fn map_with_priority(mut self, start: u16, end: u16, device: Box<dyn Addressable>, priority: u8) -> BusBuilder {
+        if start > end {
+            self.errors.push(MapError::InvalidRange { start, end });
+            return self;
+        }
+
+        let (device_start, device_end) = device.get_address_space();
+        let size = device_end.saturating_sub(device_start) as u32 + 1;
+        if size < (end - start) as u32 + 1 {
+            self.errors.push(MapError::DeviceTooSmall { start, end, size });
+            return self;
+        }
+
+        self.mappings.push(Mapping {
+            start,
+            end,
+            device: self.devices.len(),
+            origin: 0,
+            length: (end - start) as u32 + 1,
+            priority,
+            mirror: None,
+        });
+        self.devices.push(device);
+        return self;
+    }
+
+    // Repeats the addresses source_start..=source_end throughout start..=end.
+    pub fn mirror(self, start: u16, end: u16, source_start: u16, source_end: u16) -> BusBuilder {
+        return self.mirror_with_priority(start, end, source_start, source_end, 0);
+    }
+
+    pub fn mirror_with_priority(mut self, start: u16, end: u16, source_start: u16, source_end: u16, priority: u8) -> BusBuilder {
+        if start > end {
+            self.errors.push(MapError::InvalidRange { start, end });
+        } else if source_start > source_end {
+            self.errors.push(MapError::InvalidRange { start: source_start, end: source_end });
+        } else {
+            self.mirrors.push((start, end, source_start, source_end, priority));
+        }
+        return self;
+    }
+
+    // Lines the devices of this bus signal the CPU on, hand out sources before attaching devices.
+    pub fn interrupts(&self) -> &InterruptLines {
+        return &self.interrupts;
+    }
+
+    // Runs the device attached last at multiplier/divider times the CPU clock, 1/1 by default.
+    pub fn clock_ratio(mut self, multiplier: u32, divider: u32) -> BusBuilder {
+        match self.devices.len().checked_sub(1) {
+            Some(device) if multiplier != 0 && divider != 0 => self.ratios.push((device, multiplier, divider)),
+            _ => self.errors.push(MapError::InvalidClockRatio { multiplier, divider }),
+        }
+        return self;
+    }
+
+    // Open bus by default.
+    pub fn unmapped_read(mut self, unmapped_read: UnmappedRead) -> BusBuilder {
+        self.unmapped_read = Some(unmapped_read);
+        return self;
+    }
+
+    pub fn build(mut self) -> Result<Bus, MapError> {
+        if let Some(error) = self.errors.into_iter().next() {
+            return Err(error);
+        }
+
+        // Mirrors resolve against plain mappings only, so the order they were declared in does not matter.
+        let plain = self.mappings.clone();
+        for (start, end, source_start, source_end, priority) in self.mirrors {
+            let source = plain.iter()
+                .filter(|mapping| mapping.contains(source_start) && mapping.contains(source_end))
+                .max_by_key(|mapping| mapping.priority)
+                .ok_or(MapError::InvalidMirrorSource { start: source_start, end: source_end })?;
+
+            self.mappings.push(Mapping {
+                start,
+                end,
+                device: source.device,
+                origin: source.translate(source_start),
+                length: (source_end - source_start) as u32 + 1,
+                priority,
+                mirror: Some((source_start, source_end)),
+            });
+        }
+
+        for (index, first) in self.mappings.iter().enumerate() {
+            for second in &self.mappings[index + 1..] {
+                if first.priority == second.priority && first.overlaps(second) {
+                    return Err(MapError::Overlap { first: (first.start, first.end), second: (second.start, second.end) });
+                }
+            }
+        }
+
+        // Highest priority first, so the first mapping containing an address answers for it.
+        self.mappings.sort_by_key(|mapping| Reverse(mapping.priority));
+
+        let mut clocks = Vec::new();
+        let mut clock_of = vec![None; self.devices.len()];
+        for (index, device) in self.devices.iter_mut().enumerate() {
+            let ratio = self.ratios.iter().rev().find(|(device, _, _)| *device == index);
+            let clock = match (device.clocked(), ratio) {
+                (Some(_), Some(&(_, multiplier, divider))) => Clock::new(index, multiplier, divider),
+                (Some(_), None) => Clock::new(index, 1, 1),
+                (None, Some(&(_, multiplier, divider))) => return Err(MapError::InvalidClockRatio { multiplier, divider }),
+                (None, None) => continue,
+            };
+            clock_of[index] = Some(clocks.len());
+            clocks.push(clock);
+        }
+
+        let mut bus = Bus {
+            devices: self.devices.into_iter().map(RefCell::new).collect(),
+            mappings: self.mappings,
+            pages: vec![Page::Unmapped; 256],
+            journal: None,
+            data_bus: 0x00,
+            unmapped_read: self.unmapped_read.unwrap_or(UnmappedRead::OpenBus),
+            unmapped_accesses: vec![(0, 0); 256],
+            interrupts: self.interrupts,
+            clocks,
+            clock_of,
+            clock_mode: ClockMode::Instruction,
+            now: 0,
+            events: BinaryHeap::new(),
+            watchpoints: Vec::new(),
+            next_watchpoint: 0,
+            watchpoint_hit: None,
+            resume: None,
+            instruction: 0,
+        };
+        bus.map_pages();
+        return Ok(bus);
+    }
+}
 
 pub struct Bus {
-    devices : Vec<Box<dyn crate::components::device::Addressable>>,
+    devices : Vec<RefCell<Box<dyn Addressable>>>,
+    mappings: Vec<Mapping>,
+    pages: Vec<Page>,
+    // (address, previous value) of every write while journaling is enabled.
+    journal: Option<Vec<(u16, u8)>>,
+    // Last value driven on the data bus by the CPU or a device.
+    data_bus: u8,
+    unmapped_read: UnmappedRead,
+    // (reads, writes) of unmapped addresses per page.
+    unmapped_accesses: Vec<(u64, u64)>,
+    interrupts: InterruptLines,
+    clocks: Vec<Clock>,
+    // Index into clocks for every clocked device.
+    clock_of: Vec<Option<usize>>,
+    clock_mode: ClockMode,
+    // CPU cycles elapsed.
+    now: u64,
+    // (CPU cycle, clock) of scheduled events, entries no longer matching the due cycle of the clock are stale.
+    events: BinaryHeap<Reverse<(u64, usize)>>,
+    // (id, watchpoint), checked on every access while not empty.
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_watchpoint: usize,
+    // First watchpoint triggered since the last take_watchpoint_hit.
+    watchpoint_hit: Option<WatchpointHit>,
+    // Instruction an execute watchpoint stopped in front of, it runs when execution resumes there.
+    resume: Option<u16>,
+    // Address of the last opcode fetch.
+    instruction: u16,
 }
 
 impl Bus {
     pub fn write(&mut self, address: u16, data: u8) {
-        for attached_device in &mut self.devices {
-            let address_space: (u16, u16) = attached_device.get_address_space();
-            if (address_space.0 <= address) && (address_space.1 > address) {
-                attached_device.write(address, data);
+        if self.journal.is_some() {
+            let previous = self.peek(address);
+            if let Some(journal) = &mut self.journal {
+                journal.push((address, previous));
+            }
+        }
+
+        self.data_bus = data;
+        if !self.watchpoints.is_empty() {
+            self.watch(address, data, Access::Write);
+        }
+        match self.pages[(address >> 8) as usize] {
+            Page::Memory(ref memory, delta) => {
+                memory[address.wrapping_add(delta) as usize].set(data);
+                return;
+            }
+            Page::Device(index, delta) => {
+                self.access(index, |device| device.write(address.wrapping_add(delta), data));
                 return;
             }
+            Page::Unmapped => {}
+            Page::Shared => {
+                if let Some(mapping) = self.resolve(address).copied() {
+                    self.access(mapping.device, |device| device.write(mapping.translate(address), data));
+                    return;
+                }
+            }
+        }
+        self.unmapped_accesses[(address >> 8) as usize].1 += 1;
+    }
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        let data = match self.pages[(address >> 8) as usize] {
+            Page::Memory(ref memory, delta) => memory[address.wrapping_add(delta) as usize].get(),
+            Page::Device(index, delta) => self.access(index, |device| device.read(address.wrapping_add(delta))),
+            Page::Unmapped => self.read_unmapped(address),
+            Page::Shared => match self.resolve(address).copied() {
+                Some(mapping) => self.access(mapping.device, |device| device.read(mapping.translate(address))),
+                None => self.read_unmapped(address),
+            },
+        };
+        self.data_bus = data;
+        if !self.watchpoints.is_empty() {
+            self.watch(address, data, Access::Read);
+        }
+        return data;
+    }
+
+    // Reads the opcode of the next instruction.
+    pub fn fetch(&mut self, address: u16) -> u8 {
+        self.instruction = address;
+        return self.read(address);
+    }
+
+    // Checks the execute watchpoints before the instruction at the address is fetched, true if one
+    // triggers and the instruction must not run yet. Resuming at the same address runs it without
+    // stopping again.
+    pub fn breaks_at(&mut self, address: u16) -> bool {
+        let resume = self.resume.take();
+        if self.watchpoints.is_empty() || self.watchpoint_hit.is_some() || resume == Some(address) {
+            return false;
+        }
+        self.instruction = address;
+        self.watch(address, self.peek(address), Access::Execute);
+        if self.watchpoint_hit.is_none() {
+            return false;
         }
-        println!("NoDeviceFound@{:#06x} - TODO: ERROR", address);
+        self.resume = Some(address);
+        return true;
     }
 
-    pub fn read(&self, address: u16) -> u8 {
-        let mut device= &self.devices[0];
+    fn watch(&mut self, address: u16, value: u8, access: Access) {
+        if self.watchpoint_hit.is_some() {
+            return;
+        }
+        if let Some((id, _)) = self.watchpoints.iter().find(|(_, watchpoint)| watchpoint.matches(address, value, access)) {
+            self.watchpoint_hit = Some(WatchpointHit { watchpoint: *id, address, value, access, program_counter: self.instruction });
+        }
+    }
 
-        for attached_device in &self.devices {
-            let address_space: (u16, u16) = attached_device.get_address_space();
-            if (address_space.0 <= address) && (address_space.1 > address) {
-                device = attached_device;
-                break;
+    // Returns an id for remove_watchpoint.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_watchpoint;
+        self.next_watchpoint += 1;
+        self.watchpoints.push((id, watchpoint));
+        return id;
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|(watchpoint, _)| *watchpoint != id);
+        return self.watchpoints.len() != count;
+    }
+
+    // Returns and clears the first watchpoint triggered since the last call.
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        return self.watchpoint_hit.take();
+    }
+
+    // Reads without disturbing any device, for debuggers and memory viewers.
+    pub fn peek(&self, address: u16) -> u8 {
+        return match self.pages[(address >> 8) as usize] {
+            Page::Memory(ref memory, delta) => memory[address.wrapping_add(delta) as usize].get(),
+            Page::Device(index, delta) => self.peek_device(index, address.wrapping_add(delta)),
+            Page::Unmapped => self.floating(),
+            Page::Shared => match self.resolve(address) {
+                Some(mapping) => self.peek_device(mapping.device, mapping.translate(address)),
+                None => self.floating(),
+            },
+        };
+    }
+
+    // A clocked device left behind in scheduled mode is brought up to date first, so peek shows the same
+    // state a read would.
+    fn peek_device(&self, device: usize, address: u16) -> u8 {
+        if self.clock_mode == ClockMode::Scheduled {
+            if let Some(clock) = self.clock_of[device] {
+                self.catch_up(clock);
             }
         }
+        return self.devices[device].borrow().peek(address);
+    }
+
+    // In scheduled mode a clocked device is brought up to date before the CPU accesses it and asked for
+    // its next event afterwards, as the access may have changed it (e.g. a timer was reloaded).
+    fn access<T>(&mut self, device: usize, access: impl FnOnce(&mut dyn Addressable) -> T) -> T {
+        let clock = match self.clock_mode {
+            ClockMode::Scheduled => self.clock_of[device],
+            _ => None,
+        };
+        if let Some(clock) = clock {
+            self.catch_up(clock);
+        }
+        let result = access(self.devices[device].get_mut().as_mut());
+        if let Some(clock) = clock {
+            self.schedule(clock);
+        }
+        return result;
+    }
+
+    fn read_unmapped(&mut self, address: u16) -> u8 {
+        self.unmapped_accesses[(address >> 8) as usize].0 += 1;
+        return self.floating();
+    }
+
+    // Value seen on the data bus when no device drives it.
+    fn floating(&self) -> u8 {
+        return match self.unmapped_read {
+            UnmappedRead::OpenBus => self.data_bus,
+            UnmappedRead::Pattern(pattern) => pattern,
+        };
+    }
+
+    pub fn interrupts(&self) -> &InterruptLines {
+        return &self.interrupts;
+    }
+
+    pub fn set_clock_mode(&mut self, clock_mode: ClockMode) {
+        if self.clock_mode == ClockMode::Scheduled {
+            for clock in 0..self.clocks.len() {
+                self.catch_up(clock);
+                self.clocks[clock].due = None;
+            }
+            self.events.clear();
+        }
+
+        self.clock_mode = clock_mode;
+        if clock_mode == ClockMode::Scheduled {
+            self.reschedule();
+        }
+    }
+
+    // Advances the clocked devices by the given number of CPU cycles, called by the CPU after every
+    // instruction and interrupt.
+    pub fn tick(&mut self, cycles: u64) {
+        self.now += cycles;
+        match self.clock_mode {
+            ClockMode::Instruction => self.advance(cycles),
+            ClockMode::Cycle => {
+                for _ in 0..cycles {
+                    self.advance(1);
+                }
+            }
+            ClockMode::Scheduled => {
+                while let Some(&Reverse((due, clock))) = self.events.peek() {
+                    if due > self.now {
+                        break;
+                    }
+                    self.events.pop();
+                    if self.clocks[clock].due == Some(due) {
+                        self.catch_up(clock);
+                        self.schedule(clock);
+                    }
+                }
+            }
+        }
+    }
+
+    // CPU cycles elapsed since the bus was built.
+    pub fn cycles(&self) -> u64 {
+        return self.now;
+    }
+
+    // CPU cycle of the earliest scheduled event, the CPU can run up to it without the devices noticing.
+    pub fn next_event(&self) -> Option<u64> {
+        return self.clocks.iter().filter_map(|clock| clock.due).min();
+    }
+
+    fn advance(&mut self, cycles: u64) {
+        for clock in &self.clocks {
+            let device_cycles = clock.advance(cycles);
+            if device_cycles > 0 {
+                if let Some(device) = self.devices[clock.device].get_mut().clocked() {
+                    device.tick(device_cycles);
+                }
+            }
+        }
+    }
+
+    // Advances a device to the current cycle in scheduled mode. Its next event stays where it was, the
+    // device only runs up to a moment before it.
+    fn catch_up(&self, clock: usize) {
+        let clock = &self.clocks[clock];
+        let device_cycles = clock.advance(self.now - clock.last.get());
+        clock.last.set(self.now);
+        if device_cycles > 0 {
+            if let Some(device) = self.devices[clock.device].borrow_mut().clocked() {
+                device.tick(device_cycles);
+            }
+        }
+    }
+
+    // Asks a device for its next event and queues it.
+    fn schedule(&mut self, clock: usize) {
+        let next = self.devices[self.clocks[clock].device].get_mut().clocked().and_then(|device| device.next_event());
+        let due = next.map(|device_cycles| self.clocks[clock].last.get() + self.clocks[clock].until(device_cycles));
+        self.clocks[clock].due = due;
+        if let Some(due) = due {
+            self.events.push(Reverse((due, clock)));
+        }
+    }
 
-        return device.read(address);
+    fn reschedule(&mut self) {
+        self.events.clear();
+        for clock in 0..self.clocks.len() {
+            self.clocks[clock].last.set(self.now);
+            self.schedule(clock);
+        }
+    }
+
+    // Pages that saw accesses to unmapped addresses since the last reset, ordered by address.
+    pub fn unmapped_accesses(&self) -> Vec<UnmappedAccesses> {
+        return self.unmapped_accesses.iter().enumerate()
+            .filter(|(_, (reads, writes))| *reads != 0 || *writes != 0)
+            .map(|(page, (reads, writes))| UnmappedAccesses {
+                start: (page as u16) << 8,
+                end: (page as u16) << 8 | 0xFF,
+                reads: *reads,
+                writes: *writes,
+            })
+            .collect();
+    }
+
+    pub fn reset_unmapped_accesses(&mut self) {
+        self.unmapped_accesses.iter_mut().for_each(|counts| *counts = (0, 0));
+    }
+
+    fn resolve(&self, address: u16) -> Option<&Mapping> {
+        return self.mappings.iter().find(|mapping| mapping.contains(address));
     }
 
     pub fn new(memory : RandomAccessMemory) -> Bus {
-        return Bus { devices: vec![Box::new(memory)] };
+        return Bus::with_device(Box::new(memory));
+    }
+
+    pub fn with_device(device: Box<dyn Addressable>) -> Bus {
+        return BusBuilder::new().attach(device).build().expect("a single device cannot overlap");
+    }
+
+    // Rebuilds the page table from the mappings.
+    fn map_pages(&mut self) {
+        for page in 0..=0xFFu16 {
+            let start = page << 8;
+            let end = start | 0xFF;
+
+            let mut covering = self.mappings.iter().filter(|mapping| mapping.start <= end && start <= mapping.end);
+            self.pages[page as usize] = match (covering.next(), covering.next()) {
+                (None, _) => Page::Unmapped,
+                (Some(mapping), None) if mapping.start <= start && end <= mapping.end
+                    && mapping.translate(end).wrapping_sub(mapping.translate(start)) == 0xFF => {
+                    let delta = mapping.translate(start).wrapping_sub(start);
+                    match self.devices[mapping.device].get_mut().memory() {
+                        Some(memory) if memory.len() > mapping.translate(end) as usize => Page::Memory(memory, delta),
+                        _ => Page::Device(mapping.device, delta),
+                    }
+                }
+                _ => Page::Shared,
+            };
+        }
+    }
+
+    // One line per mapping, ordered by address.
+    pub fn memory_map(&self) -> String {
+        let mut mappings: Vec<&Mapping> = self.mappings.iter().collect();
+        mappings.sort_by_key(|mapping| (mapping.start, mapping.end));
+
+        let mut map = String::new();
+        for mapping in mappings {
+            map.push_str(&format!("${:04X}-${:04X}  {}", mapping.start, mapping.end, self.devices[mapping.device].borrow().name()));
+            if let Some((source_start, source_end)) = mapping.mirror {
+                map.push_str(&format!(" (mirror of ${:04X}-${:04X})", source_start, source_end));
+            }
+            if mapping.priority != 0 {
+                map.push_str(&format!(" [priority {}]", mapping.priority));
+            }
+            map.push('\n');
+        }
+        return map;
+    }
+
+    pub fn set_journaling(&mut self, enabled: bool) {
+        self.journal = if enabled { Some(self.journal.take().unwrap_or_default()) } else { None };
+    }
+
+    // Returns and clears the writes journaled so far, oldest first.
+    pub fn take_journal(&mut self) -> Vec<(u16, u8)> {
+        return self.journal.as_mut().map(std::mem::take).unwrap_or_default();
+    }
+
+    // Mapped range and saved state of every attached device, in attachment order.
+    pub fn device_states(&self) -> Vec<((u16, u16), Vec<u8>)> {
+        return self.devices.iter().enumerate().map(|(index, device)| {
+            let mut writer = SnapshotWriter::new();
+            device.borrow().save(&mut writer);
+            (self.device_range(index), writer.into_bytes())
+        }).collect();
+    }
+
+    fn device_range(&self, device: usize) -> (u16, u16) {
+        return self.mappings.iter()
+            .find(|mapping| mapping.device == device && mapping.mirror.is_none())
+            .map(|mapping| (mapping.start, mapping.end))
+            .unwrap_or_default();
+    }
+}
+
+impl Bus {
+    // Saves the bus and its devices. Without memory, devices backed by plain memory are left out, which
+    // keeps the state small when the memory is journaled separately.
+    fn save_state(&self, writer: &mut SnapshotWriter, memory: bool) {
+        writer.write_u8(self.data_bus);
+        self.interrupts.save(writer);
+        writer.write_u64(self.now);
+        writer.write_u16(self.clocks.len() as u16);
+        for clock in &self.clocks {
+            writer.write_u64(clock.remainder.get());
+            writer.write_u64(clock.last.get());
+        }
+        let saved: Vec<usize> = (0..self.devices.len()).filter(|&index| memory || self.devices[index].borrow().memory().is_none()).collect();
+        writer.write_u16(saved.len() as u16);
+        for index in saved {
+            let (start, end) = self.device_range(index);
+            let mut state = SnapshotWriter::new();
+            self.devices[index].borrow().save(&mut state);
+            writer.write_u16(start);
+            writer.write_u16(end);
+            writer.write_bytes(&state.into_bytes());
+        }
+    }
+
+    fn restore_state(&mut self, reader: &mut SnapshotReader, memory: bool) -> Result<(), SnapshotError> {
+        self.data_bus = reader.read_u8()?;
+        self.interrupts.restore(reader)?;
+        self.now = reader.read_u64()?;
+        if reader.read_u16()? as usize != self.clocks.len() {
+            return Err(SnapshotError::Mismatch(String::from("different number of clocked devices")));
+        }
+        for clock in &self.clocks {
+            clock.remainder.set(reader.read_u64()?);
+            clock.last.set(reader.read_u64()?);
+        }
+        let saved: Vec<usize> = (0..self.devices.len()).filter(|&index| memory || self.devices[index].borrow().memory().is_none()).collect();
+        let count = reader.read_u16()? as usize;
+        if count != saved.len() {
+            return Err(SnapshotError::Mismatch(format!("{} devices attached, snapshot has {}", saved.len(), count)));
+        }
+
+        for index in saved {
+            let range = self.device_range(index);
+            let address_space = (reader.read_u16()?, reader.read_u16()?);
+            if address_space != range {
+                return Err(SnapshotError::Mismatch(format!("device at {:#06x}-{:#06x} where snapshot has {:#06x}-{:#06x}",
+                    range.0, range.1, address_space.0, address_space.1)));
+            }
+
+            let mut state = SnapshotReader::new(reader.read_bytes()?);
+            self.devices[index].get_mut().restore(&mut state)?;
+            if !state.is_empty() {
+                return Err(SnapshotError::Mismatch(format!("device at {:#06x} left state unread", address_space.0)));
+            }
+        }
+
+        if self.clock_mode == ClockMode::Scheduled {
+            self.events.clear();
+            for clock in 0..self.clocks.len() {
+                self.schedule(clock);
+            }
+        }
+        return Ok(());
+    }
+
+    // State of the bus and of every device not backed by memory.
+    pub fn save_devices(&self, writer: &mut SnapshotWriter) {
+        self.save_state(writer, false);
+    }
+
+    pub fn restore_devices(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        return self.restore_state(reader, false);
+    }
+
+    // Puts a journaled value back. Only memory is written to, a device register is restored with
+    // restore_devices instead as writing it again could have side effects.
+    pub fn restore_byte(&mut self, address: u16, data: u8) {
+        if let Some(mapping) = self.resolve(address).copied() {
+            if let Some(memory) = self.devices[mapping.device].get_mut().memory() {
+                if let Some(cell) = memory.get(mapping.translate(address) as usize) {
+                    cell.set(data);
+                }
+            }
+        }
+    }
+}
+
+impl Snapshot for Bus {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        self.save_state(writer, true);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        return self.restore_state(reader, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::cpu6502::{CPU6502, Flags};
+    use crate::components::device::Clocked;
+    use crate::components::interrupt::InterruptSource;
+
+    #[test]
+    fn test_page_table() {
+        let mut bus = Bus::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap());
+
+        // Pages fully covered by memory, and the last page which the RAM only covers partially.
+        for address in [0x0000, 0x01FF, 0x7EFF, 0x7F00, 0x7FFE] {
+            bus.write(address, address as u8 ^ 0x5A);
+            assert_eq!(bus.read(address), address as u8 ^ 0x5A);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_bus_builder() {
+        let mut bus = BusBuilder::new()
+            .map(0x0000, 0x07FF, Box::new(RandomAccessMemory::new(0x0000, 0x0800).unwrap()))
+            .mirror(0x0800, 0x1FFF, 0x0000, 0x07FF)
+            .mirror(0xF800, 0xFFFF, 0x0000, 0x07FF)
+            .build()
+            .unwrap();
+
+        bus.write(0x0123, 0xAB);
+        assert_eq!(bus.read(0x0923), 0xAB);
+        assert_eq!(bus.read(0x1923), 0xAB);
+        bus.write(0xFFFF, 0xCD);
+        assert_eq!(bus.read(0x07FF), 0xCD);
+
+        assert_eq!(bus.memory_map(), "$0000-$07FF  RAM\n\
+                                      $0800-$1FFF  RAM (mirror of $0000-$07FF)\n\
+                                      $F800-$FFFF  RAM (mirror of $0000-$07FF)\n");
+
+        let overlapping = BusBuilder::new()
+            .map(0x0000, 0x3FFF, Box::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap()))
+            .map(0x2000, 0x2FFF, Box::new(RandomAccessMemory::new(0x2000, 0x1000).unwrap()))
+            .build();
+        assert_eq!(overlapping.err(), Some(MapError::Overlap { first: (0x0000, 0x3FFF), second: (0x2000, 0x2FFF) }));
+
+        // The higher priority mapping answers for the overlapping addresses.
+        let mut bus = BusBuilder::new()
+            .map(0x0000, 0x3FFF, Box::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap()))
+            .map_with_priority(0x2000, 0x2FFF, Box::new(RandomAccessMemory::new(0x2000, 0x1000).unwrap()), 1)
+            .build()
+            .unwrap();
+        bus.write(0x2000, 0x01);
+        bus.write(0x3000, 0x02);
+        assert_eq!((bus.read(0x2000), bus.read(0x3000)), (0x01, 0x02));
+        assert_eq!(bus.device_states()[0].1[4 + 0x2000], 0x00);
+        assert_eq!(bus.device_states()[1].1[4], 0x01);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut bus = Bus::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap());
+        bus.write(0x0010, 0x42);
+        assert_eq!(bus.read(0x0010), 0x42);
+        assert_eq!(bus.read(0x9000), 0x42);
+        assert_eq!(bus.peek(0x9001), 0x42);
+
+        bus.write(0xA000, 0x17);
+        assert_eq!(bus.read(0x9000), 0x17);
+
+        assert_eq!(bus.unmapped_accesses(), vec![
+            UnmappedAccesses { start: 0x9000, end: 0x90FF, reads: 2, writes: 0 },
+            UnmappedAccesses { start: 0xA000, end: 0xA0FF, reads: 0, writes: 1 },
+        ]);
+        bus.reset_unmapped_accesses();
+        assert!(bus.unmapped_accesses().is_empty());
+
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap()))
+            .unmapped_read(UnmappedRead::Pattern(0xFF))
+            .build()
+            .unwrap();
+        bus.write(0x0010, 0x42);
+        assert_eq!(bus.read(0x9000), 0xFF);
+        assert_eq!(bus.read(0x0010), 0x42);
+    }
+
+    #[test]
+    fn test_clocked_devices() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        // Counts the cycles it was advanced by, readable by the host through the shared cell.
+        struct Counter(Rc<Cell<u64>>);
+
+        impl Clocked for Counter {
+            fn tick(&mut self, cycles: u64) {
+                self.0.set(self.0.get() + cycles);
+            }
+        }
+
+        impl Addressable for Counter {
+            fn get_address_space(&self) -> (u16, u16) {
+                return (0x0000, 0x0000);
+            }
+
+            fn peek(&self, _address: u16) -> u8 {
+                return self.0.get() as u8;
+            }
+
+            fn write(&mut self, _address: u16, _data: u8) {}
+
+            fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+                return Some(self);
+            }
+        }
+
+        impl Snapshot for Counter {
+            fn save(&self, writer: &mut SnapshotWriter) {
+                writer.write_u64(self.0.get());
+            }
+
+            fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+                self.0.set(reader.read_u64()?);
+                return Ok(());
+            }
+        }
+
+        let (fast, slow) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap()))
+            .map(0x8000, 0x8000, Box::new(Counter(fast.clone())))
+            .clock_ratio(2, 1)
+            .map(0x8001, 0x8001, Box::new(Counter(slow.clone())))
+            .clock_ratio(1, 3)
+            .build()
+            .unwrap();
+        let mut cpu = CPU6502::new();
+
+        // Five CLC, two cycles each.
+        for address in 0x0000..0x0005 {
+            bus.write(address, 0x18);
+        }
+        for _ in 0..5 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!((fast.get(), slow.get()), (20, 3));
+
+        bus.set_clock_mode(ClockMode::Cycle);
+        bus.tick(2);
+        assert_eq!((fast.get(), slow.get()), (24, 4));
+
+        let error = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap()))
+            .clock_ratio(2, 1)
+            .build()
+            .err();
+        assert_eq!(error, Some(MapError::InvalidClockRatio { multiplier: 2, divider: 1 }));
+    }
+
+    #[test]
+    fn test_scheduled_devices() {
+
+        // One-shot timer counting down from the value written to it, asserting IRQ when it expires.
+        struct Timer {
+            counter: u64,
+            irq: InterruptSource,
+        }
+
+        impl Clocked for Timer {
+            fn tick(&mut self, cycles: u64) {
+                if self.counter > 0 && cycles >= self.counter {
+                    self.irq.set_irq(true);
+                }
+                self.counter = self.counter.saturating_sub(cycles);
+            }
+
+            fn next_event(&self) -> Option<u64> {
+                return if self.counter > 0 { Some(self.counter) } else { None };
+            }
+        }
+
+        impl Addressable for Timer {
+            fn get_address_space(&self) -> (u16, u16) {
+                return (0x0000, 0x0000);
+            }
+
+            fn peek(&self, _address: u16) -> u8 {
+                return self.counter as u8;
+            }
+
+            fn write(&mut self, _address: u16, data: u8) {
+                self.counter = data as u64;
+                self.irq.set_irq(false);
+            }
+
+            fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+                return Some(self);
+            }
+        }
+
+        impl Snapshot for Timer {
+            fn save(&self, writer: &mut SnapshotWriter) {
+                writer.write_u64(self.counter);
+            }
+
+            fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+                self.counter = reader.read_u64()?;
+                return Ok(());
+            }
+        }
+
+        let builder = BusBuilder::new();
+        let irq = builder.interrupts().source("Timer");
+        let mut bus = builder
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap()))
+            .map(0x8000, 0x8000, Box::new(Timer { counter: 0, irq: irq.clone() }))
+            .build()
+            .unwrap();
+        bus.set_clock_mode(ClockMode::Scheduled);
+        let mut cpu = CPU6502::new();
+        cpu.registers_mut().status_flags = Flags::InterruptDisable as u8;
+        for address in 0x0000..0x0010 {
+            bus.write(address, 0x18); // CLC
+        }
+
+        // Writing the timer reschedules it, the CPU then runs undisturbed until it expires.
+        assert_eq!(bus.next_event(), None);
+        bus.write(0x8000, 9);
+        assert_eq!(bus.next_event(), Some(9));
+        for _ in 0..4 {
+            cpu.tick(&mut bus);
+        }
+        assert!(!irq.irq_asserted());
+        cpu.tick(&mut bus);
+        assert!(irq.irq_asserted());
+        assert_eq!(bus.next_event(), None);
+
+        // Peeking and reading bring the timer up to date first.
+        bus.write(0x8000, 5);
+        cpu.tick(&mut bus);
+        assert_eq!(bus.peek(0x8000), 3);
+        assert_eq!(bus.read(0x8000), 3);
+
+        // The CPU runs until the event and stops there, or for the limit when nothing is due.
+        bus.write(0x8000, 7);
+        assert_eq!(cpu.run_until_event(&mut bus, 100), 8);
+        assert!(irq.irq_asserted());
+        assert_eq!(bus.next_event(), None);
+        assert_eq!(cpu.run_until_event(&mut bus, 3), 4);
+    }
+}
@@ -1,19 +1,10 @@
-macro_rules! dbg {
-    ($($x:tt)*) => {
-        {
-            #[cfg(debug_assertions)]
-            {
-                std::println!($($x)*)
-            }
-            #[cfg(not(debug_assertions))]
-            {
-                ($($x)*)
-            }
-        }
-    }
-}
+use std::collections::HashMap;
 
 use crate::components::bus::Bus;
+use crate::components::observer::{AccessKind, CpuObserver, Interrupt};
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::components::trace::Tracer;
+use crate::components::watchpoint::WatchpointHit;
 
 pub enum Flags {
     Carry = 0b00000001,
@@ -21,17 +12,17 @@ pub enum Flags {
     InterruptDisable = 0b00000100,
     DecimalMode = 0b00001000,
     BreakCommand = 0b00010000,
-    Overflow = 0b00100000,
-    Negative = 0b01000000,
+    Overflow = 0b01000000,
+    Negative = 0b10000000,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum IAMSubMode {
     N, X, Y
 }
 
 // For easier writing -> Refactor to InstructionAddressingMode later...
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum IAM {
     Accumulator,
     Immediate,
@@ -48,22 +39,109 @@ pub struct OperationCode {
     mode: IAM,
     bytes: u8,
     cycles: u8,
+    undocumented: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Registers {
-    program_counter: u16,
-    stack_pointer: u8,
-    accumulator: u8,
-    idx_x: u8,
-    idx_y: u8,
-    status_flags: u8,
+    pub program_counter: u16,
+    pub stack_pointer: u8,
+    pub accumulator: u8,
+    pub idx_x: u8,
+    pub idx_y: u8,
+    pub status_flags: u8,
 }
 
-#[derive(Debug)]
+// A Rust stand-in for a 6502 subroutine, see CPU6502::add_trap.
+pub type Trap = Box<dyn FnMut(&mut Registers, &mut Bus)>;
+
 pub struct CPU6502 {
     registers: Registers,
-    instructions: [Option<OperationCode>; 0xFF],
+    instructions: [Option<OperationCode>; 0x100],
+    cycles: u64,
+    trace: Option<Tracer>,
+    observers: Vec<Box<dyn CpuObserver>>,
+    // Interrupt recognized at the end of the last instruction, serviced before the next one.
+    pending: Option<Interrupt>,
+    // Each with whether it returns with an RTS.
+    traps: HashMap<u16, (Trap, bool)>,
+    // Halted by a JAM opcode, only a reset gets the CPU going again.
+    jammed: bool,
+}
+
+impl std::fmt::Debug for CPU6502 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return f.debug_struct("CPU6502")
+            .field("registers", &self.registers)
+            .field("cycles", &self.cycles)
+            .field("observers", &self.observers.len())
+            .field("pending", &self.pending)
+            .field("traps", &self.traps.len())
+            .field("jammed", &self.jammed)
+            .finish_non_exhaustive();
+    }
+}
+
+impl Snapshot for CPU6502 {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write_u16(self.registers.program_counter);
+        writer.write_u8(self.registers.stack_pointer);
+        writer.write_u8(self.registers.accumulator);
+        writer.write_u8(self.registers.idx_x);
+        writer.write_u8(self.registers.idx_y);
+        writer.write_u8(self.registers.status_flags);
+        writer.write_u64(self.cycles);
+        writer.write_u8(match self.pending {
+            None => 0,
+            Some(Interrupt::IRQ) => 1,
+            Some(_) => 2,
+        });
+        writer.write_bool(self.jammed);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.registers.program_counter = reader.read_u16()?;
+        self.registers.stack_pointer = reader.read_u8()?;
+        self.registers.accumulator = reader.read_u8()?;
+        self.registers.idx_x = reader.read_u8()?;
+        self.registers.idx_y = reader.read_u8()?;
+        self.registers.status_flags = reader.read_u8()?;
+        self.cycles = reader.read_u64()?;
+        self.pending = match reader.read_u8()? {
+            0 => None,
+            1 => Some(Interrupt::IRQ),
+            2 => Some(Interrupt::NMI),
+            pending => return Err(SnapshotError::Mismatch(format!("invalid pending interrupt {}", pending))),
+        };
+        self.jammed = reader.read_bool()?;
+        return Ok(());
+    }
+}
+
+impl OperationCode {
+    pub fn instruction(&self) -> Instruction {
+        return self.instruction;
+    }
+
+    pub fn mode(&self) -> IAM {
+        return self.mode;
+    }
+
+    pub fn bytes(&self) -> u8 {
+        return self.bytes;
+    }
+
+    // Not in the MOS programming manual, but stable on the NMOS 6502 and used by real programs.
+    pub fn undocumented(&self) -> bool {
+        return self.undocumented;
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Registers {
+        return Registers::new();
+    }
 }
 
 impl Registers {
@@ -85,7 +163,7 @@ impl Registers {
     pub fn set_flag(&mut self, flag: Flags, set: bool) {
         match set {
             true => { self.status_flags |= flag as u8 }
-            false => { self.status_flags &= flag as u8 }
+            false => { self.status_flags &= !(flag as u8) }
         }
     }
 }
@@ -110,6 +188,65 @@ pub enum Instruction {
     CLI,
     CLV,
     CMP,
+    CPX,
+    CPY,
+    DEC,
+    DEX,
+    DEY,
+    EOR,
+    INC,
+    INX,
+    INY,
+    JMP,
+    JSR,
+    LDA,
+    LDX,
+    LDY,
+    LSR,
+    NOP,
+    ORA,
+    PHA,
+    PHP,
+    PLA,
+    PLP,
+    ROL,
+    ROR,
+    RTI,
+    RTS,
+    SBC,
+    SEC,
+    SED,
+    SEI,
+    STA,
+    STX,
+    STY,
+    TAX,
+    TAY,
+    TSX,
+    TXA,
+    TXS,
+    TYA,
+    // Undocumented
+    ALR,
+    ANC,
+    ANE,
+    ARR,
+    DCP,
+    ISB,
+    JAM,
+    LAS,
+    LAX,
+    LXA,
+    RLA,
+    RRA,
+    SAX,
+    SBX,
+    SHA,
+    SHX,
+    SHY,
+    SLO,
+    SRE,
+    TAS,
 }
 
 enum Address {
@@ -117,35 +254,73 @@ enum Address {
     M(u16)
 }
 
+impl Default for CPU6502 {
+    fn default() -> CPU6502 {
+        return CPU6502::new();
+    }
+}
+
 impl CPU6502 {
-    // return: absolute address, addressed value, additional cycles needed
-    fn fetch(&mut self, opcode: &OperationCode, bus: &mut Bus) -> (Address, u8, u8) {
-        fn add(mode: IAMSubMode, idx_x: u8, idx_y: u8) -> u16 {
+    // return: absolute address, additional cycles needed. Consumes the operand bytes but leaves the
+    // addressed location alone, stores must not read it.
+    fn locate(&mut self, opcode: &OperationCode, bus: &mut Bus) -> (Address, u8) {
+        fn add(mode: IAMSubMode, idx_x: u8, idx_y: u8) -> u8 {
             return match mode {
                 IAMSubMode::N => {0x00}
-                IAMSubMode::X => {idx_x as u16}
-                IAMSubMode::Y => {idx_y as u16}
+                IAMSubMode::X => {idx_x}
+                IAMSubMode::Y => {idx_y}
             };
         }
 
+        // Indexing into the next page costs a cycle.
+        fn crossed(base: u16, address: u16) -> u8 {
+            return if base & 0xFF00 != address & 0xFF00 { 0x01 } else { 0x00 };
+        }
+
         return match opcode.mode {
             IAM::Accumulator => {
-                return (Address::A, self.registers.accumulator, 0x00);
+                (Address::A, 0x00)
             },
             IAM::Immediate => {
-                let address: u16 = self.next(bus) as u16;
-                return (Address::M(address), bus.read(address), 0x00);
+                self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
+                (Address::M(self.registers.program_counter), 0x00)
             },
             IAM::ZeroPage(sub_mode) => {
-                let address: u16 = (self.next(bus) as u16) + add(sub_mode, self.registers.idx_x, self.registers.idx_y);
-                return (Address::M(address), bus.read(address), 0x00);
+                // The index wraps around within the zero page.
+                let address: u16 = self.next(bus).wrapping_add(add(sub_mode, self.registers.idx_x, self.registers.idx_y)) as u16;
+                (Address::M(address), 0x00)
             },
             IAM::Absolute(sub_mode) => {
-                let address: u16 = (self.next(bus) as u16) << 8 + (self.next(bus) as u16) + add(sub_mode, self.registers.idx_x, self.registers.idx_y);
-                return (Address::M(address), bus.read(address), 0x00);
+                let low = self.next(bus);
+                let high = self.next(bus);
+                let base = u16::from_le_bytes([low, high]);
+                let address: u16 = base.wrapping_add(add(sub_mode, self.registers.idx_x, self.registers.idx_y) as u16);
+                (Address::M(address), crossed(base, address))
             },
-            IAM::Indirect(_sub_mode) => {
-                panic!("// TODO");
+            IAM::Indirect(IAMSubMode::N) => {
+                let low = self.next(bus);
+                let high = self.next(bus);
+                let pointer = u16::from_le_bytes([low, high]);
+                // The pointer's high byte comes from the same page, even when the low byte is at its end.
+                let low = self.read(bus, pointer, AccessKind::Data);
+                let high = self.read(bus, (pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF), AccessKind::Data);
+                let address = u16::from_le_bytes([low, high]);
+                (Address::M(address), 0x00)
+            },
+            IAM::Indirect(IAMSubMode::X) => {
+                let pointer = self.next(bus).wrapping_add(self.registers.idx_x);
+                let low = self.read(bus, pointer as u16, AccessKind::Data);
+                let high = self.read(bus, pointer.wrapping_add(1) as u16, AccessKind::Data);
+                let address = u16::from_le_bytes([low, high]);
+                (Address::M(address), 0x00)
+            },
+            IAM::Indirect(IAMSubMode::Y) => {
+                let pointer = self.next(bus);
+                let low = self.read(bus, pointer as u16, AccessKind::Data);
+                let high = self.read(bus, pointer.wrapping_add(1) as u16, AccessKind::Data);
+                let base = u16::from_le_bytes([low, high]);
+                let address = base.wrapping_add(self.registers.idx_y as u16);
+                (Address::M(address), crossed(base, address))
             },
             IAM::Relative => {
                 panic!("// TODO");
@@ -156,83 +331,667 @@ impl CPU6502 {
         };
     }
 
-    fn adc(&mut self, opcode: OperationCode, bus: &mut Bus) {
+    // return: absolute address, addressed value, additional cycles needed
+    fn fetch(&mut self, opcode: &OperationCode, bus: &mut Bus) -> (Address, u8, u8) {
+        return match opcode.mode {
+            IAM::Accumulator => (Address::A, self.registers.accumulator, 0x00),
+            IAM::Immediate => {
+                let value = self.next(bus);
+                (Address::M(self.registers.program_counter), value, 0x00)
+            },
+            _ => {
+                let (address, additional_cycles) = self.locate(opcode, bus);
+                let value = match address {
+                    Address::A => self.registers.accumulator,
+                    Address::M(address) => self.read(bus, address, AccessKind::Data),
+                };
+                (address, value, additional_cycles)
+            },
+        };
+    }
+
+    fn set_zero_negative(&mut self, value: u8) {
+        self.registers.set_flag(Flags::Zero, value == 0x00);
+        self.registers.set_flag(Flags::Negative, value & 0b10000000 != 0);
+    }
+
+    fn adc(&mut self, opcode: OperationCode, bus: &mut Bus) -> u8 {
+        let (_, addressed, additional_cycles) = self.fetch(&opcode, bus);
+        self.add(addressed);
+        return additional_cycles;
+    }
+
+    fn add(&mut self, addressed: u8) {
         let accumulator: u8 = self.registers.accumulator;
-        let (_, addressed, _additional_cycles) = self.fetch(&opcode, bus);
-        let result: u16 = (accumulator as u16) + (addressed as u16) + ((self.registers.status_flags & 0x01) as u16);
+        let carry = (self.registers.status_flags & 0x01) as u16;
+        let result: u16 = (accumulator as u16) + (addressed as u16) + carry;
 
         let [overflow, result]: [u8; 2] = result.to_be_bytes();
 
-        self.registers.set_flag(Flags::Carry, overflow >= 0x01);
-        self.registers.set_flag(Flags::Overflow, (accumulator ^ result) & !(accumulator ^ result) == 0x01);
+        if !self.registers.get_flag(Flags::DecimalMode) {
+            self.registers.set_flag(Flags::Carry, overflow >= 0x01);
+            self.registers.set_flag(Flags::Overflow, !(accumulator ^ addressed) & (accumulator ^ result) & 0x80 != 0);
+            self.set_zero_negative(result);
+            self.registers.accumulator = result;
+            return;
+        }
+
+        // NMOS decimal mode: Zero comes from the binary sum, Negative and Overflow from the sum after
+        // the low digit was adjusted, Carry from the adjusted high digit.
+        let mut low = (accumulator & 0x0F) as u16 + (addressed & 0x0F) as u16 + carry;
+        let mut high = (accumulator >> 4) as u16 + (addressed >> 4) as u16;
+        if low > 0x09 {
+            low += 0x06;
+        }
+        if low > 0x0F {
+            high += 0x01;
+        }
+        let intermediate = (high << 4) as u8;
         self.registers.set_flag(Flags::Zero, result == 0x00);
-        self.registers.set_flag(Flags::Negative, result & 0b10000000 != 0);
+        self.registers.set_flag(Flags::Negative, intermediate & 0b10000000 != 0);
+        self.registers.set_flag(Flags::Overflow, !(accumulator ^ addressed) & (accumulator ^ intermediate) & 0x80 != 0);
+        if high > 0x09 {
+            high += 0x06;
+        }
+        self.registers.set_flag(Flags::Carry, high > 0x0F);
+        self.registers.accumulator = ((high << 4) as u8) | (low as u8 & 0x0F);
+    }
 
-        self.registers.accumulator = result;
+    fn sbc(&mut self, opcode: OperationCode, bus: &mut Bus) -> u8 {
+        let (_, addressed, additional_cycles) = self.fetch(&opcode, bus);
+        self.subtract(addressed);
+        return additional_cycles;
+    }
+
+    fn subtract(&mut self, addressed: u8) {
+        let accumulator: u8 = self.registers.accumulator;
+        let borrow = 1 - (self.registers.status_flags & 0x01) as i16;
+        let result: i16 = accumulator as i16 - addressed as i16 - borrow;
+
+        // Every flag comes from the binary difference, in decimal mode too.
+        self.registers.set_flag(Flags::Carry, result >= 0);
+        self.registers.set_flag(Flags::Overflow, (accumulator ^ addressed) & (accumulator ^ result as u8) & 0x80 != 0);
+        self.set_zero_negative(result as u8);
 
-        dbg!("ADC\t{:#04x}+{:#04x}={:#04x}\tA={:#04x}\tFlags={:#010b}", accumulator, addressed, result, self.registers.accumulator, self.registers.status_flags);
+        if !self.registers.get_flag(Flags::DecimalMode) {
+            self.registers.accumulator = result as u8;
+            return;
+        }
+
+        let mut low = (accumulator & 0x0F) as i16 - (addressed & 0x0F) as i16 - borrow;
+        let mut high = (accumulator >> 4) as i16 - (addressed >> 4) as i16;
+        if low < 0 {
+            low -= 0x06;
+            high -= 0x01;
+        }
+        if high < 0 {
+            high -= 0x06;
+        }
+        self.registers.accumulator = ((high << 4) as u8) | (low as u8 & 0x0F);
     }
 
-    fn and(&mut self, opcode: OperationCode, bus: &mut Bus) {
+    fn and(&mut self, opcode: OperationCode, bus: &mut Bus) -> u8 {
         let accumulator = self.registers.accumulator;
-        let (_, addressed, _additional_cycles) = self.fetch(&opcode, bus);
+        let (_, addressed, additional_cycles) = self.fetch(&opcode, bus);
         let result = accumulator & addressed;
 
-        self.registers.set_flag(Flags::Zero, result == 0x00);
-        self.registers.set_flag(Flags::Negative, result & 0b10000000 != 0);
+        self.set_zero_negative(result);
+        self.registers.accumulator = result;
+        return additional_cycles;
+    }
+
+    fn ora(&mut self, opcode: OperationCode, bus: &mut Bus) -> u8 {
+        let accumulator = self.registers.accumulator;
+        let (_, addressed, additional_cycles) = self.fetch(&opcode, bus);
+        let result = accumulator | addressed;
+
+        self.set_zero_negative(result);
+        self.registers.accumulator = result;
+        return additional_cycles;
+    }
 
+    fn eor(&mut self, opcode: OperationCode, bus: &mut Bus) -> u8 {
+        let accumulator = self.registers.accumulator;
+        let (_, addressed, additional_cycles) = self.fetch(&opcode, bus);
+        let result = accumulator ^ addressed;
+
+        self.set_zero_negative(result);
         self.registers.accumulator = result;
+        return additional_cycles;
+    }
+
+    fn bit(&mut self, opcode: OperationCode, bus: &mut Bus) {
+        let (_, addressed, _) = self.fetch(&opcode, bus);
 
-        dbg!("AND\t{:#04x}&{:#04x}={:#04x}\tA={:#04x}\tFlags={:#010b}", accumulator, addressed, result, self.registers.accumulator, self.registers.status_flags);
+        self.registers.set_flag(Flags::Zero, self.registers.accumulator & addressed == 0x00);
+        self.registers.set_flag(Flags::Overflow, addressed & 0b01000000 != 0);
+        self.registers.set_flag(Flags::Negative, addressed & 0b10000000 != 0);
     }
 
-    fn asl(&mut self, opcode: OperationCode, bus: &mut Bus) {
-        let (address, addressed, _additional_cycles) = self.fetch(&opcode, bus);
-        let carry = (addressed & 0b10000000) >> 7 == 0x01;
+    fn compare(&mut self, opcode: OperationCode, bus: &mut Bus, register: u8) -> u8 {
+        let (_, addressed, additional_cycles) = self.fetch(&opcode, bus);
+        self.compare_value(register, addressed);
+        return additional_cycles;
+    }
+
+    fn compare_value(&mut self, register: u8, addressed: u8) {
+        self.registers.set_flag(Flags::Carry, register >= addressed);
+        self.set_zero_negative(register.wrapping_sub(addressed));
+    }
+
+    // Read-modify-write on the accumulator or memory, the operation gets the value and the carry and
+    // returns the result and the new carry. Returns the result as well.
+    fn modify(&mut self, opcode: OperationCode, bus: &mut Bus, operation: impl Fn(u8, bool) -> (u8, bool)) -> u8 {
+        let (address, addressed, _) = self.fetch(&opcode, bus);
+        let (result, carry) = operation(addressed, self.registers.get_flag(Flags::Carry));
         match address {
             Address::A => {
-                self.registers.accumulator = addressed << 1;
-                dbg!("ASL\t{:#04x}<<0x01={:#04x}\tFlags={:#010b}", addressed, self.registers.accumulator, self.registers.status_flags);
+                self.registers.accumulator = result;
             }
             Address::M(address) => {
-                bus.write(address, addressed << 1);
-                dbg!("ASL\t{:#04x}<<0x01={:#04x}\tFlags={:#010b}", addressed, bus.read(address), self.registers.status_flags);
+                self.write(bus, address, result, AccessKind::Data);
             }
         }
 
         self.registers.set_flag(Flags::Carry, carry);
+        self.set_zero_negative(result);
+        return result;
+    }
+
+    // INC and DEC, which leave the carry alone.
+    fn step(&mut self, opcode: OperationCode, bus: &mut Bus, delta: u8) -> u8 {
+        let (address, addressed, _) = self.fetch(&opcode, bus);
+        let result = addressed.wrapping_add(delta);
+        if let Address::M(address) = address {
+            self.write(bus, address, result, AccessKind::Data);
+        }
+
+        self.set_zero_negative(result);
+        return result;
+    }
+
+    fn load(&mut self, opcode: OperationCode, bus: &mut Bus) -> (u8, u8) {
+        let (_, addressed, additional_cycles) = self.fetch(&opcode, bus);
+
+        self.set_zero_negative(addressed);
+        return (addressed, additional_cycles);
+    }
+
+    fn store(&mut self, opcode: OperationCode, bus: &mut Bus, value: u8) {
+        if let (Address::M(address), _) = self.locate(&opcode, bus) {
+            self.write(bus, address, value, AccessKind::Data);
+        }
+    }
+
+    // Transfers between registers, all but TXS set Zero and Negative.
+    fn transfer(&mut self, value: u8) -> u8 {
+        self.set_zero_negative(value);
+        return value;
+    }
+
+    // SHA, SHX, SHY and TAS store the register ANDed with the high byte of the base address plus one.
+    // When the index crosses a page the result also replaces the high byte of the address.
+    fn store_high(&mut self, opcode: OperationCode, bus: &mut Bus, register: u8) {
+        if let (Address::M(address), crossed) = self.locate(&opcode, bus) {
+            let [low, high] = address.to_le_bytes();
+            let value = register & if crossed != 0 { high } else { high.wrapping_add(1) };
+            let address = if crossed != 0 { u16::from_le_bytes([low, value]) } else { address };
+            self.write(bus, address, value, AccessKind::Data);
+        }
+    }
+
+    // ARR: AND, then ROR on the accumulator with flags of its own. Decimal mode adjusts each digit the
+    // way ADC would.
+    fn arr(&mut self, opcode: OperationCode, bus: &mut Bus) {
+        let (_, addressed, _) = self.fetch(&opcode, bus);
+        let value = self.registers.accumulator & addressed;
+        let carry = self.registers.get_flag(Flags::Carry);
+        let mut result = value >> 1 | (carry as u8) << 7;
+
+        if !self.registers.get_flag(Flags::DecimalMode) {
+            self.set_zero_negative(result);
+            self.registers.set_flag(Flags::Carry, result & 0b01000000 != 0);
+            self.registers.set_flag(Flags::Overflow, (result ^ result << 1) & 0b01000000 != 0);
+            self.registers.accumulator = result;
+            return;
+        }
+
+        self.registers.set_flag(Flags::Negative, carry);
+        self.registers.set_flag(Flags::Zero, result == 0x00);
+        self.registers.set_flag(Flags::Overflow, (value ^ result) & 0b01000000 != 0);
+        if (value & 0x0F) + (value & 0x01) > 0x05 {
+            result = (result & 0xF0) | (result.wrapping_add(0x06) & 0x0F);
+        }
+        let adjust = (value >> 4) + ((value >> 4) & 0x01) > 0x05;
+        self.registers.set_flag(Flags::Carry, adjust);
+        if adjust {
+            result = result.wrapping_add(0x60);
+        }
+        self.registers.accumulator = result;
+    }
+
+    // return: additional cycles needed, one for a taken branch and another one for a target on the
+    // next page.
+    fn branch(&mut self, bus: &mut Bus, condition: bool) -> u8 {
+        let offset = self.next(bus) as i8 as u16;
+        if !condition {
+            return 0x00;
+        }
+
+        // Relative to the following instruction, which tick steps to from the operand.
+        let following = self.registers.program_counter.wrapping_add(1);
+        let target = following.wrapping_add(offset);
+        self.registers.program_counter = target.wrapping_sub(1);
+        return if following & 0xFF00 != target & 0xFF00 { 0x02 } else { 0x01 };
+    }
+
+    fn brk(&mut self, bus: &mut Bus) {
+        // The byte after BRK is skipped on return.
+        self.next(bus);
+        let return_address = self.registers.program_counter.wrapping_add(1);
+        self.interrupt(bus, Interrupt::Break, return_address);
+        // tick moves past the last byte of every instruction, land on the handler.
+        self.registers.program_counter = self.registers.program_counter.wrapping_sub(1);
+    }
+
+    fn jmp(&mut self, opcode: OperationCode, bus: &mut Bus) {
+        if let (Address::M(address), _) = self.locate(&opcode, bus) {
+            self.registers.program_counter = address.wrapping_sub(1);
+        }
+    }
+
+    // Pushes the address of its own last byte, RTS steps past it.
+    fn jsr(&mut self, opcode: OperationCode, bus: &mut Bus) {
+        if let (Address::M(address), _) = self.locate(&opcode, bus) {
+            let return_address = self.registers.program_counter;
+            self.push(bus, (return_address >> 8) as u8);
+            self.push(bus, return_address as u8);
+            self.registers.program_counter = address.wrapping_sub(1);
+        }
+    }
+
+    fn rts(&mut self, bus: &mut Bus) {
+        let low = self.pull(bus) as u16;
+        let high = self.pull(bus) as u16;
+        self.registers.program_counter = high << 8 | low;
+    }
+
+    fn rti(&mut self, bus: &mut Bus) {
+        self.plp(bus);
+        let low = self.pull(bus) as u16;
+        let high = self.pull(bus) as u16;
+        // The interrupt pushed the address of the next instruction itself.
+        self.registers.program_counter = (high << 8 | low).wrapping_sub(1);
+    }
+
+    fn php(&mut self, bus: &mut Bus) {
+        // Like BRK, PHP pushes B and bit 5 set.
+        let status = self.registers.status_flags | Flags::BreakCommand as u8 | 0b00100000;
+        self.push(bus, status);
+    }
+
+    fn plp(&mut self, bus: &mut Bus) {
+        // B only exists on the stack, bit 5 always reads as set.
+        let status = self.pull(bus);
+        self.registers.status_flags = status & !(Flags::BreakCommand as u8) | 0b00100000;
+    }
+
+    // Pushes the return state and jumps through the vector of the interrupt. An NMI edge latched while
+    // a BRK or IRQ is being entered hijacks it: the return state is pushed as usual (B stays set for
+    // BRK) but the NMI vector is taken, so the BRK or IRQ is lost.
+    fn interrupt(&mut self, bus: &mut Bus, interrupt: Interrupt, return_address: u16) {
+        self.push(bus, (return_address >> 8) as u8);
+        self.push(bus, return_address as u8);
+
+        // Bit 5 always reads as set, B only exists on the stack and tells BRK from IRQ and NMI.
+        let break_flag = if let Interrupt::Break = interrupt { Flags::BreakCommand as u8 } else { 0 };
+        let status = self.registers.status_flags & !(Flags::BreakCommand as u8) | 0b00100000 | break_flag;
+        self.push(bus, status);
+        self.registers.set_flag(Flags::InterruptDisable, true);
+
+        let (interrupt, vector) = match interrupt {
+            Interrupt::NMI => (Interrupt::NMI, 0xFFFA),
+            _ if bus.interrupts().take_nmi() => (Interrupt::NMI, 0xFFFA),
+            Interrupt::Reset => (Interrupt::Reset, 0xFFFC),
+            interrupt => (interrupt, 0xFFFE),
+        };
+        let low = self.read(bus, vector, AccessKind::Vector) as u16;
+        let high = self.read(bus, vector + 1, AccessKind::Vector) as u16;
+        self.registers.program_counter = high << 8 | low;
+
+        if !self.observers.is_empty() {
+            for observer in &mut self.observers {
+                observer.interrupt(interrupt, &self.registers);
+            }
+        }
+    }
+
+    // Samples the interrupt lines, which the 6502 does once at the end of every instruction.
+    fn poll(&mut self, bus: &Bus, interrupt_disable: bool) {
+        if bus.interrupts().take_nmi() {
+            self.pending = Some(Interrupt::NMI);
+        } else if bus.interrupts().irq() && !interrupt_disable {
+            self.pending = Some(Interrupt::IRQ);
+        }
     }
 
-    fn branch(&mut self, bus: &Bus) {
-        self.registers.program_counter as i16 += self.next(bus) as i16;
+    fn push(&mut self, bus: &mut Bus, data: u8) {
+        self.write(bus, 0x0100 | self.registers.stack_pointer as u16, data, AccessKind::Stack);
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1);
+    }
+
+    fn pull(&mut self, bus: &mut Bus) -> u8 {
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_add(1);
+        return self.read(bus, 0x0100 | self.registers.stack_pointer as u16, AccessKind::Stack);
+    }
+
+    fn next(&mut self, bus: &mut Bus) -> u8 {
+        self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
+        return self.read(bus, self.registers.program_counter, AccessKind::Operand);
+    }
+
+    fn read(&mut self, bus: &mut Bus, address: u16, kind: AccessKind) -> u8 {
+        let data = match kind {
+            AccessKind::OpcodeFetch => bus.fetch(address),
+            _ => bus.read(address),
+        };
+        if !self.observers.is_empty() {
+            for observer in &mut self.observers {
+                observer.read(address, data, kind);
+            }
+        }
+        return data;
     }
 
-    fn next(&mut self, bus: &Bus) -> u8 {
-        self.registers.program_counter += 1;
-        return bus.read(self.registers.program_counter);
+    fn write(&mut self, bus: &mut Bus, address: u16, data: u8, kind: AccessKind) {
+        bus.write(address, data);
+        if !self.observers.is_empty() {
+            for observer in &mut self.observers {
+                observer.write(address, data, kind);
+            }
+        }
     }
 
-    fn execute(&mut self, bus: &mut Bus, opcode: OperationCode) {
+    // return: additional cycles needed
+    fn execute(&mut self, bus: &mut Bus, opcode: OperationCode) -> u8 {
+        let carry = self.registers.get_flag(Flags::Carry);
+        let zero = self.registers.get_flag(Flags::Zero);
+        let negative = self.registers.get_flag(Flags::Negative);
+        let overflow = self.registers.get_flag(Flags::Overflow);
+
         match opcode.instruction {
-            Instruction::ADC => { self.adc(opcode, bus) }
-            Instruction::AND => { self.and(opcode, bus) }
-            Instruction::ASL => { self.asl(opcode, bus) }
-            Instruction::BCC => { if !self.registers.get_flag(Flags::Carry) { self.branch(bus) } }
-            Instruction::BCS => { if self.registers.get_flag(Flags::Carry) { self.branch(bus) } }
-            Instruction::BEQ => { if self.registers.get_flag(Flags::Zero) { self.branch(bus) } }
-            Instruction::BIT => { /* TODO... */ }
-            Instruction::BMI => { if self.registers.get_flag(Flags::Negative) { self.branch(bus) } }
-            Instruction::BNE => { if !self.registers.get_flag(Flags::Zero) { self.branch(bus) } }
-            Instruction::BPL => { if !self.registers.get_flag(Flags::Negative) { self.branch(bus) } }
-            Instruction::BRK => { /* TODO... */ }
-            Instruction::BVC => { if !self.registers.get_flag(Flags::Overflow) { self.branch(bus) } }
-            Instruction::BVS => { if self.registers.get_flag(Flags::Overflow) { self.branch(bus) } }
+            Instruction::ADC => { return self.adc(opcode, bus) }
+            Instruction::AND => { return self.and(opcode, bus) }
+            Instruction::ASL => { self.modify(opcode, bus, |value, _| (value << 1, value & 0b10000000 != 0)); }
+            Instruction::BCC => { return self.branch(bus, !carry) }
+            Instruction::BCS => { return self.branch(bus, carry) }
+            Instruction::BEQ => { return self.branch(bus, zero) }
+            Instruction::BIT => { self.bit(opcode, bus) }
+            Instruction::BMI => { return self.branch(bus, negative) }
+            Instruction::BNE => { return self.branch(bus, !zero) }
+            Instruction::BPL => { return self.branch(bus, !negative) }
+            Instruction::BRK => { self.brk(bus) }
+            Instruction::BVC => { return self.branch(bus, !overflow) }
+            Instruction::BVS => { return self.branch(bus, overflow) }
             Instruction::CLC => { self.registers.set_flag(Flags::Carry, false) }
             Instruction::CLD => { self.registers.set_flag(Flags::DecimalMode, false) }
             Instruction::CLI => { self.registers.set_flag(Flags::InterruptDisable, false) }
             Instruction::CLV => { self.registers.set_flag(Flags::Overflow, false) }
-            Instruction::CMP => {}
+            Instruction::CMP => { return self.compare(opcode, bus, self.registers.accumulator) }
+            Instruction::CPX => { return self.compare(opcode, bus, self.registers.idx_x) }
+            Instruction::CPY => { return self.compare(opcode, bus, self.registers.idx_y) }
+            Instruction::DEC => { self.step(opcode, bus, 0xFF); }
+            Instruction::DEX => { self.registers.idx_x = self.transfer(self.registers.idx_x.wrapping_sub(1)) }
+            Instruction::DEY => { self.registers.idx_y = self.transfer(self.registers.idx_y.wrapping_sub(1)) }
+            Instruction::EOR => { return self.eor(opcode, bus) }
+            Instruction::INC => { self.step(opcode, bus, 0x01); }
+            Instruction::INX => { self.registers.idx_x = self.transfer(self.registers.idx_x.wrapping_add(1)) }
+            Instruction::INY => { self.registers.idx_y = self.transfer(self.registers.idx_y.wrapping_add(1)) }
+            Instruction::JMP => { self.jmp(opcode, bus) }
+            Instruction::JSR => { self.jsr(opcode, bus) }
+            Instruction::LDA => {
+                let (value, additional_cycles) = self.load(opcode, bus);
+                self.registers.accumulator = value;
+                return additional_cycles;
+            }
+            Instruction::LDX => {
+                let (value, additional_cycles) = self.load(opcode, bus);
+                self.registers.idx_x = value;
+                return additional_cycles;
+            }
+            Instruction::LDY => {
+                let (value, additional_cycles) = self.load(opcode, bus);
+                self.registers.idx_y = value;
+                return additional_cycles;
+            }
+            Instruction::LSR => { self.modify(opcode, bus, |value, _| (value >> 1, value & 0b00000001 != 0)); }
+            // The undocumented forms read their operand like a load.
+            Instruction::NOP if opcode.mode != IAM::Implied => { return self.fetch(&opcode, bus).2 }
+            Instruction::NOP => {}
+            Instruction::ORA => { return self.ora(opcode, bus) }
+            Instruction::PHA => { self.push(bus, self.registers.accumulator) }
+            Instruction::PHP => { self.php(bus) }
+            Instruction::PLA => {
+                let value = self.pull(bus);
+                self.registers.accumulator = self.transfer(value);
+            }
+            Instruction::PLP => { self.plp(bus) }
+            Instruction::ROL => {
+                self.modify(opcode, bus, |value, carry| (value << 1 | carry as u8, value & 0b10000000 != 0));
+            }
+            Instruction::ROR => {
+                self.modify(opcode, bus, |value, carry| (value >> 1 | (carry as u8) << 7, value & 0b00000001 != 0));
+            }
+            Instruction::RTI => { self.rti(bus) }
+            Instruction::RTS => { self.rts(bus) }
+            Instruction::SBC => { return self.sbc(opcode, bus) }
+            Instruction::SEC => { self.registers.set_flag(Flags::Carry, true) }
+            Instruction::SED => { self.registers.set_flag(Flags::DecimalMode, true) }
+            Instruction::SEI => { self.registers.set_flag(Flags::InterruptDisable, true) }
+            Instruction::STA => { self.store(opcode, bus, self.registers.accumulator) }
+            Instruction::STX => { self.store(opcode, bus, self.registers.idx_x) }
+            Instruction::STY => { self.store(opcode, bus, self.registers.idx_y) }
+            Instruction::TAX => { self.registers.idx_x = self.transfer(self.registers.accumulator) }
+            Instruction::TAY => { self.registers.idx_y = self.transfer(self.registers.accumulator) }
+            Instruction::TSX => { self.registers.idx_x = self.transfer(self.registers.stack_pointer) }
+            Instruction::TXA => { self.registers.accumulator = self.transfer(self.registers.idx_x) }
+            Instruction::TXS => { self.registers.stack_pointer = self.registers.idx_x }
+            Instruction::TYA => { self.registers.accumulator = self.transfer(self.registers.idx_y) }
+            Instruction::ALR => {
+                self.and(opcode, bus);
+                let accumulator = self.registers.accumulator;
+                self.registers.set_flag(Flags::Carry, accumulator & 0b00000001 != 0);
+                self.registers.accumulator = self.transfer(accumulator >> 1);
+            }
+            Instruction::ANC => {
+                self.and(opcode, bus);
+                self.registers.set_flag(Flags::Carry, self.registers.accumulator & 0b10000000 != 0);
+            }
+            Instruction::ANE => {
+                // Unstable on real chips, 0xEE is the usual magic constant.
+                let (_, addressed, _) = self.fetch(&opcode, bus);
+                self.registers.accumulator = self.transfer((self.registers.accumulator | 0xEE) & self.registers.idx_x & addressed);
+            }
+            Instruction::ARR => { self.arr(opcode, bus) }
+            Instruction::DCP => {
+                let result = self.step(opcode, bus, 0xFF);
+                self.compare_value(self.registers.accumulator, result);
+            }
+            Instruction::ISB => {
+                let result = self.step(opcode, bus, 0x01);
+                self.subtract(result);
+            }
+            Instruction::JAM => {
+                // Stuck on the opcode until a reset.
+                self.jammed = true;
+                self.registers.program_counter = self.registers.program_counter.wrapping_sub(1);
+            }
+            Instruction::LAS => {
+                let (_, addressed, additional_cycles) = self.fetch(&opcode, bus);
+                let value = self.transfer(addressed & self.registers.stack_pointer);
+                (self.registers.accumulator, self.registers.idx_x, self.registers.stack_pointer) = (value, value, value);
+                return additional_cycles;
+            }
+            Instruction::LAX => {
+                let (value, additional_cycles) = self.load(opcode, bus);
+                (self.registers.accumulator, self.registers.idx_x) = (value, value);
+                return additional_cycles;
+            }
+            Instruction::LXA => {
+                // Unstable like ANE, with the same magic constant.
+                let (_, addressed, _) = self.fetch(&opcode, bus);
+                let value = self.transfer((self.registers.accumulator | 0xEE) & addressed);
+                (self.registers.accumulator, self.registers.idx_x) = (value, value);
+            }
+            Instruction::RLA => {
+                let result = self.modify(opcode, bus, |value, carry| (value << 1 | carry as u8, value & 0b10000000 != 0));
+                self.registers.accumulator = self.transfer(self.registers.accumulator & result);
+            }
+            Instruction::RRA => {
+                let result = self.modify(opcode, bus, |value, carry| (value >> 1 | (carry as u8) << 7, value & 0b00000001 != 0));
+                self.add(result);
+            }
+            Instruction::SAX => { self.store(opcode, bus, self.registers.accumulator & self.registers.idx_x) }
+            Instruction::SBX => {
+                let (_, addressed, _) = self.fetch(&opcode, bus);
+                let value = self.registers.accumulator & self.registers.idx_x;
+                self.registers.set_flag(Flags::Carry, value >= addressed);
+                self.registers.idx_x = self.transfer(value.wrapping_sub(addressed));
+            }
+            Instruction::SHA => { self.store_high(opcode, bus, self.registers.accumulator & self.registers.idx_x) }
+            Instruction::SHX => { self.store_high(opcode, bus, self.registers.idx_x) }
+            Instruction::SHY => { self.store_high(opcode, bus, self.registers.idx_y) }
+            Instruction::SLO => {
+                let result = self.modify(opcode, bus, |value, _| (value << 1, value & 0b10000000 != 0));
+                self.registers.accumulator = self.transfer(self.registers.accumulator | result);
+            }
+            Instruction::SRE => {
+                let result = self.modify(opcode, bus, |value, _| (value >> 1, value & 0b00000001 != 0));
+                self.registers.accumulator = self.transfer(self.registers.accumulator ^ result);
+            }
+            Instruction::TAS => {
+                self.registers.stack_pointer = self.registers.accumulator & self.registers.idx_x;
+                self.store_high(opcode, bus, self.registers.stack_pointer);
+            }
+        }
+        return 0x00;
+    }
+
+    pub fn registers(&self) -> &Registers {
+        return &self.registers;
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        return &mut self.registers;
+    }
+
+    pub fn cycles(&self) -> u64 {
+        return self.cycles;
+    }
+
+    pub fn jammed(&self) -> bool {
+        return self.jammed;
+    }
+
+    pub fn decode(&self, byte: u8) -> Option<OperationCode> {
+        return self.instructions.get(byte as usize).copied().flatten();
+    }
+
+    // Emits one nestest.log formatted line per instruction to the sink, None disables tracing.
+    pub fn set_trace(&mut self, sink: Option<Box<dyn std::io::Write>>) {
+        self.trace = sink.map(Tracer::new);
+    }
+
+    pub fn attach_observer(&mut self, observer: Box<dyn CpuObserver>) {
+        self.observers.push(observer);
+    }
+
+    // Starts over from the reset vector, as after power-on or a pulse on the RESET line. Like the
+    // hardware it goes through the motions of an interrupt without writing to the stack.
+    pub fn reset(&mut self, bus: &mut Bus) {
+        let low = self.read(bus, 0xFFFC, AccessKind::Vector) as u16;
+        let high = self.read(bus, 0xFFFD, AccessKind::Vector) as u16;
+        self.registers.program_counter = high << 8 | low;
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(3);
+        self.registers.set_flag(Flags::InterruptDisable, true);
+        self.pending = None;
+        self.jammed = false;
+        self.cycles += 7;
+        bus.tick(7);
+
+        if !self.observers.is_empty() {
+            for observer in &mut self.observers {
+                observer.interrupt(Interrupt::Reset, &self.registers);
+            }
+        }
+    }
+
+    // Executes up to the given number of instructions, stopping early once a watchpoint on the bus
+    // triggers: after the instruction which read or wrote, in front of the one to be executed.
+    pub fn run(&mut self, bus: &mut Bus, instructions: u64) -> Option<WatchpointHit> {
+        for _ in 0..instructions {
+            self.tick(bus);
+            if let Some(hit) = bus.take_watchpoint_hit() {
+                return Some(hit);
+            }
+        }
+        return None;
+    }
+
+    // Runs the closure instead of the code at the address whenever execution gets there, e.g. to stand
+    // in for a ROM routine which cannot be shipped or to skip a slow one in a test. The closure gets the
+    // registers and the bus, then an RTS returns to the caller; changing the program counter has no
+    // effect. Replaces any trap already at the address. The opcode at the address is still fetched, and
+    // the tracer and observers see the trap in place of the instruction.
+    pub fn add_trap(&mut self, address: u16, trap: impl FnMut(&mut Registers, &mut Bus) + 'static) {
+        self.traps.insert(address, (Box::new(trap), true));
+    }
+
+    // Like add_trap for code which is jumped to rather than called, e.g. a warm start. There is no RTS,
+    // execution goes on wherever the closure leaves the program counter, and the trap takes the 3
+    // cycles of a JMP. Left at the address, the trap runs again on every step.
+    pub fn add_jump_trap(&mut self, address: u16, trap: impl FnMut(&mut Registers, &mut Bus) + 'static) {
+        self.traps.insert(address, (Box::new(trap), false));
+    }
+
+    pub fn remove_trap(&mut self, address: u16) -> bool {
+        return self.traps.remove(&address).is_some();
+    }
+
+    // The trap followed by the RTS, which takes its 6 cycles.
+    fn trap(&mut self, bus: &mut Bus) -> bool {
+        let address = self.registers.program_counter;
+        if !self.traps.contains_key(&address) {
+            return false;
         }
+        if let Some(tracer) = &mut self.trace {
+            tracer.trace_trap(&self.registers, self.cycles);
+        }
+        let mut returns = false;
+        if let Some((trap, rts)) = self.traps.get_mut(&address) {
+            trap(&mut self.registers, bus);
+            returns = *rts;
+        }
+
+        let cycles = if returns {
+            let low = self.pull(bus) as u16;
+            let high = self.pull(bus) as u16;
+            self.registers.program_counter = (high << 8 | low).wrapping_add(1);
+            6
+        } else {
+            3
+        };
+        self.cycles += cycles;
+        bus.tick(cycles);
+        let interrupt_disable = self.registers.get_flag(Flags::InterruptDisable);
+        self.poll(bus, interrupt_disable);
+
+        if !self.observers.is_empty() {
+            for observer in &mut self.observers {
+                observer.trap(address, &self.registers);
+            }
+        }
+        return true;
     }
 
     pub fn dump_registers(&self) {
@@ -240,23 +999,100 @@ impl CPU6502 {
     }
 
     pub fn tick(&mut self, bus: &mut Bus) {
-        let byte: u8 = bus.read(self.registers.program_counter);
+        // A jammed CPU ignores interrupts and does nothing, but the clock keeps running for the devices.
+        if self.jammed {
+            self.cycles += 1;
+            bus.tick(1);
+            return;
+        }
+
+        // Entering an interrupt takes the place of an instruction. The first instruction of the handler
+        // always runs before interrupts are polled again.
+        if let Some(interrupt) = self.pending.take() {
+            self.interrupt(bus, interrupt, self.registers.program_counter);
+            self.cycles += 7;
+            bus.tick(7);
+            return;
+        }
+
+        if bus.breaks_at(self.registers.program_counter) {
+            return;
+        }
+
+        let byte: u8 = self.read(bus, self.registers.program_counter, AccessKind::OpcodeFetch);
+
+        if !self.traps.is_empty() && self.trap(bus) {
+            return;
+        }
 
         match self.instructions[byte as usize] {
             Some(opcode) => {
-                self.execute(bus, opcode);
-                self.next(bus);
+                if let Some(tracer) = &mut self.trace {
+                    tracer.trace(&opcode, &self.registers, self.cycles, bus);
+                }
+                if !self.observers.is_empty() {
+                    for observer in &mut self.observers {
+                        observer.before_instruction(&opcode, &self.registers);
+                    }
+                }
+
+                let interrupt_disable = self.registers.get_flag(Flags::InterruptDisable);
+                let cycles = opcode.cycles as u64 + self.execute(bus, opcode) as u64;
+                self.cycles += cycles;
+                // Step past the last byte of the instruction without another bus access.
+                self.registers.program_counter = self.registers.program_counter.wrapping_add(1);
+                bus.tick(cycles);
+
+                // CLI, SEI and PLP change the flag after the lines were polled, so the new value only
+                // counts from the next instruction on. Every other instruction polls with the current one.
+                match opcode.instruction {
+                    // Like any interrupt, BRK runs the first instruction of its handler before polling.
+                    Instruction::BRK => {}
+                    Instruction::CLI | Instruction::SEI | Instruction::PLP => self.poll(bus, interrupt_disable),
+                    _ => {
+                        let interrupt_disable = self.registers.get_flag(Flags::InterruptDisable);
+                        self.poll(bus, interrupt_disable);
+                    }
+                }
+
+                if !self.observers.is_empty() {
+                    for observer in &mut self.observers {
+                        observer.after_instruction(&opcode, &self.registers);
+                    }
+                }
             }
+            // Every opcode is decoded, a missing one would halt the CPU like JAM.
             None => {
-                panic!("instruction {:#06x}@{:#06x} does not exist", byte, self.registers.program_counter);
+                self.jammed = true;
             }
         };
     }
 
+    // Runs instructions until the next scheduled device event has been dispatched, or for the given
+    // number of cycles if nothing is due sooner, and returns the cycles run. Events are dispatched by the
+    // bus at the end of the instruction reaching them, so the CPU may overshoot by part of an instruction.
+    pub fn run_until_event(&mut self, bus: &mut Bus, limit: u64) -> u64 {
+        let start = bus.cycles();
+        let target = match bus.next_event() {
+            Some(due) => due.min(start + limit),
+            None => start + limit,
+        };
+        while bus.cycles() < target {
+            self.tick(bus);
+        }
+        return bus.cycles() - start;
+    }
+
     pub fn new() -> CPU6502 {
         let mut cpu: CPU6502 = CPU6502 {
             registers: Registers::new(),
-            instructions: [None; 0xFF],
+            instructions: [None; 0x100],
+            cycles: 0,
+            trace: None,
+            observers: Vec::new(),
+            pending: None,
+            traps: HashMap::new(),
+            jammed: false,
         };
 
         // Add With Carry (ADC)
@@ -266,7 +1102,8 @@ impl CPU6502 {
                 instruction : Instruction::ADC,
                 mode: IAM::Immediate,
                 bytes: 2,
-                cycles: 2
+                cycles: 2,
+                undocumented: false,
             });
 
             // ADC ZeroPage
@@ -274,7 +1111,8 @@ impl CPU6502 {
                 instruction : Instruction::ADC,
                 mode: IAM::ZeroPage(IAMSubMode::N),
                 bytes: 2,
-                cycles: 3
+                cycles: 3,
+                undocumented: false,
             });
 
             // ADC ZeroPageX
@@ -282,7 +1120,8 @@ impl CPU6502 {
                 instruction : Instruction::ADC,
                 mode: IAM::ZeroPage(IAMSubMode::X),
                 bytes: 2,
-                cycles: 4
+                cycles: 4,
+                undocumented: false,
             });
 
             // ADC Absolute
@@ -290,7 +1129,8 @@ impl CPU6502 {
                 instruction : Instruction::ADC,
                 mode: IAM::Absolute(IAMSubMode::N),
                 bytes: 3,
-                cycles: 4
+                cycles: 4,
+                undocumented: false,
             });
 
             // ADC AbsoluteX
@@ -298,7 +1138,8 @@ impl CPU6502 {
                 instruction : Instruction::ADC,
                 mode: IAM::Absolute(IAMSubMode::X),
                 bytes: 3,
-                cycles: 4
+                cycles: 4,
+                undocumented: false,
             });
 
             // ADC AbsoluteY
@@ -306,7 +1147,8 @@ impl CPU6502 {
                 instruction : Instruction::ADC,
                 mode: IAM::Absolute(IAMSubMode::Y),
                 bytes: 3,
-                cycles: 4
+                cycles: 4,
+                undocumented: false,
             });
 
             // ADC IndirectX
@@ -314,7 +1156,8 @@ impl CPU6502 {
                 instruction : Instruction::ADC,
                 mode: IAM::Indirect(IAMSubMode::X),
                 bytes: 2,
-                cycles: 6
+                cycles: 6,
+                undocumented: false,
             });
 
             // ADC AbsoluteY
@@ -322,7 +1165,8 @@ impl CPU6502 {
                 instruction : Instruction::ADC,
                 mode: IAM::Indirect(IAMSubMode::Y),
                 bytes: 2,
-                cycles: 5
+                cycles: 5,
+                undocumented: false,
             });
         }
 
@@ -333,6 +1177,7 @@ impl CPU6502 {
                 mode: IAM::Immediate,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
 
             cpu.instructions[0x25] = Some(OperationCode {
@@ -340,6 +1185,7 @@ impl CPU6502 {
                 mode: IAM::ZeroPage(IAMSubMode::N),
                 bytes: 2,
                 cycles: 3,
+                undocumented: false,
             });
 
             cpu.instructions[0x35] = Some(OperationCode {
@@ -347,6 +1193,7 @@ impl CPU6502 {
                 mode: IAM::ZeroPage(IAMSubMode::X),
                 bytes: 2,
                 cycles: 4,
+                undocumented: false,
             });
 
             cpu.instructions[0x2D] = Some(OperationCode {
@@ -354,6 +1201,7 @@ impl CPU6502 {
                 mode: IAM::Absolute(IAMSubMode::N),
                 bytes: 3,
                 cycles: 4,
+                undocumented: false,
             });
 
             cpu.instructions[0x3D] = Some(OperationCode {
@@ -361,6 +1209,7 @@ impl CPU6502 {
                 mode: IAM::Absolute(IAMSubMode::X),
                 bytes: 3,
                 cycles: 4,
+                undocumented: false,
             });
 
             cpu.instructions[0x39] = Some(OperationCode {
@@ -368,6 +1217,7 @@ impl CPU6502 {
                 mode: IAM::Absolute(IAMSubMode::Y),
                 bytes: 3,
                 cycles: 4,
+                undocumented: false,
             });
 
             cpu.instructions[0x21] = Some(OperationCode {
@@ -375,6 +1225,7 @@ impl CPU6502 {
                 mode: IAM::Indirect(IAMSubMode::X),
                 bytes: 2,
                 cycles: 6,
+                undocumented: false,
             });
 
             cpu.instructions[0x31] = Some(OperationCode {
@@ -382,6 +1233,7 @@ impl CPU6502 {
                 mode: IAM::Indirect(IAMSubMode::Y),
                 bytes: 2,
                 cycles: 5,
+                undocumented: false,
             });
         }
 
@@ -392,6 +1244,7 @@ impl CPU6502 {
                 mode: IAM::Accumulator,
                 bytes: 1,
                 cycles: 2,
+                undocumented: false,
             });
 
             cpu.instructions[0x06] = Some(OperationCode {
@@ -399,6 +1252,7 @@ impl CPU6502 {
                 mode: IAM::ZeroPage(IAMSubMode::N),
                 bytes: 2,
                 cycles: 5,
+                undocumented: false,
             });
 
             cpu.instructions[0x16] = Some(OperationCode {
@@ -406,6 +1260,7 @@ impl CPU6502 {
                 mode: IAM::ZeroPage(IAMSubMode::X),
                 bytes: 2,
                 cycles: 6,
+                undocumented: false,
             });
 
             cpu.instructions[0x0E] = Some(OperationCode {
@@ -413,6 +1268,7 @@ impl CPU6502 {
                 mode: IAM::Absolute(IAMSubMode::N),
                 bytes: 3,
                 cycles: 6,
+                undocumented: false,
             });
 
             cpu.instructions[0x1E] = Some(OperationCode {
@@ -420,6 +1276,7 @@ impl CPU6502 {
                 mode: IAM::Absolute(IAMSubMode::X),
                 bytes: 3,
                 cycles: 7,
+                undocumented: false,
             });
         }
 
@@ -430,6 +1287,7 @@ impl CPU6502 {
                 mode: IAM::Relative,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -440,6 +1298,7 @@ impl CPU6502 {
                 mode: IAM::Relative,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -450,6 +1309,7 @@ impl CPU6502 {
                 mode: IAM::Relative,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -460,13 +1320,15 @@ impl CPU6502 {
                 mode: IAM::ZeroPage(IAMSubMode::N),
                 bytes: 2,
                 cycles: 3,
+                undocumented: false,
             });
 
             cpu.instructions[0x2C] = Some(OperationCode {
                 instruction : Instruction::BIT,
-                mode: IAM::Absolute(IAMSubMode::Y),
+                mode: IAM::Absolute(IAMSubMode::N),
                 bytes: 3,
                 cycles: 4,
+                undocumented: false,
             });
         }
 
@@ -477,6 +1339,7 @@ impl CPU6502 {
                 mode: IAM::Relative,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -487,6 +1350,7 @@ impl CPU6502 {
                 mode: IAM::Relative,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -497,6 +1361,7 @@ impl CPU6502 {
                 mode: IAM::Relative,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -507,6 +1372,7 @@ impl CPU6502 {
                 mode: IAM::Implied,
                 bytes: 1,
                 cycles: 7,
+                undocumented: false,
             });
         }
 
@@ -517,6 +1383,7 @@ impl CPU6502 {
                 mode: IAM::Relative,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -527,6 +1394,7 @@ impl CPU6502 {
                 mode: IAM::Relative,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -537,6 +1405,7 @@ impl CPU6502 {
                 mode: IAM::Implied,
                 bytes: 1,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -547,6 +1416,7 @@ impl CPU6502 {
                 mode: IAM::Implied,
                 bytes: 1,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -557,6 +1427,7 @@ impl CPU6502 {
                 mode: IAM::Implied,
                 bytes: 1,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -567,6 +1438,7 @@ impl CPU6502 {
                 mode: IAM::Implied,
                 bytes: 1,
                 cycles: 2,
+                undocumented: false,
             });
         }
 
@@ -577,6 +1449,7 @@ impl CPU6502 {
                 mode: IAM::Immediate,
                 bytes: 2,
                 cycles: 2,
+                undocumented: false,
             });
 
             cpu.instructions[0xC5] = Some(OperationCode {
@@ -584,6 +1457,7 @@ impl CPU6502 {
                 mode: IAM::ZeroPage(IAMSubMode::N),
                 bytes: 2,
                 cycles: 3,
+                undocumented: false,
             });
 
             cpu.instructions[0xD5] = Some(OperationCode {
@@ -591,6 +1465,7 @@ impl CPU6502 {
                 mode: IAM::ZeroPage(IAMSubMode::X),
                 bytes: 2,
                 cycles: 4,
+                undocumented: false,
             });
 
             cpu.instructions[0xCD] = Some(OperationCode {
@@ -598,6 +1473,7 @@ impl CPU6502 {
                 mode: IAM::Absolute(IAMSubMode::N),
                 bytes: 3,
                 cycles: 4,
+                undocumented: false,
             });
 
             cpu.instructions[0xDD] = Some(OperationCode {
@@ -605,6 +1481,7 @@ impl CPU6502 {
                 mode: IAM::Absolute(IAMSubMode::X),
                 bytes: 3,
                 cycles: 4,
+                undocumented: false,
             });
 
             cpu.instructions[0xD9] = Some(OperationCode {
@@ -612,6 +1489,7 @@ impl CPU6502 {
                 mode: IAM::Absolute(IAMSubMode::Y),
                 bytes: 3,
                 cycles: 4,
+                undocumented: false,
             });
 
             cpu.instructions[0xC1] = Some(OperationCode {
@@ -619,6 +1497,7 @@ impl CPU6502 {
                 mode: IAM::Indirect(IAMSubMode::X),
                 bytes: 2,
                 cycles: 6,
+                undocumented: false,
             });
 
             cpu.instructions[0xD1] = Some(OperationCode {
@@ -626,9 +1505,2399 @@ impl CPU6502 {
                 mode: IAM::Indirect(IAMSubMode::Y),
                 bytes: 2,
                 cycles: 5,
+                undocumented: false,
             });
         }
 
-        return cpu;
+        // Compare X Register (CPX)
+        {
+            cpu.instructions[0xE0] = Some(OperationCode {
+                instruction : Instruction::CPX,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xE4] = Some(OperationCode {
+                instruction : Instruction::CPX,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xEC] = Some(OperationCode {
+                instruction : Instruction::CPX,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+        }
+
+        // Compare Y Register (CPY)
+        {
+            cpu.instructions[0xC0] = Some(OperationCode {
+                instruction : Instruction::CPY,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xC4] = Some(OperationCode {
+                instruction : Instruction::CPY,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xCC] = Some(OperationCode {
+                instruction : Instruction::CPY,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+        }
+
+        // Decrement Memory (DEC)
+        {
+            cpu.instructions[0xC6] = Some(OperationCode {
+                instruction : Instruction::DEC,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xD6] = Some(OperationCode {
+                instruction : Instruction::DEC,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xCE] = Some(OperationCode {
+                instruction : Instruction::DEC,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xDE] = Some(OperationCode {
+                instruction : Instruction::DEC,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: false,
+            });
+        }
+
+        // Decrement X Register (DEX)
+        {
+            cpu.instructions[0xCA] = Some(OperationCode {
+                instruction : Instruction::DEX,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Decrement Y Register (DEY)
+        {
+            cpu.instructions[0x88] = Some(OperationCode {
+                instruction : Instruction::DEY,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Exclusive OR (EOR)
+        {
+            cpu.instructions[0x49] = Some(OperationCode {
+                instruction : Instruction::EOR,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x45] = Some(OperationCode {
+                instruction : Instruction::EOR,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x55] = Some(OperationCode {
+                instruction : Instruction::EOR,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x4D] = Some(OperationCode {
+                instruction : Instruction::EOR,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x5D] = Some(OperationCode {
+                instruction : Instruction::EOR,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x59] = Some(OperationCode {
+                instruction : Instruction::EOR,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x41] = Some(OperationCode {
+                instruction : Instruction::EOR,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x51] = Some(OperationCode {
+                instruction : Instruction::EOR,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+        }
+
+        // Increment Memory (INC)
+        {
+            cpu.instructions[0xE6] = Some(OperationCode {
+                instruction : Instruction::INC,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xF6] = Some(OperationCode {
+                instruction : Instruction::INC,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xEE] = Some(OperationCode {
+                instruction : Instruction::INC,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xFE] = Some(OperationCode {
+                instruction : Instruction::INC,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: false,
+            });
+        }
+
+        // Increment X Register (INX)
+        {
+            cpu.instructions[0xE8] = Some(OperationCode {
+                instruction : Instruction::INX,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Increment Y Register (INY)
+        {
+            cpu.instructions[0xC8] = Some(OperationCode {
+                instruction : Instruction::INY,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Jump (JMP)
+        {
+            cpu.instructions[0x4C] = Some(OperationCode {
+                instruction : Instruction::JMP,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x6C] = Some(OperationCode {
+                instruction : Instruction::JMP,
+                mode: IAM::Indirect(IAMSubMode::N),
+                bytes: 3,
+                cycles: 5,
+                undocumented: false,
+            });
+        }
+
+        // Jump to Subroutine (JSR)
+        {
+            cpu.instructions[0x20] = Some(OperationCode {
+                instruction : Instruction::JSR,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: false,
+            });
+        }
+
+        // Load Accumulator (LDA)
+        {
+            cpu.instructions[0xA9] = Some(OperationCode {
+                instruction : Instruction::LDA,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xA5] = Some(OperationCode {
+                instruction : Instruction::LDA,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xB5] = Some(OperationCode {
+                instruction : Instruction::LDA,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xAD] = Some(OperationCode {
+                instruction : Instruction::LDA,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xBD] = Some(OperationCode {
+                instruction : Instruction::LDA,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xB9] = Some(OperationCode {
+                instruction : Instruction::LDA,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xA1] = Some(OperationCode {
+                instruction : Instruction::LDA,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xB1] = Some(OperationCode {
+                instruction : Instruction::LDA,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+        }
+
+        // Load X Register (LDX)
+        {
+            cpu.instructions[0xA2] = Some(OperationCode {
+                instruction : Instruction::LDX,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xA6] = Some(OperationCode {
+                instruction : Instruction::LDX,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xB6] = Some(OperationCode {
+                instruction : Instruction::LDX,
+                mode: IAM::ZeroPage(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xAE] = Some(OperationCode {
+                instruction : Instruction::LDX,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xBE] = Some(OperationCode {
+                instruction : Instruction::LDX,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+        }
+
+        // Load Y Register (LDY)
+        {
+            cpu.instructions[0xA0] = Some(OperationCode {
+                instruction : Instruction::LDY,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xA4] = Some(OperationCode {
+                instruction : Instruction::LDY,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xB4] = Some(OperationCode {
+                instruction : Instruction::LDY,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xAC] = Some(OperationCode {
+                instruction : Instruction::LDY,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xBC] = Some(OperationCode {
+                instruction : Instruction::LDY,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+        }
+
+        // Logical Shift Right (LSR)
+        {
+            cpu.instructions[0x4A] = Some(OperationCode {
+                instruction : Instruction::LSR,
+                mode: IAM::Accumulator,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x46] = Some(OperationCode {
+                instruction : Instruction::LSR,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x56] = Some(OperationCode {
+                instruction : Instruction::LSR,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x4E] = Some(OperationCode {
+                instruction : Instruction::LSR,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x5E] = Some(OperationCode {
+                instruction : Instruction::LSR,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: false,
+            });
+        }
+
+        // No Operation (NOP)
+        {
+            cpu.instructions[0xEA] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Logical Inclusive OR (ORA)
+        {
+            cpu.instructions[0x09] = Some(OperationCode {
+                instruction : Instruction::ORA,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x05] = Some(OperationCode {
+                instruction : Instruction::ORA,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x15] = Some(OperationCode {
+                instruction : Instruction::ORA,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x0D] = Some(OperationCode {
+                instruction : Instruction::ORA,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x1D] = Some(OperationCode {
+                instruction : Instruction::ORA,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x19] = Some(OperationCode {
+                instruction : Instruction::ORA,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x01] = Some(OperationCode {
+                instruction : Instruction::ORA,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x11] = Some(OperationCode {
+                instruction : Instruction::ORA,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+        }
+
+        // Push Accumulator (PHA)
+        {
+            cpu.instructions[0x48] = Some(OperationCode {
+                instruction : Instruction::PHA,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 3,
+                undocumented: false,
+            });
+        }
+
+        // Push Processor Status (PHP)
+        {
+            cpu.instructions[0x08] = Some(OperationCode {
+                instruction : Instruction::PHP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 3,
+                undocumented: false,
+            });
+        }
+
+        // Pull Accumulator (PLA)
+        {
+            cpu.instructions[0x68] = Some(OperationCode {
+                instruction : Instruction::PLA,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 4,
+                undocumented: false,
+            });
+        }
+
+        // Pull Processor Status (PLP)
+        {
+            cpu.instructions[0x28] = Some(OperationCode {
+                instruction : Instruction::PLP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 4,
+                undocumented: false,
+            });
+        }
+
+        // Rotate Left (ROL)
+        {
+            cpu.instructions[0x2A] = Some(OperationCode {
+                instruction : Instruction::ROL,
+                mode: IAM::Accumulator,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x26] = Some(OperationCode {
+                instruction : Instruction::ROL,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x36] = Some(OperationCode {
+                instruction : Instruction::ROL,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x2E] = Some(OperationCode {
+                instruction : Instruction::ROL,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x3E] = Some(OperationCode {
+                instruction : Instruction::ROL,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: false,
+            });
+        }
+
+        // Rotate Right (ROR)
+        {
+            cpu.instructions[0x6A] = Some(OperationCode {
+                instruction : Instruction::ROR,
+                mode: IAM::Accumulator,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x66] = Some(OperationCode {
+                instruction : Instruction::ROR,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x76] = Some(OperationCode {
+                instruction : Instruction::ROR,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x6E] = Some(OperationCode {
+                instruction : Instruction::ROR,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x7E] = Some(OperationCode {
+                instruction : Instruction::ROR,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: false,
+            });
+        }
+
+        // Return from Interrupt (RTI)
+        {
+            cpu.instructions[0x40] = Some(OperationCode {
+                instruction : Instruction::RTI,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 6,
+                undocumented: false,
+            });
+        }
+
+        // Return from Subroutine (RTS)
+        {
+            cpu.instructions[0x60] = Some(OperationCode {
+                instruction : Instruction::RTS,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 6,
+                undocumented: false,
+            });
+        }
+
+        // Subtract with Carry (SBC)
+        {
+            cpu.instructions[0xE9] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xE5] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xF5] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xED] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xFD] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xF9] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xE1] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0xF1] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 5,
+                undocumented: false,
+            });
+        }
+
+        // Set Carry Flag (SEC)
+        {
+            cpu.instructions[0x38] = Some(OperationCode {
+                instruction : Instruction::SEC,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Set Decimal Flag (SED)
+        {
+            cpu.instructions[0xF8] = Some(OperationCode {
+                instruction : Instruction::SED,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Set Interrupt Disable (SEI)
+        {
+            cpu.instructions[0x78] = Some(OperationCode {
+                instruction : Instruction::SEI,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Store Accumulator (STA)
+        {
+            cpu.instructions[0x85] = Some(OperationCode {
+                instruction : Instruction::STA,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x95] = Some(OperationCode {
+                instruction : Instruction::STA,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x8D] = Some(OperationCode {
+                instruction : Instruction::STA,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x9D] = Some(OperationCode {
+                instruction : Instruction::STA,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 5,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x99] = Some(OperationCode {
+                instruction : Instruction::STA,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 5,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x81] = Some(OperationCode {
+                instruction : Instruction::STA,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x91] = Some(OperationCode {
+                instruction : Instruction::STA,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 6,
+                undocumented: false,
+            });
+        }
+
+        // Store X Register (STX)
+        {
+            cpu.instructions[0x86] = Some(OperationCode {
+                instruction : Instruction::STX,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x96] = Some(OperationCode {
+                instruction : Instruction::STX,
+                mode: IAM::ZeroPage(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x8E] = Some(OperationCode {
+                instruction : Instruction::STX,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+        }
+
+        // Store Y Register (STY)
+        {
+            cpu.instructions[0x84] = Some(OperationCode {
+                instruction : Instruction::STY,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x94] = Some(OperationCode {
+                instruction : Instruction::STY,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: false,
+            });
+
+            cpu.instructions[0x8C] = Some(OperationCode {
+                instruction : Instruction::STY,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: false,
+            });
+        }
+
+        // Transfer Accumulator to X (TAX)
+        {
+            cpu.instructions[0xAA] = Some(OperationCode {
+                instruction : Instruction::TAX,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Transfer Accumulator to Y (TAY)
+        {
+            cpu.instructions[0xA8] = Some(OperationCode {
+                instruction : Instruction::TAY,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Transfer Stack Pointer to X (TSX)
+        {
+            cpu.instructions[0xBA] = Some(OperationCode {
+                instruction : Instruction::TSX,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Transfer X to Accumulator (TXA)
+        {
+            cpu.instructions[0x8A] = Some(OperationCode {
+                instruction : Instruction::TXA,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Transfer X to Stack Pointer (TXS)
+        {
+            cpu.instructions[0x9A] = Some(OperationCode {
+                instruction : Instruction::TXS,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Transfer Y to Accumulator (TYA)
+        {
+            cpu.instructions[0x98] = Some(OperationCode {
+                instruction : Instruction::TYA,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: false,
+            });
+        }
+
+        // Undocumented opcodes of the NMOS 6502, with the names nestest.log uses where it has them.
+        // Shift Left then OR (SLO)
+        {
+            cpu.instructions[0x07] = Some(OperationCode {
+                instruction : Instruction::SLO,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x17] = Some(OperationCode {
+                instruction : Instruction::SLO,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x0F] = Some(OperationCode {
+                instruction : Instruction::SLO,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x1F] = Some(OperationCode {
+                instruction : Instruction::SLO,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x1B] = Some(OperationCode {
+                instruction : Instruction::SLO,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x03] = Some(OperationCode {
+                instruction : Instruction::SLO,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x13] = Some(OperationCode {
+                instruction : Instruction::SLO,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+        }
+
+        // Rotate Left then AND (RLA)
+        {
+            cpu.instructions[0x27] = Some(OperationCode {
+                instruction : Instruction::RLA,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x37] = Some(OperationCode {
+                instruction : Instruction::RLA,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x2F] = Some(OperationCode {
+                instruction : Instruction::RLA,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x3F] = Some(OperationCode {
+                instruction : Instruction::RLA,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x3B] = Some(OperationCode {
+                instruction : Instruction::RLA,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x23] = Some(OperationCode {
+                instruction : Instruction::RLA,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x33] = Some(OperationCode {
+                instruction : Instruction::RLA,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+        }
+
+        // Shift Right then Exclusive OR (SRE)
+        {
+            cpu.instructions[0x47] = Some(OperationCode {
+                instruction : Instruction::SRE,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x57] = Some(OperationCode {
+                instruction : Instruction::SRE,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x4F] = Some(OperationCode {
+                instruction : Instruction::SRE,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x5F] = Some(OperationCode {
+                instruction : Instruction::SRE,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x5B] = Some(OperationCode {
+                instruction : Instruction::SRE,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x43] = Some(OperationCode {
+                instruction : Instruction::SRE,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x53] = Some(OperationCode {
+                instruction : Instruction::SRE,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+        }
+
+        // Rotate Right then Add with Carry (RRA)
+        {
+            cpu.instructions[0x67] = Some(OperationCode {
+                instruction : Instruction::RRA,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x77] = Some(OperationCode {
+                instruction : Instruction::RRA,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x6F] = Some(OperationCode {
+                instruction : Instruction::RRA,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x7F] = Some(OperationCode {
+                instruction : Instruction::RRA,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x7B] = Some(OperationCode {
+                instruction : Instruction::RRA,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x63] = Some(OperationCode {
+                instruction : Instruction::RRA,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x73] = Some(OperationCode {
+                instruction : Instruction::RRA,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+        }
+
+        // Store Accumulator AND X (SAX)
+        {
+            cpu.instructions[0x87] = Some(OperationCode {
+                instruction : Instruction::SAX,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x97] = Some(OperationCode {
+                instruction : Instruction::SAX,
+                mode: IAM::ZeroPage(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x8F] = Some(OperationCode {
+                instruction : Instruction::SAX,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x83] = Some(OperationCode {
+                instruction : Instruction::SAX,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+        }
+
+        // Load Accumulator and X (LAX)
+        {
+            cpu.instructions[0xA7] = Some(OperationCode {
+                instruction : Instruction::LAX,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xB7] = Some(OperationCode {
+                instruction : Instruction::LAX,
+                mode: IAM::ZeroPage(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xAF] = Some(OperationCode {
+                instruction : Instruction::LAX,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xBF] = Some(OperationCode {
+                instruction : Instruction::LAX,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xA3] = Some(OperationCode {
+                instruction : Instruction::LAX,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xB3] = Some(OperationCode {
+                instruction : Instruction::LAX,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 5,
+                undocumented: true,
+            });
+        }
+
+        // Decrement then Compare (DCP)
+        {
+            cpu.instructions[0xC7] = Some(OperationCode {
+                instruction : Instruction::DCP,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xD7] = Some(OperationCode {
+                instruction : Instruction::DCP,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xCF] = Some(OperationCode {
+                instruction : Instruction::DCP,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xDF] = Some(OperationCode {
+                instruction : Instruction::DCP,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xDB] = Some(OperationCode {
+                instruction : Instruction::DCP,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xC3] = Some(OperationCode {
+                instruction : Instruction::DCP,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xD3] = Some(OperationCode {
+                instruction : Instruction::DCP,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+        }
+
+        // Increment then Subtract with Carry (ISB)
+        {
+            cpu.instructions[0xE7] = Some(OperationCode {
+                instruction : Instruction::ISB,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 5,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xF7] = Some(OperationCode {
+                instruction : Instruction::ISB,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xEF] = Some(OperationCode {
+                instruction : Instruction::ISB,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 6,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xFF] = Some(OperationCode {
+                instruction : Instruction::ISB,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xFB] = Some(OperationCode {
+                instruction : Instruction::ISB,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 7,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xE3] = Some(OperationCode {
+                instruction : Instruction::ISB,
+                mode: IAM::Indirect(IAMSubMode::X),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xF3] = Some(OperationCode {
+                instruction : Instruction::ISB,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 8,
+                undocumented: true,
+            });
+        }
+
+        // AND then Copy Negative to Carry (ANC)
+        {
+            cpu.instructions[0x0B] = Some(OperationCode {
+                instruction : Instruction::ANC,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x2B] = Some(OperationCode {
+                instruction : Instruction::ANC,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+        }
+
+        // AND then Shift Right (ALR)
+        {
+            cpu.instructions[0x4B] = Some(OperationCode {
+                instruction : Instruction::ALR,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+        }
+
+        // AND then Rotate Right (ARR)
+        {
+            cpu.instructions[0x6B] = Some(OperationCode {
+                instruction : Instruction::ARR,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+        }
+
+        // Transfer X to Accumulator then AND (ANE)
+        {
+            cpu.instructions[0x8B] = Some(OperationCode {
+                instruction : Instruction::ANE,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+        }
+
+        // Load Accumulator and X with AND (LXA)
+        {
+            cpu.instructions[0xAB] = Some(OperationCode {
+                instruction : Instruction::LXA,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+        }
+
+        // Subtract from Accumulator AND X (SBX)
+        {
+            cpu.instructions[0xCB] = Some(OperationCode {
+                instruction : Instruction::SBX,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+        }
+
+        // Store Accumulator AND X AND High Byte (SHA)
+        {
+            cpu.instructions[0x9F] = Some(OperationCode {
+                instruction : Instruction::SHA,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 5,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x93] = Some(OperationCode {
+                instruction : Instruction::SHA,
+                mode: IAM::Indirect(IAMSubMode::Y),
+                bytes: 2,
+                cycles: 6,
+                undocumented: true,
+            });
+        }
+
+        // Store Y AND High Byte (SHY)
+        {
+            cpu.instructions[0x9C] = Some(OperationCode {
+                instruction : Instruction::SHY,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 5,
+                undocumented: true,
+            });
+        }
+
+        // Store X AND High Byte (SHX)
+        {
+            cpu.instructions[0x9E] = Some(OperationCode {
+                instruction : Instruction::SHX,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 5,
+                undocumented: true,
+            });
+        }
+
+        // Transfer Accumulator AND X to Stack Pointer (TAS)
+        {
+            cpu.instructions[0x9B] = Some(OperationCode {
+                instruction : Instruction::TAS,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 5,
+                undocumented: true,
+            });
+        }
+
+        // Load Accumulator, X and Stack Pointer (LAS)
+        {
+            cpu.instructions[0xBB] = Some(OperationCode {
+                instruction : Instruction::LAS,
+                mode: IAM::Absolute(IAMSubMode::Y),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+        }
+
+        // No Operation (NOP), undocumented forms which read their operand
+        {
+            cpu.instructions[0x1A] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x3A] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x5A] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x7A] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xDA] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xFA] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x80] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x82] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x89] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xC2] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xE2] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x04] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x44] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x64] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::N),
+                bytes: 2,
+                cycles: 3,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x14] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x34] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x54] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x74] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xD4] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xF4] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::ZeroPage(IAMSubMode::X),
+                bytes: 2,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x0C] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Absolute(IAMSubMode::N),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x1C] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x3C] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x5C] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x7C] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xDC] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xFC] = Some(OperationCode {
+                instruction : Instruction::NOP,
+                mode: IAM::Absolute(IAMSubMode::X),
+                bytes: 3,
+                cycles: 4,
+                undocumented: true,
+            });
+        }
+
+        // Subtract with Carry (SBC), undocumented copy of the immediate form
+        {
+            cpu.instructions[0xEB] = Some(OperationCode {
+                instruction : Instruction::SBC,
+                mode: IAM::Immediate,
+                bytes: 2,
+                cycles: 2,
+                undocumented: true,
+            });
+        }
+
+        // Halt (JAM)
+        {
+            cpu.instructions[0x02] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x12] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x22] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x32] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x42] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x52] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x62] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x72] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0x92] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xB2] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xD2] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+
+            cpu.instructions[0xF2] = Some(OperationCode {
+                instruction : Instruction::JAM,
+                mode: IAM::Implied,
+                bytes: 1,
+                cycles: 2,
+                undocumented: true,
+            });
+        }
+
+        return cpu;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::BusBuilder;
+    use crate::components::memory::RandomAccessMemory;
+    use crate::components::testing::{load, Capture};
+
+    fn setup() -> (CPU6502, Bus) {
+        let cpu : CPU6502 = CPU6502::new();
+        let memory : RandomAccessMemory = RandomAccessMemory::new(0x0000, 0x8000).unwrap();
+        let bus : Bus = Bus::new(memory);
+
+        return (cpu, bus);
+    }
+
+    // Runs the instruction at $0200 on 64K of RAM holding the given bytes, returning the CPU, the bus
+    // and the cycles it took.
+    fn single(program: &[u8], memory: &[(u16, u8)], setup: impl FnOnce(&mut Registers)) -> (CPU6502, Bus, u64) {
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap()))
+            .build()
+            .unwrap();
+        load(&mut bus, 0x0200, program);
+        for (address, data) in memory {
+            bus.write(*address, *data);
+        }
+        let mut cpu = CPU6502::new();
+        let registers = cpu.registers_mut();
+        registers.program_counter = 0x0200;
+        registers.stack_pointer = 0xFF;
+        registers.status_flags = 0x20;
+        setup(registers);
+        cpu.tick(&mut bus);
+        let cycles = cpu.cycles();
+        return (cpu, bus, cycles);
+    }
+
+    #[test]
+    fn test_absolute_addressing() {
+        const ADDRESS: u16 = 0x0100;
+
+        let (mut cpu, mut bus) = setup();
+        bus.write(ADDRESS, 0b00000010);
+        bus.write(0x0000, 0x0E); // ASL Absolute
+        bus.write(0x0001, ADDRESS.to_le_bytes()[0]);
+        bus.write(0x0002, ADDRESS.to_le_bytes()[1]);
+        cpu.tick(&mut bus);
+
+        println!("{}", bus.read(ADDRESS));
+        assert_eq!(0b00000100, bus.read(ADDRESS));
+    }
+
+    #[test]
+    fn test_adc() {
+        let (mut cpu, mut bus) = setup();
+        bus.write(0x0000, 0x69); // ADC Immediate Mode
+        bus.write(0x0001, 0x01); // Value '0x01'
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().accumulator, 0x01);
+
+        // $7F + $01 overflows into the sign bit.
+        let (mut cpu, mut bus) = setup();
+        cpu.registers_mut().accumulator = 0x7F;
+        bus.write(0x0000, 0x69);
+        bus.write(0x0001, 0x01);
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().accumulator, 0x80);
+        assert_eq!(cpu.registers().status_flags, 0xC0); // Negative, Overflow
+
+        // Decimal mode: 58 + 46 + 1 = 105, 12 - 21 = 91 with a borrow.
+        let (mut cpu, mut bus) = setup();
+        load(&mut bus, 0x0000, &[0xF8, 0x38, 0xA9, 0x58, 0x69, 0x46, 0x85, 0x80, 0x38, 0xA9, 0x12, 0xE9, 0x21]);
+        for _ in 0..5 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!(bus.read(0x0080), 0x05);
+        assert!(cpu.registers_mut().get_flag(Flags::Carry));
+        for _ in 0..3 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!(cpu.registers().accumulator, 0x91);
+        assert!(!cpu.registers_mut().get_flag(Flags::Carry));
+    }
+
+    #[test]
+    fn test_instructions() {
+        let (mut cpu, mut bus) = setup();
+        let program = [
+            0xA2, 0x03,       // $0000 LDX #$03
+            0xCA,             // $0002 DEX
+            0xD0, 0xFD,       // $0003 BNE $0002
+            0x20, 0x10, 0x00, // $0005 JSR $0010
+            0x4C, 0x08, 0x00, // $0008 JMP $0008
+        ];
+        let subroutine = [
+            0xA9, 0xC0,       // $0010 LDA #$C0
+            0x8D, 0x00, 0x02, // $0012 STA $0200
+            0x2C, 0x00, 0x02, // $0015 BIT $0200
+            0xC9, 0xC0,       // $0018 CMP #$C0
+            0x08,             // $001A PHP
+            0x68,             // $001B PLA
+            0x60,             // $001C RTS
+        ];
+        load(&mut bus, 0x0000, &program);
+        load(&mut bus, 0x0010, &subroutine);
+        cpu.registers_mut().stack_pointer = 0xFF;
+
+        // The loop runs three times, the branch back is taken twice.
+        for _ in 0..7 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!(cpu.registers().idx_x, 0x00);
+        assert_eq!(cpu.registers().program_counter, 0x0005);
+        assert_eq!(cpu.cycles(), 2 + 3 * 2 + 2 * 3 + 2);
+
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x0010);
+        assert_eq!(bus.read(0x01FF), 0x00);
+        assert_eq!(bus.read(0x01FE), 0x07);
+
+        // BIT copies bit 6 to Overflow, CMP of equal values sets Zero and Carry and clears Negative, PHP
+        // pushes B and bit 5 set.
+        for _ in 0..6 {
+            cpu.tick(&mut bus);
+        }
+        assert_eq!(bus.read(0x0200), 0xC0);
+        assert_eq!(cpu.registers().accumulator, 0x73);
+
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x0008);
+        assert_eq!(cpu.registers().stack_pointer, 0xFF);
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x0008);
+    }
+
+    #[test]
+    fn test_official() {
+        // Loads in every addressing mode, with the page crossing cycle and the zero page wrapping around.
+        let (cpu, _, cycles) = single(&[0xA9, 0x80], &[], |_| {}); // LDA #$80
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags, cycles), (0x80, 0xA0, 2));
+        let (cpu, _, cycles) = single(&[0xA5, 0x10], &[], |r| r.accumulator = 0x01); // LDA $10
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags, cycles), (0x00, 0x22, 3));
+        let (cpu, _, cycles) = single(&[0xB5, 0xFF], &[(0x0001, 0x42)], |r| r.idx_x = 0x02); // LDA $FF,X
+        assert_eq!((cpu.registers().accumulator, cycles), (0x42, 4));
+        let (cpu, _, cycles) = single(&[0xAD, 0x00, 0x03], &[(0x0300, 0x42)], |_| {}); // LDA $0300
+        assert_eq!((cpu.registers().accumulator, cycles), (0x42, 4));
+        let (cpu, _, cycles) = single(&[0xBD, 0xFF, 0x02], &[(0x0300, 0x42)], |r| r.idx_x = 0x01); // LDA $02FF,X
+        assert_eq!((cpu.registers().accumulator, cycles), (0x42, 5));
+        let (cpu, _, cycles) = single(&[0xB9, 0x00, 0x03], &[(0x0301, 0x42)], |r| r.idx_y = 0x01); // LDA $0300,Y
+        assert_eq!((cpu.registers().accumulator, cycles), (0x42, 4));
+        let (cpu, _, cycles) = single(&[0xA1, 0x10], &[(0x0012, 0x00), (0x0013, 0x03), (0x0300, 0x42)], |r| r.idx_x = 0x02); // LDA ($10,X)
+        assert_eq!((cpu.registers().accumulator, cycles), (0x42, 6));
+        let (cpu, _, cycles) = single(&[0xB1, 0x10], &[(0x0010, 0x00), (0x0011, 0x03), (0x0302, 0x42)], |r| r.idx_y = 0x02); // LDA ($10),Y
+        assert_eq!((cpu.registers().accumulator, cycles), (0x42, 5));
+        let (cpu, _, cycles) = single(&[0xB6, 0x10], &[(0x0011, 0x42)], |r| r.idx_y = 0x01); // LDX $10,Y
+        assert_eq!((cpu.registers().idx_x, cycles), (0x42, 4));
+        let (cpu, _, _) = single(&[0xA2, 0x00], &[], |r| r.idx_x = 0x01); // LDX #$00
+        assert_eq!((cpu.registers().idx_x, cpu.registers().status_flags), (0x00, 0x22));
+        let (cpu, _, cycles) = single(&[0xBC, 0x00, 0x03], &[(0x0301, 0xFF)], |r| r.idx_x = 0x01); // LDY $0300,X
+        assert_eq!((cpu.registers().idx_y, cpu.registers().status_flags, cycles), (0xFF, 0xA0, 4));
+
+        // Stores leave the flags alone and always take the indexed cycle.
+        let (cpu, bus, cycles) = single(&[0x8D, 0x00, 0x03], &[], |_| {}); // STA $0300
+        assert_eq!((bus.peek(0x0300), cpu.registers().status_flags, cycles), (0x00, 0x20, 4));
+        let (_, bus, cycles) = single(&[0x9D, 0x00, 0x03], &[], |r| { r.accumulator = 0x42; r.idx_x = 0x01 }); // STA $0300,X
+        assert_eq!((bus.peek(0x0301), cycles), (0x42, 5));
+        let (_, bus, cycles) = single(&[0x91, 0x10], &[(0x0010, 0x00), (0x0011, 0x03)], |r| { r.accumulator = 0x42; r.idx_y = 0x01 }); // STA ($10),Y
+        assert_eq!((bus.peek(0x0301), cycles), (0x42, 6));
+        let (_, bus, cycles) = single(&[0x96, 0x10], &[], |r| { r.idx_x = 0x42; r.idx_y = 0x01 }); // STX $10,Y
+        assert_eq!((bus.peek(0x0011), cycles), (0x42, 4));
+        let (_, bus, cycles) = single(&[0x8C, 0x00, 0x03], &[], |r| r.idx_y = 0x42); // STY $0300
+        assert_eq!((bus.peek(0x0300), cycles), (0x42, 4));
+
+        // Transfers set Negative and Zero, except TXS.
+        let (cpu, _, _) = single(&[0xAA], &[], |r| r.accumulator = 0x80); // TAX
+        assert_eq!((cpu.registers().idx_x, cpu.registers().status_flags), (0x80, 0xA0));
+        let (cpu, _, _) = single(&[0xA8], &[], |r| r.idx_y = 0x01); // TAY
+        assert_eq!((cpu.registers().idx_y, cpu.registers().status_flags), (0x00, 0x22));
+        let (cpu, _, _) = single(&[0x8A], &[], |r| r.idx_x = 0x42); // TXA
+        assert_eq!(cpu.registers().accumulator, 0x42);
+        let (cpu, _, _) = single(&[0x98], &[], |r| r.idx_y = 0x42); // TYA
+        assert_eq!(cpu.registers().accumulator, 0x42);
+        let (cpu, _, _) = single(&[0xBA], &[], |_| {}); // TSX
+        assert_eq!((cpu.registers().idx_x, cpu.registers().status_flags), (0xFF, 0xA0));
+        let (cpu, _, _) = single(&[0x9A], &[], |_| {}); // TXS
+        assert_eq!((cpu.registers().stack_pointer, cpu.registers().status_flags), (0x00, 0x20));
+
+        // The stack, where B and bit 5 only exist on the pushed copy of the flags.
+        let (cpu, bus, cycles) = single(&[0x48], &[], |r| r.accumulator = 0x42); // PHA
+        assert_eq!((bus.peek(0x01FF), cpu.registers().stack_pointer, cycles), (0x42, 0xFE, 3));
+        let (_, bus, cycles) = single(&[0x08], &[], |r| r.status_flags = 0x01); // PHP
+        assert_eq!((bus.peek(0x01FF), cycles), (0x31, 3));
+        let (cpu, _, cycles) = single(&[0x68], &[(0x01FF, 0x00)], |r| { r.accumulator = 0x01; r.stack_pointer = 0xFE }); // PLA
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags, cycles), (0x00, 0x22, 4));
+        let (cpu, _, cycles) = single(&[0x28], &[(0x01FF, 0xFF)], |r| r.stack_pointer = 0xFE); // PLP
+        assert_eq!((cpu.registers().status_flags, cpu.registers().stack_pointer, cycles), (0xEF, 0xFF, 4));
+
+        // Logic.
+        let (cpu, _, _) = single(&[0x29, 0x0F], &[], |r| r.accumulator = 0xF0); // AND #$0F
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x00, 0x22));
+        let (cpu, _, _) = single(&[0x09, 0x80], &[], |r| r.accumulator = 0x01); // ORA #$80
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x81, 0xA0));
+        let (cpu, _, _) = single(&[0x49, 0xFF], &[], |r| r.accumulator = 0xFF); // EOR #$FF
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x00, 0x22));
+        let (cpu, _, cycles) = single(&[0x24, 0x10], &[(0x0010, 0xC0)], |r| r.accumulator = 0x3F); // BIT $10
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags, cycles), (0x3F, 0xE2, 3));
+
+        // Arithmetic with the carry in and out.
+        let (cpu, _, _) = single(&[0x69, 0x01], &[], |r| { r.accumulator = 0xFF; r.status_flags |= 0x01 }); // ADC #$01
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x01, 0x21));
+        let (cpu, _, _) = single(&[0xE9, 0x01], &[], |r| r.status_flags |= 0x01); // SBC #$01
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0xFF, 0xA0));
+        let (cpu, _, _) = single(&[0xE9, 0x01], &[], |r| { r.accumulator = 0x80; r.status_flags |= 0x01 }); // SBC #$01
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x7F, 0x61));
+        let (cpu, _, _) = single(&[0xC9, 0x10], &[], |r| r.accumulator = 0x0F); // CMP #$10
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x0F, 0xA0));
+        let (cpu, _, _) = single(&[0xE0, 0x01], &[], |r| r.idx_x = 0x02); // CPX #$01
+        assert_eq!(cpu.registers().status_flags, 0x21);
+        let (cpu, _, _) = single(&[0xC0, 0x05], &[], |r| r.idx_y = 0x05); // CPY #$05
+        assert_eq!(cpu.registers().status_flags, 0x23);
+
+        // Increments and decrements wrap around.
+        let (cpu, bus, cycles) = single(&[0xE6, 0x10], &[(0x0010, 0xFF)], |_| {}); // INC $10
+        assert_eq!((bus.peek(0x0010), cpu.registers().status_flags, cycles), (0x00, 0x22, 5));
+        let (_, bus, cycles) = single(&[0xFE, 0x00, 0x03], &[(0x0301, 0x41)], |r| r.idx_x = 0x01); // INC $0300,X
+        assert_eq!((bus.peek(0x0301), cycles), (0x42, 7));
+        let (cpu, bus, _) = single(&[0xC6, 0x10], &[], |_| {}); // DEC $10
+        assert_eq!((bus.peek(0x0010), cpu.registers().status_flags), (0xFF, 0xA0));
+        let (cpu, _, _) = single(&[0xE8], &[], |r| r.idx_x = 0xFF); // INX
+        assert_eq!((cpu.registers().idx_x, cpu.registers().status_flags), (0x00, 0x22));
+        let (cpu, _, _) = single(&[0xC8], &[], |r| r.idx_y = 0x41); // INY
+        assert_eq!(cpu.registers().idx_y, 0x42);
+        let (cpu, _, _) = single(&[0xCA], &[], |_| {}); // DEX
+        assert_eq!((cpu.registers().idx_x, cpu.registers().status_flags), (0xFF, 0xA0));
+        let (cpu, _, _) = single(&[0x88], &[], |r| r.idx_y = 0x43); // DEY
+        assert_eq!(cpu.registers().idx_y, 0x42);
+
+        // Shifts and rotates, on the accumulator and in memory.
+        let (cpu, _, cycles) = single(&[0x0A], &[], |r| r.accumulator = 0x81); // ASL A
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags, cycles), (0x02, 0x21, 2));
+        let (cpu, _, _) = single(&[0x4A], &[], |r| r.accumulator = 0x01); // LSR A
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x00, 0x23));
+        let (cpu, _, _) = single(&[0x2A], &[], |r| { r.accumulator = 0x80; r.status_flags |= 0x01 }); // ROL A
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x01, 0x21));
+        let (cpu, _, _) = single(&[0x6A], &[], |r| { r.accumulator = 0x01; r.status_flags |= 0x01 }); // ROR A
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x80, 0xA1));
+        let (cpu, bus, cycles) = single(&[0x66, 0x10], &[(0x0010, 0x02)], |_| {}); // ROR $10
+        assert_eq!((bus.peek(0x0010), cpu.registers().status_flags, cycles), (0x01, 0x20, 5));
+        let (_, bus, cycles) = single(&[0x5E, 0x00, 0x03], &[(0x0301, 0x84)], |r| r.idx_x = 0x01); // LSR $0300,X
+        assert_eq!((bus.peek(0x0301), cycles), (0x42, 7));
+
+        // Jumps, subroutines and interrupts.
+        let (cpu, _, cycles) = single(&[0x4C, 0x34, 0x12], &[], |_| {}); // JMP $1234
+        assert_eq!((cpu.registers().program_counter, cycles), (0x1234, 3));
+        let (cpu, _, cycles) = single(&[0x6C, 0xFF, 0x03], &[(0x03FF, 0x34), (0x0300, 0x12), (0x0400, 0x56)], |_| {}); // JMP ($03FF)
+        assert_eq!((cpu.registers().program_counter, cycles), (0x1234, 5));
+        let (cpu, bus, cycles) = single(&[0x20, 0x34, 0x12], &[], |_| {}); // JSR $1234
+        assert_eq!((cpu.registers().program_counter, cpu.registers().stack_pointer, cycles), (0x1234, 0xFD, 6));
+        assert_eq!((bus.peek(0x01FF), bus.peek(0x01FE)), (0x02, 0x02));
+        let (cpu, _, cycles) = single(&[0x60], &[(0x01FE, 0x02), (0x01FF, 0x03)], |r| r.stack_pointer = 0xFD); // RTS
+        assert_eq!((cpu.registers().program_counter, cpu.registers().stack_pointer, cycles), (0x0303, 0xFF, 6));
+        let (cpu, bus, cycles) = single(&[0x00, 0xEA], &[(0xFFFE, 0x00), (0xFFFF, 0x04)], |_| {}); // BRK
+        assert_eq!((cpu.registers().program_counter, cpu.registers().status_flags, cycles), (0x0400, 0x24, 7));
+        assert_eq!((bus.peek(0x01FF), bus.peek(0x01FE), bus.peek(0x01FD)), (0x02, 0x02, 0x30));
+        let (cpu, _, cycles) = single(&[0x40], &[(0x01FD, 0xD3), (0x01FE, 0x00), (0x01FF, 0x03)], |r| r.stack_pointer = 0xFC); // RTI
+        assert_eq!((cpu.registers().program_counter, cpu.registers().status_flags, cycles), (0x0300, 0xE3, 6));
+
+        // Branches, taken, not taken and taken across a page.
+        for (opcode, flag, when_set) in [
+            (0x10, 0x80, false), (0x30, 0x80, true), (0x50, 0x40, false), (0x70, 0x40, true),
+            (0x90, 0x01, false), (0xB0, 0x01, true), (0xD0, 0x02, false), (0xF0, 0x02, true),
+        ] {
+            let taken = if when_set { 0x20 | flag } else { 0x20 };
+            let not_taken = taken ^ flag;
+            let (cpu, _, cycles) = single(&[opcode, 0x02], &[], |r| r.status_flags = taken);
+            assert_eq!((cpu.registers().program_counter, cycles), (0x0204, 3), "{:02X}", opcode);
+            let (cpu, _, cycles) = single(&[opcode, 0x02], &[], |r| r.status_flags = not_taken);
+            assert_eq!((cpu.registers().program_counter, cycles), (0x0202, 2), "{:02X}", opcode);
+            let (cpu, _, cycles) = single(&[opcode, 0xFC], &[], |r| r.status_flags = taken);
+            assert_eq!((cpu.registers().program_counter, cycles), (0x01FE, 4), "{:02X}", opcode);
+        }
+
+        // Flag instructions and NOP.
+        for (opcode, before, after) in [
+            (0x18, 0x21, 0x20), (0x38, 0x20, 0x21), (0x58, 0x24, 0x20), (0x78, 0x20, 0x24),
+            (0xB8, 0x60, 0x20), (0xD8, 0x28, 0x20), (0xF8, 0x20, 0x28), (0xEA, 0x20, 0x20),
+        ] {
+            let (cpu, _, cycles) = single(&[opcode], &[], |r| r.status_flags = before);
+            assert_eq!((cpu.registers().status_flags, cpu.registers().program_counter, cycles), (after, 0x0201, 2), "{:02X}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_program_counter_wraps() {
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap()))
+            .build()
+            .unwrap();
+        let mut cpu = CPU6502::new();
+
+        // Operands at the top of memory continue at the bottom.
+        load(&mut bus, 0xFFFE, &[0xAD, 0x00]); // LDA $0300
+        load(&mut bus, 0x0000, &[0x03]);
+        bus.write(0x0300, 0x17);
+        cpu.registers_mut().program_counter = 0xFFFE;
+        cpu.tick(&mut bus);
+        assert_eq!((cpu.registers().accumulator, cpu.registers().program_counter), (0x17, 0x0001));
+
+        load(&mut bus, 0xFFFF, &[0xA9]); // LDA #$42
+        load(&mut bus, 0x0000, &[0x42]);
+        cpu.registers_mut().program_counter = 0xFFFF;
+        cpu.tick(&mut bus);
+        assert_eq!((cpu.registers().accumulator, cpu.registers().program_counter), (0x42, 0x0001));
+    }
+
+    #[test]
+    fn test_undocumented() {
+        // Read-modify-write, then the accumulator operation on the result.
+        let (cpu, bus, cycles) = single(&[0x07, 0x10], &[(0x0010, 0xC0)], |r| r.accumulator = 0x01); // SLO $10
+        assert_eq!((bus.peek(0x0010), cpu.registers().accumulator, cpu.registers().status_flags, cycles), (0x80, 0x81, 0xA1, 5));
+        let (cpu, bus, _) = single(&[0x2F, 0x00, 0x03], &[(0x0300, 0x40)], |r| { r.accumulator = 0xFF; r.status_flags |= 0x01 }); // RLA $0300
+        assert_eq!((bus.peek(0x0300), cpu.registers().accumulator, cpu.registers().status_flags), (0x81, 0x81, 0xA0));
+        let (cpu, bus, cycles) = single(&[0x57, 0x10], &[(0x0011, 0x03)], |r| { r.accumulator = 0x01; r.idx_x = 0x01 }); // SRE $10,X
+        assert_eq!((bus.peek(0x0011), cpu.registers().accumulator, cpu.registers().status_flags, cycles), (0x01, 0x00, 0x23, 6));
+        let (cpu, bus, _) = single(&[0x67, 0x10], &[(0x0010, 0x02)], |r| r.accumulator = 0x10); // RRA $10
+        assert_eq!((bus.peek(0x0010), cpu.registers().accumulator, cpu.registers().status_flags), (0x01, 0x11, 0x20));
+        let (cpu, bus, cycles) = single(&[0xDF, 0x00, 0x03], &[(0x0301, 0x05)], |r| { r.accumulator = 0x04; r.idx_x = 0x01 }); // DCP $0300,X
+        assert_eq!((bus.peek(0x0301), cpu.registers().status_flags, cycles), (0x04, 0x23, 7));
+        let (cpu, bus, _) = single(&[0xE7, 0x10], &[(0x0010, 0x0F)], |r| { r.accumulator = 0x20; r.status_flags |= 0x01 }); // ISB $10
+        assert_eq!((bus.peek(0x0010), cpu.registers().accumulator, cpu.registers().status_flags), (0x10, 0x10, 0x21));
+
+        // Loads and stores of A and X together.
+        let (_, bus, cycles) = single(&[0x87, 0x20], &[], |r| { r.accumulator = 0xF0; r.idx_x = 0x3C }); // SAX $20
+        assert_eq!((bus.peek(0x0020), cycles), (0x30, 3));
+        let (cpu, _, cycles) = single(&[0xB3, 0x30], &[(0x0030, 0xFF), (0x0031, 0x02), (0x0300, 0x7F)], |r| r.idx_y = 0x01); // LAX ($30),Y
+        assert_eq!((cpu.registers().accumulator, cpu.registers().idx_x, cycles), (0x7F, 0x7F, 6));
+        let (cpu, _, cycles) = single(&[0xBB, 0x00, 0x03], &[(0x0300, 0x3C)], |r| r.stack_pointer = 0xF0); // LAS $0300,Y
+        assert_eq!((cpu.registers().accumulator, cpu.registers().idx_x, cpu.registers().stack_pointer, cycles), (0x30, 0x30, 0x30, 4));
+
+        // Immediate operations.
+        let (cpu, _, _) = single(&[0x0B, 0xFF], &[], |r| r.accumulator = 0x80); // ANC #$FF
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x80, 0xA1));
+        let (cpu, _, _) = single(&[0x4B, 0x01], &[], |r| r.accumulator = 0x03); // ALR #$01
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0x00, 0x23));
+        let (cpu, _, _) = single(&[0x6B, 0xFF], &[], |r| { r.accumulator = 0xFF; r.status_flags |= 0x01 }); // ARR #$FF
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0xFF, 0xA1));
+        let (cpu, _, _) = single(&[0x6B, 0xFF], &[], |r| { r.accumulator = 0x99; r.status_flags |= 0x08 }); // ARR #$FF, decimal
+        assert_eq!((cpu.registers().accumulator, cpu.registers().status_flags), (0xA2, 0x69));
+        let (cpu, _, _) = single(&[0xCB, 0x02], &[], |r| { r.accumulator = 0x0F; r.idx_x = 0xFC }); // SBX #$02
+        assert_eq!((cpu.registers().idx_x, cpu.registers().status_flags), (0x0A, 0x21));
+        let (cpu, _, _) = single(&[0x8B, 0x0F], &[], |r| r.idx_x = 0xFF); // ANE #$0F
+        assert_eq!(cpu.registers().accumulator, 0x0E);
+        let (cpu, _, _) = single(&[0xAB, 0x0F], &[], |r| r.accumulator = 0x01); // LXA #$0F
+        assert_eq!((cpu.registers().accumulator, cpu.registers().idx_x), (0x0F, 0x0F));
+        let (cpu, _, _) = single(&[0xEB, 0x01], &[], |r| { r.accumulator = 0x03; r.status_flags |= 0x01 }); // SBC #$01
+        assert_eq!(cpu.registers().accumulator, 0x02);
+
+        // Stores ANDed with the high byte of the address plus one, which replaces the high byte when
+        // the index crosses a page.
+        let (_, bus, cycles) = single(&[0x9E, 0x00, 0x03], &[], |r| { r.idx_x = 0xFF; r.idx_y = 0x01 }); // SHX $0300,Y
+        assert_eq!((bus.peek(0x0301), cycles), (0x04, 5));
+        let (_, bus, _) = single(&[0x9E, 0xFF, 0x0B], &[], |r| { r.idx_x = 0x0A; r.idx_y = 0x01 }); // SHX $0BFF,Y
+        assert_eq!((bus.peek(0x0C00), bus.peek(0x0800)), (0x00, 0x08));
+        let (_, bus, _) = single(&[0x9C, 0x00, 0x03], &[], |r| r.idx_y = 0xFF); // SHY $0300,X
+        assert_eq!(bus.peek(0x0300), 0x04);
+        let (_, bus, _) = single(&[0x9F, 0x00, 0x03], &[], |r| { r.accumulator = 0xFF; r.idx_x = 0xF7 }); // SHA $0300,Y
+        assert_eq!(bus.peek(0x0300), 0x04);
+        let (cpu, bus, _) = single(&[0x9B, 0x00, 0x13], &[], |r| { r.accumulator = 0xF3; r.idx_x = 0x3F }); // TAS $1300,Y
+        assert_eq!((cpu.registers().stack_pointer, bus.peek(0x1300)), (0x33, 0x10));
+
+        // NOPs which read their operand, with the page crossing cycle.
+        let (cpu, _, cycles) = single(&[0x1C, 0xFF, 0x02], &[], |r| r.idx_x = 0x01); // NOP $02FF,X
+        assert_eq!((cpu.registers().program_counter, cycles), (0x0203, 5));
+        let (cpu, _, cycles) = single(&[0x1A], &[], |_| {}); // NOP
+        assert_eq!((cpu.registers().program_counter, cycles), (0x0201, 2));
+    }
+
+    #[test]
+    fn test_jam() {
+        let (mut cpu, mut bus, _) = single(&[0x02], &[(0xFFFC, 0x00), (0xFFFD, 0x03), (0x0300, 0xEA)], |_| {});
+        assert!(cpu.jammed());
+        assert_eq!(cpu.registers().program_counter, 0x0200);
+
+        // Interrupts are ignored while time goes on.
+        let irq = bus.interrupts().source("test");
+        irq.set_irq(true);
+        cpu.registers_mut().set_flag(Flags::InterruptDisable, false);
+        let cycles = cpu.cycles();
+        cpu.tick(&mut bus);
+        cpu.tick(&mut bus);
+        assert_eq!((cpu.registers().program_counter, cpu.cycles()), (0x0200, cycles + 2));
+        irq.set_irq(false);
+
+        // Only a reset gets it going again.
+        cpu.reset(&mut bus);
+        assert!(!cpu.jammed());
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x0301);
+    }
+
+    #[test]
+    fn test_traps() {
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap()))
+            .build()
+            .unwrap();
+        let mut cpu = CPU6502::new();
+
+        // A CHROUT stand-in collecting what the program prints, and a routine doubling X in memory.
+        let printed = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sink = printed.clone();
+        cpu.add_trap(0xFFD2, move |registers, _| sink.borrow_mut().push(registers.accumulator));
+        cpu.add_trap(0xC000, |registers, bus| {
+            bus.write(0x0010, registers.idx_x.wrapping_mul(2));
+            registers.set_flag(Flags::Carry, true);
+            registers.program_counter = 0x1234;
+        });
+
+        // As if JSR $FFD2 at $0200 had pushed $0202.
+        bus.write(0x01FF, 0x02);
+        bus.write(0x01FE, 0x02);
+        bus.write(0x0203, 0x18); // CLC
+        cpu.registers_mut().stack_pointer = 0xFD;
+        cpu.registers_mut().program_counter = 0xFFD2;
+        cpu.registers_mut().accumulator = b'A';
+        cpu.tick(&mut bus);
+        assert_eq!(*printed.borrow(), b"A");
+        assert_eq!((cpu.registers().program_counter, cpu.registers().stack_pointer), (0x0203, 0xFF));
+        assert_eq!(cpu.cycles(), 6);
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x0204);
+
+        cpu.registers_mut().stack_pointer = 0xFD;
+        cpu.registers_mut().program_counter = 0xC000;
+        cpu.registers_mut().idx_x = 21;
+        cpu.tick(&mut bus);
+        assert_eq!(bus.read(0x0010), 42);
+        assert!(cpu.registers_mut().get_flag(Flags::Carry));
+        assert_eq!(cpu.registers().program_counter, 0x0203);
+
+        // Without the trap the code at the address runs again.
+        assert!(cpu.remove_trap(0xC000));
+        assert!(!cpu.remove_trap(0xC000));
+        bus.write(0xC000, 0x18); // CLC
+        cpu.registers_mut().program_counter = 0xC000;
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0xC001);
+
+        // A jump trap leaves the stack alone and goes on where the closure pointed.
+        cpu.add_jump_trap(0xA000, |registers, _| registers.program_counter = 0x0203);
+        cpu.registers_mut().program_counter = 0xA000;
+        let (stack_pointer, cycles) = (cpu.registers().stack_pointer, cpu.cycles());
+        cpu.tick(&mut bus);
+        assert_eq!((cpu.registers().program_counter, cpu.registers().stack_pointer), (0x0203, stack_pointer));
+        assert_eq!(cpu.cycles(), cycles + 3);
+    }
+
+    #[test]
+    fn test_traps_observed() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Recorder(Rc<RefCell<Vec<String>>>);
+
+        impl CpuObserver for Recorder {
+            fn read(&mut self, address: u16, _data: u8, kind: AccessKind) {
+                self.0.borrow_mut().push(format!("read {:#06x} {:?}", address, kind));
+            }
+
+            fn trap(&mut self, address: u16, registers: &Registers) {
+                self.0.borrow_mut().push(format!("trap {:#06x} PC={:#06x}", address, registers.program_counter));
+            }
+        }
+
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap()))
+            .build()
+            .unwrap();
+        let mut cpu = CPU6502::new();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        cpu.attach_observer(Box::new(Recorder(events.clone())));
+        let trace = Capture::default();
+        cpu.set_trace(Some(Box::new(trace.clone())));
+        cpu.add_trap(0xFFD2, |_, _| {});
+
+        // JSR $FFD2 at $0200, then a NOP after the trap returned.
+        bus.write(0x0200, 0x20);
+        bus.write(0x0201, 0xD2);
+        bus.write(0x0202, 0xFF);
+        bus.write(0x0203, 0xEA);
+        cpu.registers_mut().program_counter = 0x0200;
+        cpu.registers_mut().stack_pointer = 0xFF;
+        cpu.tick(&mut bus);
+        events.borrow_mut().clear();
+        cpu.tick(&mut bus);
+        cpu.tick(&mut bus);
+
+        assert_eq!(*events.borrow(), vec![
+            "read 0xffd2 OpcodeFetch",
+            "read 0x01fe Stack",
+            "read 0x01ff Stack",
+            "trap 0xffd2 PC=0x0203",
+            "read 0x0203 OpcodeFetch",
+        ]);
+        let lines = String::from_utf8(trace.take()).unwrap();
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(lines[1], "FFD2            TRAP                            A:00 X:00 Y:00 P:00 SP:FD CYC:6");
+        assert!(lines[2].starts_with("0203  EA        NOP "));
+        assert!(lines[2].ends_with("SP:FF CYC:12"));
     }
 }
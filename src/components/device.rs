@@ -1,5 +1,100 @@
-pub trait Addressable {
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::components::snapshot::Snapshot;
+
+pub type SharedMemory = Rc<[Cell<u8>]>;
+
+// Devices with a notion of time (timers, UARTs, video chips) are advanced by the bus as the CPU runs.
+pub trait Clocked {
+    // Advances the device by the given number of its own clock cycles.
+    fn tick(&mut self, cycles: u64);
+
+    // Own clock cycles until the next moment the device has to be advanced for on its own, e.g. a timer
+    // underflow or a received byte, None while nothing happens until the CPU accesses it. Only used in
+    // the scheduled clock mode, the default asks to be advanced on every cycle.
+    fn next_event(&self) -> Option<u64> {
+        return Some(1);
+    }
+}
+
+pub trait Addressable: Snapshot {
+    // Inclusive range of bus addresses the device answers for when attached without an explicit mapping.
     fn get_address_space(&self) -> (u16, u16);
-    fn read(&self, address: u16) -> u8;
+    // Reads without any side effect, used by debuggers, disassemblers and memory viewers.
+    fn peek(&self, address: u16) -> u8;
+
+    // Reads on behalf of the CPU, devices override this when a read changes their state (e.g. clears an
+    // interrupt flag or advances a FIFO).
+    fn read(&mut self, address: u16) -> u8 {
+        return self.peek(address);
+    }
+
     fn write(&mut self, address: u16, data: u8);
-}
\ No newline at end of file
+
+    // Plain memory without side effects can share its backing storage, indexed by the offset from the
+    // start of the device's address space, so the bus can access it directly instead of going through
+    // read and write.
+    fn memory(&self) -> Option<SharedMemory> {
+        return None;
+    }
+
+    // Clocked devices return themselves, the bus only advances devices which do.
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        return None;
+    }
+
+    // Shown in the memory map.
+    fn name(&self) -> &str {
+        return "Device";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::Bus;
+
+    #[test]
+    fn test_peek_has_no_side_effects() {
+        use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+        // A keyboard style latch which is cleared by reading it.
+        struct Latch(u8);
+
+        impl Addressable for Latch {
+            fn get_address_space(&self) -> (u16, u16) {
+                return (0xD010, 0xD010);
+            }
+
+            fn peek(&self, _address: u16) -> u8 {
+                return self.0;
+            }
+
+            fn read(&mut self, _address: u16) -> u8 {
+                return std::mem::take(&mut self.0);
+            }
+
+            fn write(&mut self, _address: u16, data: u8) {
+                self.0 = data;
+            }
+        }
+
+        impl Snapshot for Latch {
+            fn save(&self, writer: &mut SnapshotWriter) {
+                writer.write_u8(self.0);
+            }
+
+            fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+                self.0 = reader.read_u8()?;
+                return Ok(());
+            }
+        }
+
+        let mut bus = Bus::with_device(Box::new(Latch(0x8D)));
+        assert_eq!(bus.peek(0xD010), 0x8D);
+        assert_eq!(bus.peek(0xD010), 0x8D);
+        assert_eq!(bus.read(0xD010), 0x8D);
+        assert_eq!(bus.peek(0xD010), 0x00);
+    }
+}
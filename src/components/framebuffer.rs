@@ -0,0 +1,230 @@
+use std::cell::Cell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::components::device::{Addressable, SharedMemory};
+use crate::components::memory::MemoryError;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// The 16 colours of easy6502, which are those of the C64, as 0xRRGGBB.
+pub const EASY6502_PALETTE: [u32; 16] = [
+    0x000000, 0xFFFFFF, 0x880000, 0xAAFFEE, 0xCC44CC, 0x00CC55, 0x0000AA, 0xEEEE77,
+    0xDD8855, 0x664400, 0xFF7777, 0x333333, 0x777777, 0xAAFF66, 0x0088FF, 0xBBBBBB,
+];
+
+// One byte per pixel, row by row, of which the low four bits select a palette entry. The pixels are
+// plain memory, so the bus writes them directly; see FramebufferView for showing them.
+pub struct Framebuffer {
+    address: u16,
+    pixels: SharedMemory,
+    width: usize,
+    height: usize,
+    palette: [u32; 16],
+}
+
+impl Framebuffer {
+    pub fn new(address: u16, width: usize, height: usize) -> Result<Framebuffer, MemoryError> {
+        let size = width * height;
+        if size == 0 || size > 0x10000 {
+            return Err(MemoryError::InvalidSize(size));
+        }
+        if address as usize + size > 0x10000 {
+            return Err(MemoryError::OutOfAddressSpace { address, size });
+        }
+        let pixels = Rc::from(vec![Cell::new(0); size]);
+        return Ok(Framebuffer { address, pixels, width, height, palette: EASY6502_PALETTE });
+    }
+
+    pub fn view(&self) -> FramebufferView {
+        return FramebufferView { pixels: self.pixels.clone(), width: self.width, height: self.height, palette: self.palette };
+    }
+}
+
+impl Addressable for Framebuffer {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (self.address, (self.address as usize + self.pixels.len() - 1) as u16);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        return self.pixels[address as usize].get();
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.pixels[address as usize].set(data);
+    }
+
+    fn memory(&self) -> Option<SharedMemory> {
+        return Some(self.pixels.clone());
+    }
+
+    fn name(&self) -> &str {
+        return "Framebuffer";
+    }
+}
+
+impl Snapshot for Framebuffer {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        let pixels: Vec<u8> = self.pixels.iter().map(Cell::get).collect();
+        writer.write_bytes(&pixels);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let mut pixels = vec![0; self.pixels.len()];
+        reader.read_into(&mut pixels)?;
+        for (cell, byte) in self.pixels.iter().zip(pixels) {
+            cell.set(byte);
+        }
+        return Ok(());
+    }
+}
+
+// The host side of a Framebuffer, sharing its pixels.
+#[derive(Clone)]
+pub struct FramebufferView {
+    pixels: SharedMemory,
+    width: usize,
+    height: usize,
+    palette: [u32; 16],
+}
+
+impl FramebufferView {
+    // Colour of a pixel as 0xRRGGBB.
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        return self.palette[(self.pixels[y * self.width + x].get() & 0x0F) as usize];
+    }
+
+    // The raw pixel bytes, to tell whether anything changed since the last frame.
+    pub fn frame(&self) -> Vec<u8> {
+        return self.pixels.iter().map(Cell::get).collect();
+    }
+
+    fn rgb(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.width * self.height * 3);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let colour = self.pixel(x, y);
+                rgb.extend_from_slice(&[(colour >> 16) as u8, (colour >> 8) as u8, colour as u8]);
+            }
+        }
+        return rgb;
+    }
+
+    // Two pixels per character cell using the upper half block, with the top pixel as foreground and
+    // the bottom one as background colour. Needs a terminal with 24 bit colour.
+    pub fn render(&self) -> String {
+        let mut frame = String::new();
+        for y in (0..self.height).step_by(2) {
+            for x in 0..self.width {
+                let top = self.pixel(x, y);
+                let bottom = if y + 1 < self.height { self.pixel(x, y + 1) } else { 0x000000 };
+                frame.push_str(&format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                    top >> 16, (top >> 8) & 0xFF, top & 0xFF, bottom >> 16, (bottom >> 8) & 0xFF, bottom & 0xFF));
+            }
+            frame.push_str("\x1b[0m\n");
+        }
+        return frame;
+    }
+
+    // Binary PPM (P6), one image pixel per framebuffer pixel.
+    pub fn write_ppm(&self, writer: &mut impl Write) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        return writer.write_all(&self.rgb());
+    }
+
+    // 8 bit RGB PNG. The image data is stored rather than compressed, which keeps the encoder short
+    // and the frames are tiny anyway.
+    pub fn write_png(&self, writer: &mut impl Write) -> io::Result<()> {
+        let rgb = self.rgb();
+        let mut scanlines = Vec::with_capacity(rgb.len() + self.height);
+        for row in rgb.chunks(self.width * 3) {
+            // Filter type None.
+            scanlines.push(0);
+            scanlines.extend_from_slice(row);
+        }
+
+        // A zlib stream of stored deflate blocks of up to 65535 bytes.
+        let mut zlib = vec![0x78, 0x01];
+        let blocks: Vec<&[u8]> = scanlines.chunks(0xFFFF).collect();
+        for (index, block) in blocks.iter().enumerate() {
+            zlib.push(if index == blocks.len() - 1 { 1 } else { 0 });
+            let length = block.len() as u16;
+            zlib.extend_from_slice(&length.to_le_bytes());
+            zlib.extend_from_slice(&(!length).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, deflate, no interlacing.
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+        write_chunk(writer, b"IHDR", &header)?;
+        write_chunk(writer, b"IDAT", &zlib)?;
+        return write_chunk(writer, b"IEND", &[]);
+    }
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let mut crc = crc32(0xFFFFFFFF, kind);
+    crc = crc32(crc, data);
+    return writer.write_all(&(!crc).to_be_bytes());
+}
+
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    return crc;
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    return b << 16 | a;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png() {
+        let mut framebuffer = Framebuffer::new(0x0200, 2, 2).unwrap();
+        for (offset, colour) in [0x01, 0x02, 0x0E, 0x00].iter().enumerate() {
+            framebuffer.write(offset as u16, *colour);
+        }
+
+        // The same image from Python's zlib.compress(level 0) and zlib.crc32.
+        let mut png = Vec::new();
+        framebuffer.view().write_png(&mut png).unwrap();
+        assert_eq!(png, [
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
+            0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x02, 0x00, 0x00, 0x00, 0xFD, 0xD4, 0x9A,
+            0x73, 0x00, 0x00, 0x00, 0x19, 0x49, 0x44, 0x41, 0x54, 0x78, 0x01, 0x01, 0x0E, 0x00, 0xF1, 0xFF,
+            0x00, 0xFF, 0xFF, 0xFF, 0x88, 0x00, 0x00, 0x00, 0x00, 0x88, 0xFF, 0x00, 0x00, 0x00, 0x2F, 0xDE,
+            0x05, 0x0D, 0xA3, 0xBE, 0x8F, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42,
+            0x60, 0x82,
+        ]);
+
+        // Frames over 64K of image data take several stored blocks.
+        let framebuffer = Framebuffer::new(0x0000, 256, 256).unwrap();
+        let mut png = Vec::new();
+        framebuffer.view().write_png(&mut png).unwrap();
+        let scanlines = 256 * (1 + 256 * 3);
+        let length = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(length, 2 + scanlines + 4 * 5 + 4);
+        assert_eq!(&png[41..46], [0x78, 0x01, 0x00, 0xFF, 0xFF]);
+    }
+}
@@ -0,0 +1,590 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::components::via6522::{Peripheral, ViaPins};
+
+// Execution times in microseconds, for the 270 kHz oscillator of the datasheet.
+const CLEAR_TIME: u64 = 1520;
+const INSTRUCTION_TIME: u64 = 37;
+
+// The display data RAM holds two lines of 40 characters, or a single line of 80.
+const LINE_LENGTH: usize = 40;
+const DDRAM_SIZE: usize = 80;
+const CGRAM_SIZE: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Port {
+    A,
+    B,
+}
+
+// Which VIA pins the LCD is wired to. The control lines are masks of a single pin each.
+#[derive(Debug, Copy, Clone)]
+pub struct Wiring {
+    pub data: Port,
+    // Lowest pin of D4-D7 when only the upper four data lines are connected, None for all eight.
+    pub nibble: Option<u8>,
+    pub control: Port,
+    pub register_select: u8,
+    pub read_write: u8,
+    pub enable: u8,
+}
+
+impl Wiring {
+    // D0-D7 on port B, E, RW and RS on PA7, PA6 and PA5, as in the first Ben Eater videos.
+    pub fn eight_bit() -> Wiring {
+        return Wiring { data: Port::B, nibble: None, control: Port::A, register_select: 0x20, read_write: 0x40, enable: 0x80 };
+    }
+
+    // Everything on port B: D4-D7 on PB0-PB3, RS on PB4, RW on PB5 and E on PB6.
+    pub fn four_bit() -> Wiring {
+        return Wiring { data: Port::B, nibble: Some(0), control: Port::B, register_select: 0x10, read_write: 0x20, enable: 0x40 };
+    }
+
+    fn data_mask(&self) -> u8 {
+        return match self.nibble {
+            Some(shift) => 0x0F << shift,
+            None => 0xFF,
+        };
+    }
+}
+
+struct Controller {
+    columns: usize,
+    rows: usize,
+    ddram: [u8; DDRAM_SIZE],
+    cgram: [u8; CGRAM_SIZE],
+    // Address counter, pointing into CGRAM after a CGRAM address was set.
+    address: u8,
+    cgram_selected: bool,
+    increment: bool,
+    shift_display: bool,
+    display_on: bool,
+    cursor: bool,
+    blink: bool,
+    eight_bit: bool,
+    two_lines: bool,
+    large_font: bool,
+    // How far the display is shifted to the left.
+    offset: usize,
+    // Cycles until the current instruction is done.
+    busy: u64,
+    // The high half of a byte transferred over four data lines.
+    high_nibble: Option<u8>,
+    changed: bool,
+}
+
+impl Controller {
+    // The state after the internal reset at power-on.
+    fn new(columns: usize, rows: usize) -> Controller {
+        return Controller {
+            columns,
+            rows,
+            ddram: [0x20; DDRAM_SIZE],
+            cgram: [0; CGRAM_SIZE],
+            address: 0,
+            cgram_selected: false,
+            increment: true,
+            shift_display: false,
+            display_on: false,
+            cursor: false,
+            blink: false,
+            eight_bit: true,
+            two_lines: false,
+            large_font: false,
+            offset: 0,
+            busy: 0,
+            high_nibble: None,
+            changed: true,
+        };
+    }
+
+    fn line_length(&self) -> usize {
+        return if self.two_lines { LINE_LENGTH } else { DDRAM_SIZE };
+    }
+
+    fn ddram_index(&self, address: u8) -> usize {
+        return if self.two_lines && address >= 0x40 { LINE_LENGTH + (address - 0x40) as usize } else { address as usize };
+    }
+
+    // Moves the address counter one step, wrapping around within DDRAM the way the chip does.
+    fn advance(&mut self, forward: bool) {
+        if self.cgram_selected {
+            self.address = if forward { self.address.wrapping_add(1) } else { self.address.wrapping_sub(1) } & 0x3F;
+            return;
+        }
+        let (line, column) = if self.two_lines { (self.address & 0x40, (self.address & 0x3F) as usize) } else { (0, self.address as usize) };
+        let length = self.line_length();
+        let column = if forward { (column + 1) % length } else { (column + length - 1) % length };
+        self.address = if self.two_lines {
+            // Running off the end of one line continues on the other.
+            let wrapped = (forward && column == 0) || (!forward && column == length - 1);
+            (if wrapped { line ^ 0x40 } else { line }) | column as u8
+        } else {
+            column as u8
+        };
+    }
+
+    fn shift(&mut self, left: bool) {
+        let length = self.line_length();
+        self.offset = if left { (self.offset + 1) % length } else { (self.offset + length - 1) % length };
+    }
+
+    fn instruction(&mut self, data: u8, clock: u64) {
+        let mut time = INSTRUCTION_TIME;
+        match data {
+            // Not an instruction, e.g. what floating pins latch before the port is set up.
+            0x00 => return,
+            0x01 => {
+                self.ddram = [0x20; DDRAM_SIZE];
+                self.address = 0;
+                self.cgram_selected = false;
+                self.offset = 0;
+                self.increment = true;
+                time = CLEAR_TIME;
+            }
+            0x02..=0x03 => {
+                self.address = 0;
+                self.cgram_selected = false;
+                self.offset = 0;
+                time = CLEAR_TIME;
+            }
+            0x04..=0x07 => {
+                self.increment = data & 0x02 != 0;
+                self.shift_display = data & 0x01 != 0;
+            }
+            0x08..=0x0F => {
+                self.display_on = data & 0x04 != 0;
+                self.cursor = data & 0x02 != 0;
+                self.blink = data & 0x01 != 0;
+            }
+            0x10..=0x1F => {
+                if data & 0x08 != 0 {
+                    self.shift(data & 0x04 == 0);
+                } else {
+                    self.advance(data & 0x04 != 0);
+                }
+            }
+            0x20..=0x3F => {
+                self.eight_bit = data & 0x10 != 0;
+                self.two_lines = data & 0x08 != 0;
+                self.large_font = data & 0x04 != 0;
+                self.high_nibble = None;
+                if !self.two_lines {
+                    self.address &= 0x7F;
+                }
+            }
+            0x40..=0x7F => {
+                self.address = data & 0x3F;
+                self.cgram_selected = true;
+            }
+            _ => {
+                self.address = data & 0x7F;
+                self.cgram_selected = false;
+            }
+        }
+        self.busy = time * clock / 1_000_000;
+        self.changed = true;
+    }
+
+    fn write_data(&mut self, data: u8, clock: u64) {
+        if self.cgram_selected {
+            self.cgram[self.address as usize & 0x3F] = data;
+        } else {
+            let index = self.ddram_index(self.address) % DDRAM_SIZE;
+            self.ddram[index] = data;
+            if self.shift_display {
+                self.shift(self.increment);
+            }
+        }
+        self.advance(self.increment);
+        self.busy = INSTRUCTION_TIME * clock / 1_000_000;
+        self.changed = true;
+    }
+
+    fn read(&self, register_select: bool) -> u8 {
+        if !register_select {
+            return (if self.busy > 0 { 0x80 } else { 0x00 }) | self.address & 0x7F;
+        }
+        return if self.cgram_selected {
+            self.cgram[self.address as usize & 0x3F]
+        } else {
+            self.ddram[self.ddram_index(self.address) % DDRAM_SIZE]
+        };
+    }
+
+    fn text(&self) -> String {
+        let mut lines = Vec::new();
+        for row in 0..self.rows {
+            let mut line = String::new();
+            for column in 0..self.columns {
+                // Rows 3 and 4 of four line modules continue lines 1 and 2.
+                let position = (row / 2) * self.columns + column + self.offset;
+                let address = match (self.two_lines, row % 2) {
+                    (true, line) => Some(line * LINE_LENGTH + position % LINE_LENGTH),
+                    (false, 0) if row == 0 => Some(position % DDRAM_SIZE),
+                    (false, _) => None,
+                };
+                line.push(match address {
+                    Some(address) if self.display_on => character(self.ddram[address]),
+                    _ => ' ',
+                });
+            }
+            lines.push(line);
+        }
+        return lines.join("\n");
+    }
+
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write_bytes(&self.ddram);
+        writer.write_bytes(&self.cgram);
+        writer.write_u8(self.address);
+        for flag in [self.cgram_selected, self.increment, self.shift_display, self.display_on, self.cursor, self.blink,
+            self.eight_bit, self.two_lines, self.large_font] {
+            writer.write_bool(flag);
+        }
+        writer.write_u16(self.offset as u16);
+        writer.write_u64(self.busy);
+        writer.write_bool(self.high_nibble.is_some());
+        writer.write_u8(self.high_nibble.unwrap_or(0));
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        reader.read_into(&mut self.ddram)?;
+        reader.read_into(&mut self.cgram)?;
+        self.address = reader.read_u8()?;
+        self.cgram_selected = reader.read_bool()?;
+        self.increment = reader.read_bool()?;
+        self.shift_display = reader.read_bool()?;
+        self.display_on = reader.read_bool()?;
+        self.cursor = reader.read_bool()?;
+        self.blink = reader.read_bool()?;
+        self.eight_bit = reader.read_bool()?;
+        self.two_lines = reader.read_bool()?;
+        self.large_font = reader.read_bool()?;
+        self.offset = reader.read_u16()? as usize;
+        self.busy = reader.read_u64()?;
+        let pending = reader.read_bool()?;
+        let nibble = reader.read_u8()?;
+        self.high_nibble = if pending { Some(nibble) } else { None };
+        self.changed = true;
+        return Ok(());
+    }
+}
+
+// Character ROM A00, the common Japanese variant. Custom characters from CGRAM and the katakana show
+// as placeholders.
+fn character(code: u8) -> char {
+    return match code {
+        0x00..=0x0F => '▒',
+        0x5C => '¥',
+        0x7E => '→',
+        0x7F => '←',
+        0x20..=0x7D => code as char,
+        0xDF => '°',
+        0xFF => '█',
+        _ => '?',
+    };
+}
+
+// The visible part of an Hd44780, for the host to show. Clones share the same LCD.
+#[derive(Clone)]
+pub struct LcdDisplay {
+    controller: Rc<RefCell<Controller>>,
+}
+
+impl LcdDisplay {
+    // One line per row, blank while the display is off. The cursor is not shown.
+    pub fn text(&self) -> String {
+        return self.controller.borrow().text();
+    }
+
+    // The text framed for printing to a terminal.
+    pub fn render(&self) -> String {
+        let controller = self.controller.borrow();
+        let border = "─".repeat(controller.columns);
+        let mut frame = format!("┌{}┐\n", border);
+        for line in controller.text().lines() {
+            frame.push_str(&format!("│{}│\n", line));
+        }
+        frame.push_str(&format!("└{}┘\n", border));
+        return frame;
+    }
+
+    // Whether anything was written since the last call, to redraw only when needed.
+    pub fn take_changed(&self) -> bool {
+        return std::mem::replace(&mut self.controller.borrow_mut().changed, false);
+    }
+
+    // The address counter and whether an instruction is still executing, as the busy flag read shows them.
+    pub fn address(&self) -> u8 {
+        return self.controller.borrow().address;
+    }
+
+    pub fn busy(&self) -> bool {
+        return self.controller.borrow().busy > 0;
+    }
+}
+
+// Hitachi HD44780 character LCD controller, driven through VIA port pins rather than the bus. It
+// latches writes on the falling edge of E and drives the data lines while E is high with RW set.
+// Instructions sent while the busy flag is set are ignored, as on the real chip. The clock is the
+// one of the VIA in Hz, to turn execution times into cycles:
+//
+//     let lcd = Hd44780::new(16, 2, Wiring::four_bit(), 1_000_000);
+//     let display = lcd.display();
+//     via.connect(Box::new(lcd));
+pub struct Hd44780 {
+    controller: Rc<RefCell<Controller>>,
+    wiring: Wiring,
+    clock: u64,
+    enable: bool,
+}
+
+impl Hd44780 {
+    pub fn new(columns: usize, rows: usize, wiring: Wiring, clock: u64) -> Hd44780 {
+        assert!(columns > 0 && columns <= LINE_LENGTH && rows > 0 && rows <= 4, "unsupported LCD size {}x{}", columns, rows);
+        let controller = Controller::new(columns, rows);
+        return Hd44780 { controller: Rc::new(RefCell::new(controller)), wiring, clock, enable: false };
+    }
+
+    pub fn display(&self) -> LcdDisplay {
+        return LcdDisplay { controller: self.controller.clone() };
+    }
+
+    fn port(&self, pins: &ViaPins, port: Port) -> u8 {
+        return match port {
+            Port::A => pins.port_a(),
+            Port::B => pins.port_b(),
+        };
+    }
+
+    // Drives the data lines, None lets them float high again.
+    fn drive(&self, pins: &ViaPins, data: Option<u8>) {
+        let mask = self.wiring.data_mask();
+        let levels = match (data, self.wiring.nibble) {
+            (None, _) => 0xFF,
+            (Some(data), Some(shift)) => !mask | (data << shift),
+            (Some(data), None) => data,
+        };
+        match self.wiring.data {
+            Port::A => pins.set_port_a(levels),
+            Port::B => pins.set_port_b(levels),
+        }
+    }
+
+    // What a read puts on the data lines, the high nibble first over four lines.
+    fn output(&self, register_select: bool) -> u8 {
+        let controller = self.controller.borrow();
+        let data = controller.read(register_select);
+        return match (self.wiring.nibble, controller.eight_bit) {
+            (Some(_), false) if controller.high_nibble.is_some() => data & 0x0F,
+            (Some(_), _) => data >> 4,
+            (None, _) => data,
+        };
+    }
+
+    // Completes a transfer on the falling edge of E.
+    fn transfer(&mut self, data: u8, register_select: bool, read: bool) {
+        let mut controller = self.controller.borrow_mut();
+        let byte = match (self.wiring.nibble, controller.eight_bit) {
+            (Some(_), false) => match controller.high_nibble.take() {
+                None => {
+                    controller.high_nibble = Some(data & 0x0F);
+                    return;
+                }
+                Some(high) => high << 4 | data & 0x0F,
+            },
+            // D0-D3 are not connected and read as low.
+            (Some(_), true) => (data & 0x0F) << 4,
+            (None, _) => data,
+        };
+
+        if read {
+            // Reading data moves the address counter on like a write does.
+            if register_select {
+                let increment = controller.increment;
+                controller.advance(increment);
+            }
+        } else if controller.busy > 0 {
+            return;
+        } else if register_select {
+            controller.write_data(byte, self.clock);
+        } else {
+            controller.instruction(byte, self.clock);
+        }
+    }
+}
+
+impl Peripheral for Hd44780 {
+    fn update(&mut self, pins: &ViaPins) {
+        let control = self.port(pins, self.wiring.control);
+        let enable = control & self.wiring.enable != 0;
+        let read = control & self.wiring.read_write != 0;
+        let register_select = control & self.wiring.register_select != 0;
+
+        if enable && !self.enable && read {
+            self.drive(pins, Some(self.output(register_select)));
+        } else if !enable && self.enable {
+            let data = self.port(pins, self.wiring.data) & self.wiring.data_mask();
+            self.transfer(data >> self.wiring.nibble.unwrap_or(0), register_select, read);
+        }
+        if !(enable && read) {
+            self.drive(pins, None);
+        }
+        self.enable = enable;
+    }
+
+    fn tick(&mut self, cycles: u64, pins: &ViaPins) {
+        let done = {
+            let mut controller = self.controller.borrow_mut();
+            let busy = controller.busy;
+            controller.busy = busy.saturating_sub(cycles);
+            busy > 0 && controller.busy == 0
+        };
+        // The busy flag drops while a status read is in progress.
+        let control = self.port(pins, self.wiring.control);
+        if done && self.enable && control & (self.wiring.read_write | self.wiring.register_select) == self.wiring.read_write {
+            self.drive(pins, Some(self.output(false)));
+        }
+    }
+}
+
+impl Snapshot for Hd44780 {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        self.controller.borrow().save(writer);
+        writer.write_bool(self.enable);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.controller.borrow_mut().restore(reader)?;
+        self.enable = reader.read_bool()?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::BusBuilder;
+    use crate::components::device::{Addressable, Clocked};
+    use crate::components::via6522::Via6522;
+
+    // Polls the busy flag with the eight bit wiring until it clears, returning the number of polls.
+    fn wait(via: &mut Via6522) -> usize {
+        via.write(0x2, 0x00);
+        let mut polls = 0;
+        loop {
+            via.write(0x1, 0x40); // RW
+            via.write(0x1, 0xC0); // RW | E
+            let status = via.read(0x0);
+            via.write(0x1, 0x40);
+            if status & 0x80 == 0 {
+                break;
+            }
+            via.tick(10);
+            polls += 1;
+        }
+        via.write(0x2, 0xFF);
+        return polls;
+    }
+
+    // Latches a byte with the eight bit wiring, to the instruction register or with RS to data.
+    fn send(via: &mut Via6522, data: u8, register_select: u8) {
+        via.write(0x0, data);
+        via.write(0x1, register_select);
+        via.write(0x1, register_select | 0x80);
+        via.write(0x1, register_select);
+    }
+
+    // Latches a nibble with the four bit wiring: data on PB3-PB0, RS on PB4 and E on PB6.
+    fn nibble(via: &mut Via6522, data: u8) {
+        via.write(0x0, data);
+        via.write(0x0, data | 0x40);
+        via.write(0x0, data);
+        via.tick(2000);
+    }
+
+    #[test]
+    fn test_hd44780() {
+        // Eight data lines on port B, E, RW and RS on PA7-PA5, busy flag polled like the BE6502 demo.
+        let builder = BusBuilder::new();
+        let mut via = Via6522::new(0x6000, builder.interrupts().source("VIA")).unwrap();
+        let lcd = Hd44780::new(16, 2, Wiring::eight_bit(), 1_000_000);
+        let display = lcd.display();
+        via.connect(Box::new(lcd));
+        via.write(0x2, 0xFF); // DDRB
+        via.write(0x3, 0xE0); // DDRA
+
+        for instruction in [0x38, 0x0C, 0x06, 0x01] {
+            wait(&mut via);
+            send(&mut via, instruction, 0x00);
+        }
+        // Clearing takes 1.52 ms, which the busy flag shows.
+        assert!(display.busy());
+        assert_eq!(wait(&mut via), 152);
+        for character in b"Hello" {
+            wait(&mut via);
+            send(&mut via, *character, 0x20);
+        }
+        wait(&mut via);
+        send(&mut via, 0xC0, 0x00); // DDRAM $40, the second line
+        wait(&mut via);
+        send(&mut via, b'!', 0x20);
+        assert_eq!(display.text(), "Hello           \n!               ");
+        assert!(display.take_changed());
+        assert!(!display.take_changed());
+
+        // Writes while busy are lost.
+        send(&mut via, b'?', 0x20);
+        assert_eq!(display.address(), 0x41);
+        wait(&mut via);
+
+        // Shifting the display left by one hides the first column.
+        send(&mut via, 0x18, 0x00);
+        assert!(display.render().contains("│ello            │"));
+
+        // Reading data returns DDRAM and moves the address counter on.
+        wait(&mut via);
+        send(&mut via, 0x80, 0x00);
+        wait(&mut via);
+        via.write(0x2, 0x00);
+        via.write(0x1, 0x60); // RW | RS
+        via.write(0x1, 0xE0);
+        assert_eq!(via.read(0x0), b'H');
+        via.write(0x1, 0x60);
+        assert_eq!(display.address(), 0x01);
+
+        // Four data lines: the init sequence switches from eight bits, then bytes go as two nibbles.
+        let mut via = Via6522::new(0x6000, builder.interrupts().source("VIA")).unwrap();
+        let lcd = Hd44780::new(16, 2, Wiring::four_bit(), 1_000_000);
+        let display = lcd.display();
+        via.connect(Box::new(lcd));
+        via.write(0x2, 0xFF);
+        nibble(&mut via, 0x02); // function set: four bits
+        for byte in [0x28, 0x0E, 0x06, 0x01] {
+            nibble(&mut via, byte >> 4);
+            nibble(&mut via, byte & 0x0F);
+        }
+        for character in b"4 bit" {
+            nibble(&mut via, 0x10 | character >> 4);
+            nibble(&mut via, 0x10 | character & 0x0F);
+        }
+        assert_eq!(display.text().lines().next(), Some("4 bit           "));
+
+        // The busy flag is read as two nibbles too, D7 on PB3.
+        nibble(&mut via, 0x00);
+        via.write(0x0, 0x01); // clear, without waiting for it
+        via.write(0x0, 0x41);
+        via.write(0x0, 0x01);
+        via.write(0x2, 0xF0);
+        via.write(0x0, 0x20);
+        via.write(0x0, 0x60);
+        assert_eq!(via.read(0x0) & 0x0F, 0x08);
+        via.write(0x0, 0x20);
+        via.write(0x0, 0x60);
+        assert_eq!(via.read(0x0) & 0x0F, 0x00);
+        via.write(0x0, 0x20);
+    }
+}
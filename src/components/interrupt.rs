@@ -0,0 +1,189 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// Every source owns one bit of the IRQ and NMI masks.
+const MAX_SOURCES: usize = 32;
+
+#[derive(Debug, Default)]
+struct Lines {
+    irq: Cell<u32>,
+    nmi: Cell<u32>,
+    // Set on the falling edge of NMI, cleared once the CPU has seen it.
+    nmi_edge: Cell<bool>,
+    names: RefCell<Vec<String>>,
+}
+
+// The IRQ and NMI lines of a bus. Both are open collector, so the line is asserted as long as any
+// source pulls it. IRQ is level triggered, NMI is edge triggered: only a transition from released to
+// asserted is latched for the CPU, holding it asserted does not trigger again.
+//
+// Clones share the same lines. Devices are handed an InterruptSource before they are attached:
+//
+//     let builder = BusBuilder::new();
+//     let via = Via6522::new(0x6000, builder.interrupts().source("VIA"))?;
+#[derive(Debug, Clone, Default)]
+pub struct InterruptLines {
+    lines: Rc<Lines>,
+}
+
+impl InterruptLines {
+    pub fn new() -> InterruptLines {
+        return InterruptLines::default();
+    }
+
+    // Registers a device which may pull the lines, the name is only used for reporting.
+    pub fn source(&self, name: &str) -> InterruptSource {
+        let mut names = self.lines.names.borrow_mut();
+        assert!(names.len() < MAX_SOURCES, "at most {} interrupt sources can share a bus", MAX_SOURCES);
+        names.push(String::from(name));
+        return InterruptSource { lines: self.lines.clone(), mask: 1 << (names.len() - 1) };
+    }
+
+    pub fn irq(&self) -> bool {
+        return self.lines.irq.get() != 0;
+    }
+
+    // One bit per source currently pulling IRQ, see InterruptSource::mask.
+    pub fn irq_sources(&self) -> u32 {
+        return self.lines.irq.get();
+    }
+
+    // Names of the sources currently pulling IRQ, in registration order.
+    pub fn asserting_irq(&self) -> Vec<String> {
+        let irq = self.lines.irq.get();
+        return self.lines.names.borrow().iter().enumerate()
+            .filter(|(index, _)| irq & (1 << index) != 0)
+            .map(|(_, name)| name.clone())
+            .collect();
+    }
+
+    pub fn nmi(&self) -> bool {
+        return self.lines.nmi.get() != 0;
+    }
+
+    pub fn nmi_pending(&self) -> bool {
+        return self.lines.nmi_edge.get();
+    }
+
+    // Returns whether an NMI edge was latched and clears the latch.
+    pub fn take_nmi(&self) -> bool {
+        return self.lines.nmi_edge.replace(false);
+    }
+}
+
+impl Snapshot for InterruptLines {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.lines.irq.get());
+        writer.write_u32(self.lines.nmi.get());
+        writer.write_bool(self.lines.nmi_edge.get());
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.lines.irq.set(reader.read_u32()?);
+        self.lines.nmi.set(reader.read_u32()?);
+        self.lines.nmi_edge.set(reader.read_bool()?);
+        return Ok(());
+    }
+}
+
+// A device's connection to the interrupt lines of a bus.
+#[derive(Debug, Clone)]
+pub struct InterruptSource {
+    lines: Rc<Lines>,
+    mask: u32,
+}
+
+impl InterruptSource {
+    pub fn set_irq(&self, asserted: bool) {
+        let irq = self.lines.irq.get();
+        self.lines.irq.set(if asserted { irq | self.mask } else { irq & !self.mask });
+    }
+
+    pub fn set_nmi(&self, asserted: bool) {
+        let nmi = self.lines.nmi.get();
+        if asserted && nmi == 0 {
+            self.lines.nmi_edge.set(true);
+        }
+        self.lines.nmi.set(if asserted { nmi | self.mask } else { nmi & !self.mask });
+    }
+
+    pub fn irq_asserted(&self) -> bool {
+        return self.lines.irq.get() & self.mask != 0;
+    }
+
+    // The bit of this source in InterruptLines::irq_sources.
+    pub fn mask(&self) -> u32 {
+        return self.mask;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::components::bus::BusBuilder;
+    use crate::components::cpu6502::{CPU6502, Flags};
+    use crate::components::memory::RandomAccessMemory;
+
+    #[test]
+    fn test_interrupts() {
+        let builder = BusBuilder::new();
+        let timer = builder.interrupts().source("Timer");
+        let serial = builder.interrupts().source("Serial");
+        let mut bus = builder.attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap())).build().unwrap();
+        let mut cpu = CPU6502::new();
+
+        // Wired-OR: the line stays asserted until every source has released it.
+        timer.set_irq(true);
+        serial.set_irq(true);
+        serial.set_irq(false);
+        assert!(bus.interrupts().irq());
+        assert_eq!(bus.interrupts().irq_sources(), timer.mask());
+        assert_eq!(bus.interrupts().asserting_irq(), vec!["Timer"]);
+
+        bus.write(0xFFFA, 0x00); // NMI vector
+        bus.write(0xFFFB, 0x30);
+        bus.write(0xFFFE, 0x00); // IRQ/BRK vector
+        bus.write(0xFFFF, 0x40);
+        bus.write(0x0200, 0x58); // CLI
+        bus.write(0x0201, 0x18); // CLC
+        bus.write(0x0202, 0x18); // CLC
+        cpu.registers_mut().program_counter = 0x0200;
+        cpu.registers_mut().stack_pointer = 0xFF;
+        cpu.registers_mut().status_flags = Flags::InterruptDisable as u8;
+
+        // CLI only takes effect after the following instruction.
+        cpu.tick(&mut bus);
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x0202);
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x4000);
+        assert_eq!(cpu.registers().stack_pointer, 0xFC);
+        assert_eq!((bus.peek(0x01FF), bus.peek(0x01FE), bus.peek(0x01FD)), (0x02, 0x02, 0x20));
+        assert!(cpu.registers().status_flags & Flags::InterruptDisable as u8 != 0);
+
+        // NMI only triggers on the edge, not while it is held.
+        timer.set_irq(false);
+        bus.write(0x4000, 0x18); // CLC
+        bus.write(0x4001, 0x18); // CLC
+        timer.set_nmi(true);
+        cpu.tick(&mut bus);
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x3000);
+        serial.set_nmi(true);
+        assert!(!bus.interrupts().nmi_pending());
+        assert!(bus.interrupts().nmi());
+
+        // An NMI arriving during BRK hijacks it, B is still pushed.
+        timer.set_nmi(false);
+        assert!(bus.interrupts().nmi());
+        serial.set_nmi(false);
+        assert!(!bus.interrupts().nmi());
+        timer.set_nmi(true);
+        bus.write(0x3000, 0x00); // BRK
+        cpu.tick(&mut bus);
+        assert_eq!(cpu.registers().program_counter, 0x3000);
+        assert_eq!(bus.peek(0x0100 | (cpu.registers().stack_pointer as u16 + 1)) & 0x30, 0x30);
+        assert_eq!(bus.peek(0x0100 | (cpu.registers().stack_pointer as u16 + 2)), 0x02);
+    }
+}
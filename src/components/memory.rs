@@ -1,27 +1,271 @@
-// Testing... I don't want to mess with compile-time non-constant struct sizes yet...
-const MEMORY_SIZE: usize = 0x7fff;
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::components::device::SharedMemory;
+use crate::components::snapshot::{SnapshotError, SnapshotReader, SnapshotWriter};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    InvalidSize(usize),
+    // The memory would extend past $FFFF.
+    OutOfAddressSpace { address: u16, size: usize },
+    // The mapped window of a ROM has to be a multiple of its size.
+    InvalidWindow { size: usize, window: usize },
+    Io(String),
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            MemoryError::InvalidSize(size) => write!(f, "invalid memory size {:#x}", size),
+            MemoryError::OutOfAddressSpace { address, size } => write!(f, "{:#x} bytes at ${:04X} exceed the address space", size, address),
+            MemoryError::InvalidWindow { size, window } => write!(f, "{:#x} bytes cannot be mirrored through a {:#x} byte window", size, window),
+            MemoryError::Io(error) => write!(f, "could not load memory contents: {}", error),
+        };
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+fn validate(address: u16, size: usize) -> Result<(), MemoryError> {
+    if size == 0 || size > 0x10000 {
+        return Err(MemoryError::InvalidSize(size));
+    }
+    if address as usize + size > 0x10000 {
+        return Err(MemoryError::OutOfAddressSpace { address, size });
+    }
+    return Ok(());
+}
 
 pub struct RandomAccessMemory {
     address: u16,
-    data: [u8; MEMORY_SIZE],
+    data: SharedMemory,
 }
 
 impl RandomAccessMemory {
-    pub fn new(address : u16) -> RandomAccessMemory {
-        return RandomAccessMemory { address, data: [0; MEMORY_SIZE] };
+    // `size` bytes of memory starting at `address`, anything from a single byte up to the full 64K.
+    pub fn new(address : u16, size: usize) -> Result<RandomAccessMemory, MemoryError> {
+        validate(address, size)?;
+        return Ok(RandomAccessMemory { address, data: Rc::from(vec![Cell::new(0); size]) });
     }
 }
 
 impl crate::components::device::Addressable for RandomAccessMemory {
     fn get_address_space(&self) -> (u16, u16) {
-        return (self.address, 0x7fff);
+        return (self.address, (self.address as usize + self.data.len() - 1) as u16);
     }
 
-    fn read(&self, address: u16) -> u8 {
-        return self.data[address as usize];
+    // Addresses are relative to the start of the memory.
+    fn peek(&self, address: u16) -> u8 {
+        return self.data[address as usize].get();
     }
 
     fn write(&mut self, address: u16, data: u8) {
-        self.data[address as usize] = data;
+        self.data[address as usize].set(data);
+    }
+
+    fn memory(&self) -> Option<SharedMemory> {
+        return Some(self.data.clone());
+    }
+
+    fn name(&self) -> &str {
+        return "RAM";
     }
-}
\ No newline at end of file
+}
+
+impl crate::components::snapshot::Snapshot for RandomAccessMemory {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        let data: Vec<u8> = self.data.iter().map(Cell::get).collect();
+        writer.write_bytes(&data);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let mut data = vec![0; self.data.len()];
+        reader.read_into(&mut data)?;
+        for (cell, byte) in self.data.iter().zip(data) {
+            cell.set(byte);
+        }
+        return Ok(());
+    }
+}
+
+// What a ReadOnlyMemory does when the CPU writes to it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WritePolicy {
+    Ignore,
+    // Report the write on stderr, stdout belongs to the emulated machine.
+    Log,
+    // Treat the write as a fault, the first one is kept for the runner to check through `fault`.
+    Error,
+}
+
+// First (address, data) written to a ROM with the Error policy, shared so it can still be checked once
+// the ROM is attached to a bus.
+#[derive(Debug, Clone, Default)]
+pub struct RomFault(Rc<Cell<Option<(u16, u8)>>>);
+
+impl RomFault {
+    // Returns and clears the recorded write.
+    pub fn take(&self) -> Option<(u16, u8)> {
+        return self.0.take();
+    }
+}
+
+pub struct ReadOnlyMemory {
+    address: u16,
+    data: Vec<u8>,
+    // Number of addresses answered for, the contents repeat throughout.
+    window: usize,
+    policy: WritePolicy,
+    fault: RomFault,
+}
+
+impl ReadOnlyMemory {
+    pub fn new(address: u16, data: &[u8]) -> Result<ReadOnlyMemory, MemoryError> {
+        validate(address, data.len())?;
+        return Ok(ReadOnlyMemory { address, data: data.to_vec(), window: data.len(), policy: WritePolicy::Ignore, fault: RomFault::default() });
+    }
+
+    pub fn from_file(address: u16, path: impl AsRef<std::path::Path>) -> Result<ReadOnlyMemory, MemoryError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|error| MemoryError::Io(format!("{}: {}", path.display(), error)))?;
+        return ReadOnlyMemory::new(address, &data);
+    }
+
+    // Mirrors the contents through a larger window, e.g. an 8K ROM answering for $C000-$FFFF.
+    pub fn with_window(mut self, window: usize) -> Result<ReadOnlyMemory, MemoryError> {
+        if window < self.data.len() || !window.is_multiple_of(self.data.len()) {
+            return Err(MemoryError::InvalidWindow { size: self.data.len(), window });
+        }
+        validate(self.address, window)?;
+        self.window = window;
+        return Ok(self);
+    }
+
+    pub fn with_write_policy(mut self, policy: WritePolicy) -> ReadOnlyMemory {
+        self.policy = policy;
+        return self;
+    }
+
+    pub fn fault(&self) -> RomFault {
+        return self.fault.clone();
+    }
+
+    // FNV-1a, identifies the loaded image in snapshots.
+    fn checksum(&self) -> u32 {
+        return self.data.iter().fold(0x811C9DC5u32, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193));
+    }
+}
+
+impl crate::components::device::Addressable for ReadOnlyMemory {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (self.address, (self.address as usize + self.window - 1) as u16);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        return self.data[address as usize % self.data.len()];
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        let absolute = self.address.wrapping_add(address);
+        match self.policy {
+            WritePolicy::Ignore => {}
+            WritePolicy::Log => eprintln!("WriteToROM@{:#06x}={:#04x} - ignored", absolute, data),
+            WritePolicy::Error => {
+                if self.fault.0.get().is_none() {
+                    self.fault.0.set(Some((absolute, data)));
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        return "ROM";
+    }
+}
+
+// Contents are not saved, only checked to be the same image on restore.
+impl crate::components::snapshot::Snapshot for ReadOnlyMemory {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.data.len() as u32);
+        writer.write_u32(self.checksum());
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let (size, checksum) = (reader.read_u32()? as usize, reader.read_u32()?);
+        if size != self.data.len() || checksum != self.checksum() {
+            return Err(SnapshotError::Mismatch(format!("ROM at {:#06x} holds a different image", self.address)));
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::{Bus, BusBuilder, MapError};
+    use crate::components::testing::TempPath;
+
+    #[test]
+    fn test_memory_placement() {
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x4000).unwrap()))
+            .attach(Box::new(RandomAccessMemory::new(0x8000, 0x8000).unwrap()))
+            .build()
+            .unwrap();
+        bus.write(0x8000, 0x01);
+        bus.write(0xFFFF, 0x02);
+        bus.write(0x3FFF, 0x03);
+        assert_eq!((bus.read(0x8000), bus.read(0xFFFF), bus.read(0x3FFF)), (0x01, 0x02, 0x03));
+        assert_eq!(bus.device_states()[1].1[4], 0x01);
+
+        let mut bus = Bus::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap());
+        bus.write(0xFFFF, 0x04);
+        assert_eq!(bus.read(0xFFFF), 0x04);
+
+        let too_small = BusBuilder::new()
+            .map(0x0000, 0x1FFF, Box::new(RandomAccessMemory::new(0x0000, 0x0800).unwrap()))
+            .build();
+        assert_eq!(too_small.err(), Some(MapError::DeviceTooSmall { start: 0x0000, end: 0x1FFF, size: 0x0800 }));
+
+        assert_eq!(RandomAccessMemory::new(0x0000, 0).err(), Some(MemoryError::InvalidSize(0)));
+        assert_eq!(RandomAccessMemory::new(0x0000, 0x10001).err(), Some(MemoryError::InvalidSize(0x10001)));
+        assert_eq!(RandomAccessMemory::new(0xC000, 0x8000).err(), Some(MemoryError::OutOfAddressSpace { address: 0xC000, size: 0x8000 }));
+    }
+
+    #[test]
+    fn test_read_only_memory() {
+        let mut image = vec![0xEA; 0x2000];
+        image[0x1FFC] = 0x00; // Reset vector $E000
+        image[0x1FFD] = 0xE0;
+
+        let file = TempPath::file("rom.bin", &image);
+        let rom = ReadOnlyMemory::from_file(0xC000, file.path()).unwrap().with_window(0x4000).unwrap();
+
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap()))
+            .attach(Box::new(rom))
+            .build()
+            .unwrap();
+
+        assert_eq!((bus.read(0xFFFC), bus.read(0xFFFD)), (0x00, 0xE0));
+        assert_eq!((bus.read(0xDFFC), bus.read(0xDFFD)), (0x00, 0xE0));
+        bus.write(0xFFFC, 0x12);
+        assert_eq!(bus.read(0xFFFC), 0x00);
+
+        assert_eq!(ReadOnlyMemory::new(0xC000, &image).unwrap().with_window(0x3000).err(),
+            Some(MemoryError::InvalidWindow { size: 0x2000, window: 0x3000 }));
+        assert!(matches!(ReadOnlyMemory::from_file(0xC000, "does/not/exist.bin"), Err(MemoryError::Io(_))));
+
+        // Faulting writes are recorded, not applied, and only the first one is kept.
+        let strict = ReadOnlyMemory::new(0xC000, &image).unwrap().with_write_policy(WritePolicy::Error);
+        let fault = strict.fault();
+        let mut bus = Bus::with_device(Box::new(strict));
+        bus.write(0xC001, 0x12);
+        bus.write(0xC002, 0x34);
+        assert_eq!(bus.read(0xC001), 0xEA);
+        assert_eq!(fault.take(), Some((0xC001, 0x12)));
+        assert_eq!(fault.take(), None);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::components::cpu6502::{OperationCode, Registers};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccessKind {
+    OpcodeFetch,
+    Operand,
+    Data,
+    Stack,
+    Vector,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupt {
+    Reset,
+    Break,
+    IRQ,
+    NMI,
+}
+
+// Hooks into the execution of a CPU6502, e.g. for profilers and test oracles. Every callback has an
+// empty default so observers only implement what they need. Without attached observers the CPU
+// skips all notifications after a single emptiness check.
+pub trait CpuObserver {
+    fn before_instruction(&mut self, _opcode: &OperationCode, _registers: &Registers) {}
+    fn after_instruction(&mut self, _opcode: &OperationCode, _registers: &Registers) {}
+    fn read(&mut self, _address: u16, _data: u8, _kind: AccessKind) {}
+    fn write(&mut self, _address: u16, _data: u8, _kind: AccessKind) {}
+    // Called once the return state has been pushed and the program counter points at the handler.
+    fn interrupt(&mut self, _interrupt: Interrupt, _registers: &Registers) {}
+    // Called when a trap stood in for the routine at the address, once it returned to the caller.
+    fn trap(&mut self, _address: u16, _registers: &Registers) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::Bus;
+    use crate::components::cpu6502::CPU6502;
+    use crate::components::memory::RandomAccessMemory;
+
+    #[test]
+    fn test_observer() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Recorder(Rc<RefCell<Vec<String>>>);
+
+        impl CpuObserver for Recorder {
+            fn before_instruction(&mut self, opcode: &OperationCode, _registers: &Registers) {
+                self.0.borrow_mut().push(format!("before {:?}", opcode.instruction()));
+            }
+
+            fn after_instruction(&mut self, _opcode: &OperationCode, registers: &Registers) {
+                self.0.borrow_mut().push(format!("after A={:#04x}", registers.accumulator));
+            }
+
+            fn read(&mut self, address: u16, _data: u8, kind: AccessKind) {
+                self.0.borrow_mut().push(format!("read {:#06x} {:?}", address, kind));
+            }
+
+            fn write(&mut self, address: u16, data: u8, kind: AccessKind) {
+                self.0.borrow_mut().push(format!("write {:#06x}={:#04x} {:?}", address, data, kind));
+            }
+        }
+
+        let mut cpu = CPU6502::new();
+        let mut bus = Bus::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        cpu.attach_observer(Box::new(Recorder(events.clone())));
+
+        bus.write(0x0000, 0x06); // ASL ZeroPage
+        bus.write(0x0001, 0x10);
+        bus.write(0x0010, 0x01);
+        cpu.tick(&mut bus);
+
+        assert_eq!(*events.borrow(), vec![
+            "read 0x0000 OpcodeFetch",
+            "before ASL",
+            "read 0x0001 Operand",
+            "read 0x0010 Data",
+            "write 0x0010=0x02 Data",
+            "after A=0x00",
+        ]);
+    }
+}
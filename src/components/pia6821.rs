@@ -0,0 +1,451 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::components::device::{Addressable, Clocked};
+use crate::components::interrupt::InterruptSource;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// Control register bits.
+const C1_ENABLE: u8 = 0x01;
+const C1_POSITIVE: u8 = 0x02;
+const SELECT_PERIPHERAL: u8 = 0x04;
+const C2_ENABLE: u8 = 0x08;
+const C2_POSITIVE: u8 = 0x10;
+const C2_OUTPUT: u8 = 0x20;
+const IRQ2: u8 = 0x40;
+const IRQ1: u8 = 0x80;
+
+// One side of the PIA: a port with its data direction and control register and the C1/C2 lines.
+#[derive(Default)]
+struct Side {
+    output: u8,
+    ddr: u8,
+    control: u8,
+    // Levels driven onto the port pins from outside.
+    input: u8,
+    c1: bool,
+    c2: bool,
+    // C2 is pulsed low for a single cycle.
+    pulse: bool,
+}
+
+impl Side {
+    fn new() -> Side {
+        return Side { input: 0xFF, c1: true, c2: true, ..Side::default() };
+    }
+
+    fn pins(&self) -> u8 {
+        return (self.output & self.ddr) | (self.input & !self.ddr);
+    }
+
+    fn irq(&self) -> bool {
+        return (self.control & IRQ1 != 0 && self.control & C1_ENABLE != 0)
+            || (self.control & IRQ2 != 0 && self.control & C2_ENABLE != 0 && self.control & C2_OUTPUT == 0);
+    }
+
+    fn write_control(&mut self, data: u8) {
+        // The flags are read-only.
+        self.control = (self.control & (IRQ1 | IRQ2)) | (data & 0x3F);
+        if data & C2_OUTPUT == 0 {
+            return;
+        }
+        if data & C2_POSITIVE != 0 {
+            // Manual output, C2 follows bit 3.
+            self.c2 = data & C2_ENABLE != 0;
+        } else {
+            self.c2 = true;
+        }
+    }
+
+    // Handshake or pulse output on C2 after the port was accessed.
+    fn strobe(&mut self) {
+        if self.control & (C2_OUTPUT | C2_POSITIVE) == C2_OUTPUT {
+            self.c2 = false;
+            self.pulse = self.control & C2_ENABLE != 0;
+        }
+    }
+
+    fn set_c1(&mut self, level: bool) {
+        if level != self.c1 {
+            self.c1 = level;
+            if level == (self.control & C1_POSITIVE != 0) {
+                self.control |= IRQ1;
+                // A handshake ends with the active C1 transition.
+                if self.control & (C2_OUTPUT | C2_POSITIVE | C2_ENABLE) == C2_OUTPUT {
+                    self.c2 = true;
+                }
+            }
+        }
+    }
+
+    fn set_c2(&mut self, level: bool) {
+        if self.control & C2_OUTPUT == 0 && level != self.c2 {
+            self.c2 = level;
+            if level == (self.control & C2_POSITIVE != 0) {
+                self.control |= IRQ2;
+            }
+        }
+    }
+
+    fn save(&self, writer: &mut SnapshotWriter) {
+        for byte in [self.output, self.ddr, self.control, self.input] {
+            writer.write_u8(byte);
+        }
+        for level in [self.c1, self.c2, self.pulse] {
+            writer.write_bool(level);
+        }
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.output = reader.read_u8()?;
+        self.ddr = reader.read_u8()?;
+        self.control = reader.read_u8()?;
+        self.input = reader.read_u8()?;
+        self.c1 = reader.read_bool()?;
+        self.c2 = reader.read_bool()?;
+        self.pulse = reader.read_bool()?;
+        return Ok(());
+    }
+}
+
+struct State {
+    a: Side,
+    b: Side,
+    irq: InterruptSource,
+}
+
+impl State {
+    fn update_irq(&mut self) {
+        self.irq.set_irq(self.a.irq() || self.b.irq());
+    }
+
+    fn peek(&self, register: u16) -> u8 {
+        return match register {
+            0 if self.a.control & SELECT_PERIPHERAL != 0 => self.a.pins(),
+            0 => self.a.ddr,
+            1 => self.a.control,
+            // Port B reads its output register for output bits.
+            2 if self.b.control & SELECT_PERIPHERAL != 0 => (self.b.output & self.b.ddr) | (self.b.input & !self.b.ddr),
+            2 => self.b.ddr,
+            _ => self.b.control,
+        };
+    }
+
+    // Reading a port clears its interrupt flags, port A also strobes CA2.
+    fn read(&mut self, register: u16) -> u8 {
+        let data = self.peek(register);
+        match register {
+            0 if self.a.control & SELECT_PERIPHERAL != 0 => {
+                self.a.control &= !(IRQ1 | IRQ2);
+                self.a.strobe();
+            }
+            2 if self.b.control & SELECT_PERIPHERAL != 0 => self.b.control &= !(IRQ1 | IRQ2),
+            _ => {}
+        }
+        self.update_irq();
+        return data;
+    }
+
+    // Writing port B strobes CB2.
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 if self.a.control & SELECT_PERIPHERAL != 0 => self.a.output = data,
+            0 => self.a.ddr = data,
+            1 => self.a.write_control(data),
+            2 if self.b.control & SELECT_PERIPHERAL != 0 => {
+                self.b.output = data;
+                self.b.strobe();
+            }
+            2 => self.b.ddr = data,
+            _ => self.b.write_control(data),
+        }
+        self.update_irq();
+    }
+
+    fn outputs(&self) -> (u8, u8, u8, u8, bool, bool) {
+        return (self.a.pins(), self.b.pins(), self.a.ddr, self.b.ddr, self.a.c2, self.b.c2);
+    }
+}
+
+// The port and control line pins of a Pia6821, for host code emulating what is wired to them. Clones
+// share the same PIA. Inputs are pulled high until driven.
+#[derive(Clone)]
+pub struct PiaPins {
+    state: Rc<RefCell<State>>,
+}
+
+impl PiaPins {
+    pub fn port_a(&self) -> u8 {
+        return self.state.borrow().a.pins();
+    }
+
+    pub fn port_b(&self) -> u8 {
+        return self.state.borrow().b.pins();
+    }
+
+    pub fn ddr_a(&self) -> u8 {
+        return self.state.borrow().a.ddr;
+    }
+
+    pub fn ddr_b(&self) -> u8 {
+        return self.state.borrow().b.ddr;
+    }
+
+    pub fn set_port_a(&self, levels: u8) {
+        self.state.borrow_mut().a.input = levels;
+    }
+
+    pub fn set_port_b(&self, levels: u8) {
+        self.state.borrow_mut().b.input = levels;
+    }
+
+    pub fn set_ca1(&self, level: bool) {
+        let mut state = self.state.borrow_mut();
+        state.a.set_c1(level);
+        state.update_irq();
+    }
+
+    pub fn set_cb1(&self, level: bool) {
+        let mut state = self.state.borrow_mut();
+        state.b.set_c1(level);
+        state.update_irq();
+    }
+
+    // Ignored while the line is an output.
+    pub fn set_ca2(&self, level: bool) {
+        let mut state = self.state.borrow_mut();
+        state.a.set_c2(level);
+        state.update_irq();
+    }
+
+    pub fn set_cb2(&self, level: bool) {
+        let mut state = self.state.borrow_mut();
+        state.b.set_c2(level);
+        state.update_irq();
+    }
+
+    pub fn ca2(&self) -> bool {
+        return self.state.borrow().a.c2;
+    }
+
+    pub fn cb2(&self) -> bool {
+        return self.state.borrow().b.c2;
+    }
+
+    // Whether an active CA1 transition is waiting to be acknowledged by reading port A.
+    pub fn ca1_flag(&self) -> bool {
+        return self.state.borrow().a.control & IRQ1 != 0;
+    }
+}
+
+// Something wired to the pins of a PIA rather than to the bus. It is updated whenever the levels driven
+// by the PIA change and can drive the input pins in response.
+pub trait Peripheral: Snapshot {
+    fn update(&mut self, pins: &PiaPins);
+}
+
+// Motorola 6821 Peripheral Interface Adapter: two 8 bit ports, each behind a control register which
+// selects between the data direction and the peripheral register, with two control lines per port.
+// Both IRQ outputs are wired to the same interrupt source.
+pub struct Pia6821 {
+    address: u16,
+    state: Rc<RefCell<State>>,
+    peripherals: Vec<Box<dyn Peripheral>>,
+}
+
+impl Pia6821 {
+    pub fn new(address: u16, irq: InterruptSource) -> Pia6821 {
+        let state = State { a: Side::new(), b: Side::new(), irq };
+        return Pia6821 { address, state: Rc::new(RefCell::new(state)), peripherals: Vec::new() };
+    }
+
+    pub fn pins(&self) -> PiaPins {
+        return PiaPins { state: self.state.clone() };
+    }
+
+    pub fn connect(&mut self, peripheral: Box<dyn Peripheral>) {
+        self.peripherals.push(peripheral);
+        self.notify();
+    }
+
+    fn notify(&mut self) {
+        let pins = self.pins();
+        for peripheral in &mut self.peripherals {
+            peripheral.update(&pins);
+        }
+    }
+
+    fn access<T>(&mut self, access: impl FnOnce(&mut State) -> T) -> T {
+        let outputs = self.state.borrow().outputs();
+        let result = access(&mut self.state.borrow_mut());
+        if self.state.borrow().outputs() != outputs {
+            self.notify();
+        }
+        return result;
+    }
+}
+
+impl Addressable for Pia6821 {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (self.address, self.address + 0x03);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        return self.state.borrow().peek(address & 0x03);
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        return self.access(|state| state.read(address & 0x03));
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.access(|state| state.write(address & 0x03, data));
+    }
+
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        return Some(self);
+    }
+
+    fn name(&self) -> &str {
+        return "PIA";
+    }
+}
+
+// Only needed to end C2 pulses.
+impl Clocked for Pia6821 {
+    fn tick(&mut self, cycles: u64) {
+        if cycles == 0 {
+            return;
+        }
+        self.access(|state| {
+            for side in [&mut state.a, &mut state.b] {
+                if side.pulse {
+                    side.pulse = false;
+                    side.c2 = true;
+                }
+            }
+        });
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        let state = self.state.borrow();
+        return if state.a.pulse || state.b.pulse { Some(1) } else { None };
+    }
+}
+
+impl Snapshot for Pia6821 {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        let state = self.state.borrow();
+        state.a.save(writer);
+        state.b.save(writer);
+        for peripheral in &self.peripherals {
+            peripheral.save(writer);
+        }
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        {
+            let mut state = self.state.borrow_mut();
+            state.a.restore(reader)?;
+            state.b.restore(reader)?;
+            state.update_irq();
+        }
+        for peripheral in &mut self.peripherals {
+            peripheral.restore(reader)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::BusBuilder;
+    use crate::components::interrupt::InterruptLines;
+
+    #[test]
+    fn test_pia6821() {
+        let builder = BusBuilder::new();
+        let mut pia = Pia6821::new(0xD010, builder.interrupts().source("PIA"));
+        let pins = pia.pins();
+
+        // Control bit 2 switches between the data direction and the peripheral register.
+        pia.write(0x0, 0x0F); // DDRA
+        pia.write(0x1, 0x04);
+        pia.write(0x0, 0xA5);
+        pins.set_port_a(0x30);
+        assert_eq!(pia.read(0x0), 0x35);
+
+        // An active CA1 edge sets the flag and interrupts, reading the port acknowledges it.
+        pia.write(0x1, 0x07); // positive edge, interrupt enabled
+        pins.set_ca1(false);
+        assert!(!builder.interrupts().irq());
+        pins.set_ca1(true);
+        assert!(builder.interrupts().irq());
+        assert_eq!(pia.peek(0x1) & 0x80, 0x80);
+        pia.read(0x0);
+        assert!(!builder.interrupts().irq());
+
+        // Writing port B in handshake mode pulls CB2 low until CB1 answers.
+        pia.write(0x3, 0x26); // CB2 handshake, positive CB1
+        assert!(pins.cb2());
+        pia.write(0x2, 0x41);
+        assert!(!pins.cb2());
+        pins.set_cb1(false);
+        pins.set_cb1(true);
+        assert!(pins.cb2());
+    }
+
+    #[test]
+    fn test_pia_pins() {
+        let lines = InterruptLines::new();
+        let mut pia = Pia6821::new(0xD010, lines.source("PIA"));
+        let pins = pia.pins();
+
+        // The pins show the outputs where the data direction says so, the inputs elsewhere.
+        pia.write(0x0, 0x0F); // DDRA
+        pia.write(0x2, 0xF0); // DDRB
+        assert_eq!((pins.ddr_a(), pins.ddr_b()), (0x0F, 0xF0));
+        pia.write(0x1, 0x04);
+        pia.write(0x3, 0x04);
+        pia.write(0x0, 0xA5);
+        pia.write(0x2, 0xA5);
+        pins.set_port_a(0x30);
+        pins.set_port_b(0x03);
+        assert_eq!((pins.port_a(), pins.port_b()), (0x35, 0xA3));
+
+        // C2 inputs set their flag on the selected edge and interrupt when enabled.
+        pia.write(0x1, 0x0C); // CA2 negative edge, interrupt enabled
+        pins.set_ca2(false);
+        assert_eq!(pia.peek(0x1) & 0x40, 0x40);
+        assert!(lines.irq());
+        pia.read(0x0);
+        assert!(!lines.irq());
+        pia.write(0x3, 0x14); // CB2 positive edge, interrupt disabled
+        pins.set_cb2(false);
+        assert_eq!(pia.peek(0x3) & 0x40, 0x00);
+        pins.set_cb2(true);
+        assert_eq!(pia.peek(0x3) & 0x40, 0x40);
+        assert!(!lines.irq());
+
+        // As an output C2 ignores the pin, in manual mode it follows control bit 3.
+        pia.write(0x1, 0x34);
+        assert!(!pins.ca2());
+        pins.set_ca2(true);
+        assert!(!pins.ca2());
+        pia.write(0x1, 0x3C);
+        assert!(pins.ca2());
+
+        // Reading port A pulses CA2 low for a cycle, or holds it low until the active CA1 edge.
+        pia.write(0x1, 0x2C); // CA2 pulse
+        pia.read(0x0);
+        assert!(!pins.ca2());
+        pia.tick(1);
+        assert!(pins.ca2());
+        pia.write(0x1, 0x24); // CA2 handshake, negative CA1
+        pia.read(0x0);
+        assert!(!pins.ca2());
+        pins.set_ca1(false);
+        assert!(pins.ca2());
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+
+use crate::components::bus::Bus;
+use crate::components::cpu6502::CPU6502;
+use crate::components::snapshot::{self, Snapshot, SnapshotReader, SnapshotWriter};
+
+struct Checkpoint {
+    cycles: u64,
+    state: Vec<u8>,
+}
+
+// One executed instruction: the CPU and device state before it and the previous value of every byte it
+// wrote.
+struct JournalEntry {
+    cpu: Vec<u8>,
+    devices: Vec<u8>,
+    writes: Vec<(u16, u8)>,
+}
+
+// Rewind buffer for stepping backwards through execution. A full snapshot is taken every `interval`
+// cycles and the memory writes of every instruction in between are journaled, along with the state of
+// the devices that are not plain memory. At most `capacity` snapshots are kept, the oldest are dropped
+// first, which bounds both the snapshots and the journal.
+//
+// Going back within the journal undoes the writes of each instruction. Going back further restores the
+// nearest older snapshot and executes forward again, which relies on the machine being deterministic.
+pub struct Rewind {
+    interval: u64,
+    capacity: usize,
+    checkpoints: VecDeque<Checkpoint>,
+    // Instructions executed since the newest checkpoint.
+    journal: Vec<JournalEntry>,
+}
+
+impl Rewind {
+    pub fn new(interval: u64, capacity: usize) -> Rewind {
+        assert!(capacity > 0, "rewind buffer needs room for at least one checkpoint");
+        return Rewind { interval, capacity, checkpoints: VecDeque::new(), journal: Vec::new() };
+    }
+
+    // Executes one instruction, recording what is needed to undo it.
+    pub fn tick(&mut self, cpu: &mut CPU6502, bus: &mut Bus) {
+        let due = match self.checkpoints.back() {
+            Some(checkpoint) => cpu.cycles() >= checkpoint.cycles + self.interval,
+            None => true,
+        };
+        if due {
+            self.checkpoints.push_back(Checkpoint { cycles: cpu.cycles(), state: snapshot::save(cpu, bus) });
+            if self.checkpoints.len() > self.capacity {
+                self.checkpoints.pop_front();
+            }
+            self.journal.clear();
+        }
+
+        self.record(cpu, bus);
+    }
+
+    fn record(&mut self, cpu: &mut CPU6502, bus: &mut Bus) {
+        let mut writer = SnapshotWriter::new();
+        cpu.save(&mut writer);
+        let mut devices = SnapshotWriter::new();
+        bus.save_devices(&mut devices);
+
+        bus.set_journaling(true);
+        cpu.tick(bus);
+        let writes = bus.take_journal();
+        bus.set_journaling(false);
+
+        self.journal.push(JournalEntry { cpu: writer.into_bytes(), devices: devices.into_bytes(), writes });
+    }
+
+    // Undoes the newest journaled instruction, false if the journal is empty.
+    fn undo(&mut self, cpu: &mut CPU6502, bus: &mut Bus) -> bool {
+        let entry = match self.journal.pop() {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        for (address, previous) in entry.writes.into_iter().rev() {
+            bus.restore_byte(address, previous);
+        }
+        bus.restore_devices(&mut SnapshotReader::new(&entry.devices)).expect("journaled device state is always complete");
+        cpu.restore(&mut SnapshotReader::new(&entry.cpu)).expect("journaled CPU state is always complete");
+        return true;
+    }
+
+    // Oldest cycle count that can still be returned to.
+    pub fn horizon(&self) -> Option<u64> {
+        return self.checkpoints.front().map(|checkpoint| checkpoint.cycles);
+    }
+
+    // Returns to the last instruction boundary at or before the given cycle count. Everything recorded
+    // after it is discarded. Returns false, leaving the machine untouched, if it lies beyond the horizon.
+    pub fn seek(&mut self, cpu: &mut CPU6502, bus: &mut Bus, cycles: u64) -> bool {
+        match self.horizon() {
+            Some(horizon) if horizon <= cycles => {}
+            _ => return false,
+        }
+
+        if self.checkpoints.back().is_none_or(|checkpoint| checkpoint.cycles > cycles) {
+            while self.checkpoints.back().is_some_and(|checkpoint| checkpoint.cycles > cycles) {
+                self.checkpoints.pop_back();
+            }
+
+            let checkpoint = self.checkpoints.back().unwrap();
+            snapshot::restore(cpu, bus, &checkpoint.state).expect("checkpoints are taken from this machine");
+            self.journal.clear();
+
+            while cpu.cycles() < cycles {
+                self.record(cpu, bus);
+            }
+        }
+
+        while cpu.cycles() > cycles && self.undo(cpu, bus) {}
+        return true;
+    }
+
+    // Goes back the given number of cycles, landing on the instruction boundary at or before the target.
+    pub fn rewind(&mut self, cpu: &mut CPU6502, bus: &mut Bus, cycles: u64) -> bool {
+        return self.seek(cpu, bus, cpu.cycles().saturating_sub(cycles));
+    }
+
+    // Goes back exactly one instruction.
+    pub fn step_back(&mut self, cpu: &mut CPU6502, bus: &mut Bus) -> bool {
+        if self.undo(cpu, bus) {
+            return true;
+        }
+        return cpu.cycles() > 0 && self.seek(cpu, bus, cpu.cycles() - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::BusBuilder;
+    use crate::components::memory::RandomAccessMemory;
+    use crate::components::via6522::Via6522;
+
+    #[test]
+    fn test_rewind() {
+        let mut cpu = CPU6502::new();
+        let mut bus = Bus::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap());
+        for instruction in 0..8 {
+            bus.write(instruction * 2, 0x06); // ASL ZeroPage
+            bus.write(instruction * 2 + 1, 0x80);
+        }
+        bus.write(0x0080, 0x01);
+
+        // ASL ZeroPage takes 5 cycles, checkpoint every other instruction.
+        let mut rewind = Rewind::new(10, 2);
+        for _ in 0..8 {
+            rewind.tick(&mut cpu, &mut bus);
+        }
+        assert_eq!((bus.read(0x0080), cpu.cycles()), (0x00, 40));
+
+        assert!(rewind.step_back(&mut cpu, &mut bus));
+        assert_eq!((bus.read(0x0080), cpu.cycles(), cpu.registers().program_counter), (0x80, 35, 0x000E));
+
+        // Back across a checkpoint, landing on the instruction boundary before cycle 27.
+        assert!(rewind.rewind(&mut cpu, &mut bus, 8));
+        assert_eq!((bus.read(0x0080), cpu.cycles()), (0x20, 25));
+
+        // Only two checkpoints are kept, the first ones are gone.
+        assert_eq!(rewind.horizon(), Some(20));
+        assert!(!rewind.rewind(&mut cpu, &mut bus, 10));
+        assert_eq!(cpu.cycles(), 25);
+    }
+
+    #[test]
+    fn test_rewind_devices() {
+        let builder = BusBuilder::new();
+        let via = Via6522::new(0x8000, builder.interrupts().source("VIA")).unwrap();
+        let mut bus = builder
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap()))
+            .attach(Box::new(via))
+            .build()
+            .unwrap();
+        let mut cpu = CPU6502::new();
+        for instruction in 0..4 {
+            bus.write(instruction * 2, 0x06); // ASL ZeroPage
+            bus.write(instruction * 2 + 1, 0x80);
+        }
+        bus.write(0x8004, 0xFF); // T1 counts down on its own
+        bus.write(0x8005, 0xFF);
+
+        let mut rewind = Rewind::new(100, 1);
+        rewind.tick(&mut cpu, &mut bus);
+        rewind.tick(&mut cpu, &mut bus);
+        let timer = (bus.peek(0x8004), bus.peek(0x8005));
+        rewind.tick(&mut cpu, &mut bus);
+        assert_ne!((bus.peek(0x8004), bus.peek(0x8005)), timer);
+
+        // The timer is part of the journal, stepping back restores it along with the CPU.
+        assert!(rewind.step_back(&mut cpu, &mut bus));
+        assert_eq!((bus.peek(0x8004), bus.peek(0x8005)), timer);
+        assert_eq!(cpu.cycles(), 10);
+    }
+}
@@ -0,0 +1,478 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use crate::components::bus::Bus;
+use crate::components::device::Addressable;
+use crate::components::memory::MemoryError;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// Registers.
+const PUTCHAR: u16 = 0x0;
+const GETCHAR: u16 = 0x1;
+const STATUS: u16 = 0x2;
+const EXIT: u16 = 0x3;
+const PARAMETERS_LOW: u16 = 0x4;
+const PARAMETERS_HIGH: u16 = 0x5;
+const COMMAND: u16 = 0x6;
+const RESULT: u16 = 0x7;
+
+// Status register bits.
+const END_OF_INPUT: u8 = 0x01;
+
+// Commands, taking their arguments from the parameter block.
+pub const OPEN: u8 = 0x01;
+pub const READ: u8 = 0x02;
+pub const WRITE: u8 = 0x03;
+pub const CLOSE: u8 = 0x04;
+pub const ARGC: u8 = 0x05;
+pub const ARGV: u8 = 0x06;
+
+// Values of the result register.
+pub const SUCCESS: u8 = 0x00;
+pub const BAD_HANDLE: u8 = 0x01;
+pub const IO_ERROR: u8 = 0x02;
+pub const BAD_COMMAND: u8 = 0x03;
+// Until the command was carried out.
+pub const PENDING: u8 = 0xFF;
+
+// Where the `semihost` command maps the device, just below the vectors.
+pub const DEFAULT_ADDRESS: u16 = 0xFFF0;
+
+// Handles 0-2 are the emulator's own stdin, stdout and stderr.
+const FIRST_FILE: u8 = 3;
+
+struct State {
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+    errors: Box<dyn Write>,
+    arguments: Vec<String>,
+    files: HashMap<u8, File>,
+    status: u8,
+    parameters: u16,
+    command: Option<u8>,
+    result: u8,
+    exit: Option<u8>,
+}
+
+impl State {
+    // A prompt has to be out before blocking on the answer.
+    fn getchar(&mut self) -> u8 {
+        let _ = self.output.flush();
+        let mut byte = [0; 1];
+        return match self.input.read(&mut byte) {
+            Ok(1) => byte[0],
+            _ => {
+                self.status |= END_OF_INPUT;
+                0
+            }
+        };
+    }
+}
+
+// Lets a program use the host like a command-line tool does: console I/O, files, its arguments and an
+// exit code. Eight registers at a configurable address:
+//
+//     +0  PUTCHAR  write a byte to stdout
+//     +1  GETCHAR  read a byte from stdin, waiting for it, 0 and END_OF_INPUT at the end
+//     +2  STATUS   bit 0 END_OF_INPUT
+//     +3  EXIT     stop with the byte written as exit code
+//     +4  PARAMS   address of the parameter block, low then high byte
+//     +6  COMMAND  OPEN, READ, WRITE, CLOSE, ARGC or ARGV on the parameter block
+//     +7  RESULT   SUCCESS or an error, PENDING until the command ran
+//
+// The parameter block is 8 bytes in memory:
+//
+//     +0  handle, returned by OPEN; the argument index for ARGV; ARGC returns the count here
+//     +1  OPEN mode: 0 read, 1 write (create or truncate), 2 append
+//     +2  buffer address, the NUL terminated file name for OPEN
+//     +4  buffer length
+//     +6  bytes transferred, returned by READ, WRITE and ARGV; 0 from READ at the end of the file
+//
+// Commands need the bus, so the device only latches them and Semihost::service carries them out,
+// which the run loop calls after every instruction.
+pub struct Semihosting {
+    address: u16,
+    state: Rc<RefCell<State>>,
+}
+
+impl Semihosting {
+    // Uses the emulator's stdin, stdout and stderr. The eight registers have to fit below $FFFF.
+    pub fn new(address: u16, arguments: Vec<String>) -> Result<Semihosting, MemoryError> {
+        if address.checked_add(RESULT).is_none() {
+            return Err(MemoryError::OutOfAddressSpace { address, size: 0x08 });
+        }
+        let state = State {
+            input: Box::new(io::stdin()),
+            output: Box::new(io::stdout()),
+            errors: Box::new(io::stderr()),
+            arguments,
+            files: HashMap::new(),
+            status: 0,
+            parameters: 0,
+            command: None,
+            result: SUCCESS,
+            exit: None,
+        };
+        return Ok(Semihosting { address, state: Rc::new(RefCell::new(state)) });
+    }
+
+    // Replaces stdin and stdout, e.g. to script a run in a test.
+    pub fn with_io(self, input: Box<dyn Read>, output: Box<dyn Write>) -> Semihosting {
+        {
+            let mut state = self.state.borrow_mut();
+            state.input = input;
+            state.output = output;
+        }
+        return self;
+    }
+
+    pub fn host(&self) -> Semihost {
+        return Semihost { state: self.state.clone() };
+    }
+}
+
+impl Addressable for Semihosting {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (self.address, self.address + RESULT);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let state = self.state.borrow();
+        return match address & 0x07 {
+            STATUS => state.status,
+            PARAMETERS_LOW => state.parameters as u8,
+            PARAMETERS_HIGH => (state.parameters >> 8) as u8,
+            COMMAND => state.command.unwrap_or(0),
+            RESULT => state.result,
+            _ => 0,
+        };
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        if address & 0x07 == GETCHAR {
+            return self.state.borrow_mut().getchar();
+        }
+        return self.peek(address);
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        let mut state = self.state.borrow_mut();
+        match address & 0x07 {
+            PUTCHAR => {
+                let _ = state.output.write_all(&[data]);
+            }
+            EXIT => state.exit = Some(data),
+            PARAMETERS_LOW => state.parameters = (state.parameters & 0xFF00) | data as u16,
+            PARAMETERS_HIGH => state.parameters = (state.parameters & 0x00FF) | (data as u16) << 8,
+            COMMAND => {
+                state.command = Some(data);
+                state.result = PENDING;
+            }
+            _ => {}
+        }
+    }
+
+    fn name(&self) -> &str {
+        return "Semihosting";
+    }
+}
+
+// Host files cannot be part of a save state, only the registers are.
+impl Snapshot for Semihosting {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        let state = self.state.borrow();
+        writer.write_u8(state.status);
+        writer.write_u16(state.parameters);
+        writer.write_u8(state.result);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let mut state = self.state.borrow_mut();
+        state.status = reader.read_u8()?;
+        state.parameters = reader.read_u16()?;
+        state.result = reader.read_u8()?;
+        return Ok(());
+    }
+}
+
+// The host side of a Semihosting device.
+#[derive(Clone)]
+pub struct Semihost {
+    state: Rc<RefCell<State>>,
+}
+
+impl Semihost {
+    // Carries out a latched command. Returns the exit code once the program wrote one.
+    pub fn service(&self, bus: &mut Bus) -> Option<u8> {
+        let mut state = self.state.borrow_mut();
+        if let Some(command) = state.command.take() {
+            state.result = execute(&mut state, bus, command);
+            let _ = state.output.flush();
+        }
+        if state.exit.is_some() {
+            let _ = state.output.flush();
+        }
+        return state.exit;
+    }
+}
+
+fn peek_word(bus: &Bus, address: u16) -> u16 {
+    return bus.peek(address) as u16 | (bus.peek(address.wrapping_add(1)) as u16) << 8;
+}
+
+fn write_word(bus: &mut Bus, address: u16, value: u16) {
+    bus.write(address, value as u8);
+    bus.write(address.wrapping_add(1), (value >> 8) as u8);
+}
+
+fn execute(state: &mut State, bus: &mut Bus, command: u8) -> u8 {
+    let block = state.parameters;
+    let handle = bus.peek(block);
+    let buffer = peek_word(bus, block.wrapping_add(2));
+    let length = peek_word(bus, block.wrapping_add(4));
+
+    match command {
+        OPEN => {
+            let mut name = Vec::new();
+            let mut address = buffer;
+            while bus.peek(address) != 0 && name.len() < 0x100 {
+                name.push(bus.peek(address));
+                address = address.wrapping_add(1);
+            }
+            let path = String::from_utf8_lossy(&name).into_owned();
+            let mut options = OpenOptions::new();
+            match bus.peek(block.wrapping_add(1)) {
+                0 => options.read(true),
+                1 => options.write(true).create(true).truncate(true),
+                2 => options.append(true).create(true),
+                _ => return BAD_COMMAND,
+            };
+            let handle = match (FIRST_FILE..=0xFF).find(|handle| !state.files.contains_key(handle)) {
+                Some(handle) => handle,
+                None => return IO_ERROR,
+            };
+            return match options.open(path) {
+                Ok(file) => {
+                    state.files.insert(handle, file);
+                    bus.write(block, handle);
+                    SUCCESS
+                }
+                Err(_) => IO_ERROR,
+            };
+        }
+        READ => {
+            let mut data = vec![0; length as usize];
+            let result = match handle {
+                0 => state.input.read(&mut data),
+                _ => match state.files.get_mut(&handle) {
+                    Some(file) => file.read(&mut data),
+                    None => return BAD_HANDLE,
+                },
+            };
+            return match result {
+                Ok(count) => {
+                    for (offset, byte) in data[..count].iter().enumerate() {
+                        bus.write(buffer.wrapping_add(offset as u16), *byte);
+                    }
+                    write_word(bus, block.wrapping_add(6), count as u16);
+                    SUCCESS
+                }
+                Err(_) => IO_ERROR,
+            };
+        }
+        WRITE => {
+            let data: Vec<u8> = (0..length).map(|offset| bus.peek(buffer.wrapping_add(offset))).collect();
+            let result = match handle {
+                1 => state.output.write_all(&data),
+                2 => state.errors.write_all(&data),
+                _ => match state.files.get_mut(&handle) {
+                    Some(file) => file.write_all(&data),
+                    None => return BAD_HANDLE,
+                },
+            };
+            if result.is_err() {
+                return IO_ERROR;
+            }
+            write_word(bus, block.wrapping_add(6), length);
+            return SUCCESS;
+        }
+        CLOSE => {
+            return if state.files.remove(&handle).is_some() { SUCCESS } else { BAD_HANDLE };
+        }
+        ARGC => {
+            bus.write(block, state.arguments.len().min(0xFF) as u8);
+            return SUCCESS;
+        }
+        ARGV => {
+            let argument = match state.arguments.get(handle as usize) {
+                Some(argument) => argument.as_bytes(),
+                None => return BAD_COMMAND,
+            };
+            // Cut short to fit the buffer with its NUL.
+            let count = argument.len().min((length as usize).saturating_sub(1));
+            for (offset, byte) in argument[..count].iter().chain([0u8].iter()).enumerate() {
+                if offset < length as usize {
+                    bus.write(buffer.wrapping_add(offset as u16), *byte);
+                }
+            }
+            write_word(bus, block.wrapping_add(6), count as u16);
+            return SUCCESS;
+        }
+        _ => return BAD_COMMAND,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::components::bus::{Bus, BusBuilder};
+    use crate::components::cpu6502::CPU6502;
+    use crate::components::memory::RandomAccessMemory;
+    use crate::components::testing::{load, Capture, TempPath};
+
+    // Runs a command on the parameter block at $0300 when the host services the device, returning the
+    // status.
+    fn command(bus: &mut Bus, host: &Semihost, command: u8, block: &[u8]) -> u8 {
+        load(bus, 0x0300, block);
+        bus.write(0xFFF4, 0x00);
+        bus.write(0xFFF5, 0x03);
+        bus.write(0xFFF6, command);
+        assert_eq!(bus.read(0xFFF7), PENDING);
+        assert_eq!(host.service(bus), None);
+        return bus.read(0xFFF7);
+    }
+
+    #[test]
+    fn test_prompt_flushed() {
+        let output = Capture::default();
+        let semihosting = Semihosting::new(0xFFF0, Vec::new())
+            .unwrap()
+            .with_io(Box::new(std::io::Cursor::new(b"y".to_vec())), Box::new(std::io::BufWriter::new(output.clone())));
+        let mut bus = Bus::with_device(Box::new(semihosting));
+
+        // Buffered output shows up before the program waits for input.
+        bus.write(0xFFF0, b'?');
+        assert!(output.take().is_empty());
+        assert_eq!(bus.read(0xFFF1), b'y');
+        assert_eq!(output.take(), b"?");
+    }
+
+    #[test]
+    fn test_semihosting() {
+        let output = Capture::default();
+        let semihosting = Semihosting::new(0xFFF0, vec![String::from("tool"), String::from("input.txt")])
+            .unwrap()
+            .with_io(Box::new(std::io::Cursor::new(b"y".to_vec())), Box::new(output.clone()));
+        let host = semihosting.host();
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap()))
+            .map_with_priority(0xFFF0, 0xFFF7, Box::new(semihosting), 1)
+            .build()
+            .unwrap();
+
+        // Console: characters out, characters in until the end of input.
+        bus.write(0xFFF0, b'?');
+        assert_eq!(bus.read(0xFFF1), b'y');
+        assert_eq!(bus.read(0xFFF2), 0x00);
+        assert_eq!(bus.read(0xFFF1), 0x00);
+        assert_eq!(bus.read(0xFFF2), 0x01);
+        assert_eq!(output.take(), b"?");
+
+        // Commands run on the parameter block at $0300 when the host services the device.
+        assert_eq!(command(&mut bus, &host, ARGV, &[1, 0, 0x00, 0x04, 0x20, 0x00]), SUCCESS);
+        assert_eq!((bus.peek(0x0409), bus.peek(0x0306)), (0x00, 9));
+        assert_eq!((0x0400..0x0409).map(|address| bus.peek(address)).collect::<Vec<u8>>(), b"input.txt");
+
+        // A file written, then read back through its name.
+        let file = TempPath::file("semihosting.txt", b"");
+        let name: Vec<u8> = file.path().to_str().unwrap().bytes().chain([0]).collect();
+        load(&mut bus, 0x0500, &name);
+        load(&mut bus, 0x0600, b"hello");
+        assert_eq!(command(&mut bus, &host, OPEN, &[0, 1, 0x00, 0x05]), SUCCESS);
+        let handle = bus.peek(0x0300);
+        assert_eq!(handle, 3);
+        assert_eq!(command(&mut bus, &host, WRITE, &[handle, 0, 0x00, 0x06, 0x05, 0x00]), SUCCESS);
+        assert_eq!(command(&mut bus, &host, CLOSE, &[handle]), SUCCESS);
+        assert_eq!(command(&mut bus, &host, CLOSE, &[handle]), BAD_HANDLE);
+        assert_eq!(std::fs::read(file.path()).unwrap(), b"hello");
+
+        assert_eq!(command(&mut bus, &host, OPEN, &[0, 0, 0x00, 0x05]), SUCCESS);
+        assert_eq!(command(&mut bus, &host, READ, &[handle, 0, 0x00, 0x07, 0x10, 0x00]), SUCCESS);
+        assert_eq!((bus.peek(0x0306), bus.peek(0x0700), bus.peek(0x0704)), (5, b'h', b'o'));
+        assert_eq!(command(&mut bus, &host, READ, &[handle, 0, 0x00, 0x07, 0x10, 0x00]), SUCCESS);
+        assert_eq!(bus.peek(0x0306), 0);
+
+        bus.write(0xFFF3, 42);
+        assert_eq!(host.service(&mut bus), Some(42));
+
+        assert!(matches!(Semihosting::new(0xFFF9, Vec::new()), Err(MemoryError::OutOfAddressSpace { address: 0xFFF9, size: 0x08 })));
+    }
+
+    #[test]
+    fn test_semihosted_program() {
+        // Echoes stdin to stdout, then exits with its argument count.
+        let source = "
+            PUTCHAR = $FFF0
+            GETCHAR = $FFF1
+            STATUS  = $FFF2
+            EXIT    = $FFF3
+            PARAMS  = $FFF4
+            COMMAND = $FFF6
+            RESULT  = $FFF7
+            ARGC    = $05
+            BLOCK   = $0300
+
+                .org $0200
+                lda #<BLOCK
+                sta PARAMS
+                lda #>BLOCK
+                sta PARAMS+1
+                lda #ARGC
+                sta COMMAND
+            wait:
+                lda RESULT
+                cmp #$FF
+                beq wait
+            echo:
+                lda GETCHAR
+                ldx STATUS
+                bne done
+                sta PUTCHAR
+                jmp echo
+            done:
+                lda BLOCK
+                sta EXIT
+            halt:
+                jmp halt
+        ";
+        let image = assembler::assemble(source).unwrap().image(0x0200, 0x40, 0x00).unwrap();
+        let output = Capture::default();
+        let semihosting = Semihosting::new(0xFFF0, vec![String::from("echo"), String::from("-n")])
+            .unwrap()
+            .with_io(Box::new(std::io::Cursor::new(b"yes\n".to_vec())), Box::new(output.clone()));
+        let host = semihosting.host();
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap()))
+            .map_with_priority(0xFFF0, 0xFFF7, Box::new(semihosting), 1)
+            .build()
+            .unwrap();
+        load(&mut bus, 0x0200, &image);
+
+        let mut cpu = CPU6502::new();
+        cpu.registers_mut().program_counter = 0x0200;
+        let mut exit = None;
+        for _ in 0..1000 {
+            cpu.tick(&mut bus);
+            exit = host.service(&mut bus);
+            if exit.is_some() {
+                break;
+            }
+        }
+        assert_eq!(exit, Some(2));
+        assert_eq!(output.take(), b"yes\n");
+    }
+}
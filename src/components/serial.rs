@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+use crate::components::terminal;
+
+// Where the bytes of an emulated serial port go to and come from. Neither call may block, the
+// emulation runs on the same thread.
+pub trait SerialBackend {
+    // The next byte received, None if nothing is waiting.
+    fn receive(&mut self) -> Option<u8>;
+    fn transmit(&mut self, byte: u8);
+    // True once nothing more will be received, e.g. at the end of piped input.
+    fn closed(&self) -> bool {
+        return false;
+    }
+}
+
+// Scripted input and captured output, for tests and batch runs. Clones share the same buffers.
+#[derive(Clone, Default)]
+pub struct BufferBackend {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl BufferBackend {
+    pub fn new() -> BufferBackend {
+        return BufferBackend::default();
+    }
+
+    // Queues bytes to be received by the emulated machine.
+    pub fn send(&self, bytes: &[u8]) {
+        self.input.borrow_mut().extend(bytes);
+    }
+
+    // Returns and clears everything transmitted so far.
+    pub fn take_output(&self) -> Vec<u8> {
+        return std::mem::take(&mut self.output.borrow_mut());
+    }
+}
+
+impl SerialBackend for BufferBackend {
+    fn receive(&mut self) -> Option<u8> {
+        return self.input.borrow_mut().pop_front();
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        self.output.borrow_mut().push(byte);
+    }
+
+    // A script ends with its last byte.
+    fn closed(&self) -> bool {
+        return self.input.borrow().is_empty();
+    }
+}
+
+// The terminal the emulator runs in, switched to raw mode while the backend exists.
+pub struct TerminalBackend {
+    #[cfg(unix)]
+    _raw_mode: Option<terminal::RawMode>,
+    input: Receiver<u8>,
+    closed: bool,
+}
+
+impl Default for TerminalBackend {
+    fn default() -> TerminalBackend {
+        return TerminalBackend::new();
+    }
+}
+
+impl TerminalBackend {
+    // Falls back to line buffered input when stdin is not a terminal, e.g. when it is piped.
+    pub fn new() -> TerminalBackend {
+        return TerminalBackend {
+            #[cfg(unix)]
+            _raw_mode: terminal::RawMode::enable().ok(),
+            input: terminal::stdin_bytes(),
+            closed: false,
+        };
+    }
+}
+
+impl SerialBackend for TerminalBackend {
+    fn receive(&mut self) -> Option<u8> {
+        return match self.input.try_recv() {
+            Ok(byte) => Some(byte),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        };
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&[byte]);
+        let _ = stdout.flush();
+    }
+
+    fn closed(&self) -> bool {
+        return self.closed;
+    }
+}
+
+// A pseudo-terminal, attach to it with e.g. `screen <path> 9600` or `minicom -p <path>`. Bytes sent
+// while nothing is attached are dropped.
+#[cfg(unix)]
+pub struct PtyBackend {
+    master: std::fs::File,
+    // Held open so the master does not report a hang-up before a program has attached.
+    _slave: std::fs::File,
+    path: std::path::PathBuf,
+}
+
+#[cfg(unix)]
+impl PtyBackend {
+    pub fn new() -> io::Result<PtyBackend> {
+        use std::ffi::CStr;
+        use std::os::unix::fs::OpenOptionsExt;
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = std::fs::File::from_raw_fd(fd);
+            if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0
+                || libc::fcntl(fd, libc::F_SETFL, libc::fcntl(fd, libc::F_GETFL) | libc::O_NONBLOCK) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let path = std::path::PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+
+            let slave = std::fs::OpenOptions::new().read(true).write(true).custom_flags(libc::O_NOCTTY).open(&path)?;
+            let mut attributes: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(slave.as_raw_fd(), &mut attributes) == 0 {
+                libc::cfmakeraw(&mut attributes);
+                libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &attributes);
+            }
+
+            return Ok(PtyBackend { master, _slave: slave, path });
+        }
+    }
+
+    // Device path of the terminal side.
+    pub fn path(&self) -> &std::path::Path {
+        return &self.path;
+    }
+}
+
+#[cfg(unix)]
+impl SerialBackend for PtyBackend {
+    fn receive(&mut self) -> Option<u8> {
+        let mut byte = [0; 1];
+        return match self.master.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        };
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        let _ = self.master.write_all(&[byte]);
+    }
+}
+
+// Listens on a localhost TCP port, connect with e.g. `nc localhost <port>` or `telnet`. One client at
+// a time, bytes sent while nobody is connected are dropped.
+pub struct TcpBackend {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+}
+
+impl TcpBackend {
+    // Port 0 picks a free port, see local_addr.
+    pub fn new(port: u16) -> io::Result<TcpBackend> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        return Ok(TcpBackend { listener, client: None });
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        return self.listener.local_addr();
+    }
+
+    fn client(&mut self) -> Option<&mut TcpStream> {
+        if self.client.is_none() {
+            if let Ok((stream, _)) = self.listener.accept() {
+                if stream.set_nonblocking(true).is_ok() {
+                    let _ = stream.set_nodelay(true);
+                    self.client = Some(stream);
+                }
+            }
+        }
+        return self.client.as_mut();
+    }
+}
+
+impl SerialBackend for TcpBackend {
+    fn receive(&mut self) -> Option<u8> {
+        let mut byte = [0; 1];
+        let result = self.client()?.read(&mut byte);
+        return match result {
+            Ok(1) => Some(byte[0]),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => None,
+            // Closed or failed, wait for the next client.
+            _ => {
+                self.client = None;
+                None
+            }
+        };
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        let failed = match self.client() {
+            Some(client) => client.write_all(&[byte]).is_err(),
+            None => false,
+        };
+        if failed {
+            self.client = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcp_backend() {
+        use std::time::{Duration, Instant};
+
+        // Bytes sent while nobody is connected are dropped.
+        let mut backend = TcpBackend::new(0).unwrap();
+        backend.transmit(b'x');
+        assert_eq!(backend.receive(), None);
+
+        let mut client = std::net::TcpStream::connect(backend.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(b"hi").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut received = Vec::new();
+        while received.len() < 2 && Instant::now() < deadline {
+            match backend.receive() {
+                Some(byte) => received.push(byte),
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        assert_eq!(received, b"hi");
+
+        backend.transmit(b'o');
+        backend.transmit(b'k');
+        let mut reply = [0; 2];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"ok");
+    }
+}
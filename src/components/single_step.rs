@@ -0,0 +1,286 @@
+// Conformance harness for the ProcessorTests / SingleStepTests JSON format (one file per opcode,
+// "00.json" to "ff.json", each holding the initial state, the final state and the bus activity of
+// every cycle for a single instruction).
+//
+// The test files are not part of the repository, point SINGLE_STEP_TESTS at a local checkout of the
+// 6502 test directory to run them. SINGLE_STEP_OPCODES optionally restricts the run to a comma
+// separated list of hex opcodes, e.g. "69,29,0a".
+//
+// The CPU does not make the dummy reads and writes of the real chip, so only the number of cycles is
+// compared with the cycle list by default. Set SINGLE_STEP_BUS to compare every bus access as well.
+
+use std::cell::RefCell;
+use std::panic;
+use std::path::Path;
+use std::rc::Rc;
+
+use serde::Deserialize;
+
+use crate::components::bus::Bus;
+use crate::components::cpu6502::CPU6502;
+use crate::components::device::Addressable;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+const TESTS_VARIABLE: &str = "SINGLE_STEP_TESTS";
+const OPCODES_VARIABLE: &str = "SINGLE_STEP_OPCODES";
+const BUS_VARIABLE: &str = "SINGLE_STEP_BUS";
+
+// Failures printed in full per opcode, the rest are only counted.
+const REPORTED_FAILURES: usize = 3;
+
+#[derive(Debug, Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TestCase {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+type AccessLog = Rc<RefCell<Vec<(u16, u8, String)>>>;
+
+// Flat 64K of memory which records every access made through the bus.
+struct RecordingMemory {
+    data: Vec<u8>,
+    accesses: AccessLog,
+}
+
+impl Addressable for RecordingMemory {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (0x0000, 0xffff);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        return self.data[address as usize];
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.data[address as usize];
+        self.accesses.borrow_mut().push((address, data, String::from("read")));
+        return data;
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.data[address as usize] = data;
+        self.accesses.borrow_mut().push((address, data, String::from("write")));
+    }
+}
+
+impl Snapshot for RecordingMemory {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write_bytes(&self.data);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        return reader.read_into(&mut self.data);
+    }
+}
+
+// Runs a single test case, returning a description of every mismatch. The bus accesses are only
+// compared one by one with `bus_accesses`, otherwise just their number.
+fn run_case(case: &TestCase, bus_accesses: bool) -> Vec<String> {
+    let accesses: AccessLog = Rc::new(RefCell::new(Vec::new()));
+    let mut memory = RecordingMemory { data: vec![0; 0x10000], accesses: accesses.clone() };
+    for (address, data) in &case.initial.ram {
+        memory.data[*address as usize] = *data;
+    }
+
+    let mut bus = Bus::with_device(Box::new(memory));
+    let mut cpu = CPU6502::new();
+    {
+        let registers = cpu.registers_mut();
+        registers.program_counter = case.initial.pc;
+        registers.stack_pointer = case.initial.s;
+        registers.accumulator = case.initial.a;
+        registers.idx_x = case.initial.x;
+        registers.idx_y = case.initial.y;
+        registers.status_flags = case.initial.p;
+    }
+
+    // Anything the CPU panics on is caught and its message reported like a mismatch.
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| cpu.tick(&mut bus)));
+    if let Err(cause) = result {
+        let message = cause.downcast_ref::<String>().cloned()
+            .or_else(|| cause.downcast_ref::<&str>().map(|message| message.to_string()))
+            .unwrap_or_default();
+        return vec![format!("panicked: {}", message)];
+    }
+
+    let mut mismatches = Vec::new();
+    let registers = cpu.registers();
+    let expected = &case.expected;
+    let compared: [(&str, u16, u16); 6] = [
+        ("pc", expected.pc, registers.program_counter),
+        ("s", expected.s as u16, registers.stack_pointer as u16),
+        ("a", expected.a as u16, registers.accumulator as u16),
+        ("x", expected.x as u16, registers.idx_x as u16),
+        ("y", expected.y as u16, registers.idx_y as u16),
+        ("p", expected.p as u16, registers.status_flags as u16),
+    ];
+    for (name, expected, actual) in compared {
+        if expected != actual {
+            mismatches.push(format!("{} expected {:#06x} got {:#06x}", name, expected, actual));
+        }
+    }
+
+    if cpu.cycles() != case.cycles.len() as u64 {
+        mismatches.push(format!("expected {} cycles got {}", case.cycles.len(), cpu.cycles()));
+    }
+
+    let actual_cycles: Vec<(u16, u8, String)> = accesses.borrow().clone();
+    for (cycle, expected) in case.cycles.iter().enumerate().filter(|_| bus_accesses) {
+        match actual_cycles.get(cycle) {
+            Some(actual) if actual == expected => {}
+            Some(actual) => {
+                mismatches.push(format!("cycle {} expected {} {:#06x}={:#04x} got {} {:#06x}={:#04x}",
+                    cycle + 1, expected.2, expected.0, expected.1, actual.2, actual.0, actual.1));
+            }
+            None => {
+                mismatches.push(format!("cycle {} expected {} {:#06x}={:#04x} got nothing",
+                    cycle + 1, expected.2, expected.0, expected.1));
+            }
+        }
+    }
+    if bus_accesses && actual_cycles.len() > case.cycles.len() {
+        mismatches.push(format!("expected {} bus accesses got {}", case.cycles.len(), actual_cycles.len()));
+    }
+
+    for (address, data) in &expected.ram {
+        let actual = bus.peek(*address);
+        if actual != *data {
+            mismatches.push(format!("memory@{:#06x} expected {:#04x} got {:#04x}", address, data, actual));
+        }
+    }
+
+    return mismatches;
+}
+
+// Runs every case of an opcode file, returning the number of passed cases and the failures.
+fn run_file(path: &Path, bus_accesses: bool) -> (usize, Vec<(String, Vec<String>)>) {
+    let contents = std::fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("could not read {}: {}", path.display(), error));
+    let cases: Vec<TestCase> = serde_json::from_str(&contents)
+        .unwrap_or_else(|error| panic!("could not parse {}: {}", path.display(), error));
+
+    let mut passed = 0;
+    let mut failures = Vec::new();
+    for case in &cases {
+        let mismatches = run_case(case, bus_accesses);
+        if mismatches.is_empty() {
+            passed += 1;
+        } else {
+            failures.push((case.name.clone(), mismatches));
+        }
+    }
+
+    return (passed, failures);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_step_tests() {
+        let directory = match std::env::var(TESTS_VARIABLE) {
+            Ok(directory) => directory,
+            Err(_) => {
+                println!("{} is not set, skipping SingleStepTests", TESTS_VARIABLE);
+                return;
+            }
+        };
+
+        let opcodes: Vec<u8> = match std::env::var(OPCODES_VARIABLE) {
+            Ok(opcodes) => opcodes.split(',')
+                .map(|opcode| u8::from_str_radix(opcode.trim(), 16)
+                    .unwrap_or_else(|_| panic!("invalid opcode '{}' in {}", opcode, OPCODES_VARIABLE)))
+                .collect(),
+            Err(_) => (0x00..=0xff).collect(),
+        };
+
+        let bus_accesses = std::env::var(BUS_VARIABLE).is_ok();
+        let mut failed_opcodes = Vec::new();
+        for opcode in opcodes {
+            let path = Path::new(&directory).join(format!("{:02x}.json", opcode));
+            if !path.exists() {
+                continue;
+            }
+
+            let (passed, failures) = run_file(&path, bus_accesses);
+            println!("{:02x}: {}/{} passed", opcode, passed, passed + failures.len());
+            for (name, mismatches) in failures.iter().take(REPORTED_FAILURES) {
+                println!("    [{}] {}", name, mismatches.join(", "));
+            }
+            if !failures.is_empty() {
+                failed_opcodes.push(format!("{:02x}", opcode));
+            }
+        }
+
+        assert!(failed_opcodes.is_empty(), "failing opcodes: {}", failed_opcodes.join(" "));
+    }
+
+    #[test]
+    fn single_step_reports_mismatches() {
+        // CLC with the expected accumulator deliberately wrong. The second cycle is the dummy read of the
+        // following byte, which only shows up as missing when the bus accesses are compared.
+        let case: TestCase = serde_json::from_str(r#"{
+            "name": "18 00 00",
+            "initial": { "pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 0, "ram": [[512, 24], [513, 0]] },
+            "final": { "pc": 513, "s": 253, "a": 1, "x": 0, "y": 0, "p": 0, "ram": [[512, 24], [513, 0]] },
+            "cycles": [[512, 24, "read"], [513, 0, "read"]]
+        }"#).unwrap();
+
+        assert_eq!(run_case(&case, false), vec![String::from("a expected 0x0001 got 0x0000")]);
+        assert_eq!(run_case(&case, true), vec![
+            String::from("a expected 0x0001 got 0x0000"),
+            String::from("cycle 2 expected read 0x0201=0x00 got nothing"),
+        ]);
+
+        // Undocumented opcodes run like any other, LAX loads both A and X.
+        let case: TestCase = serde_json::from_str(r#"{
+            "name": "a7 10 00",
+            "initial": { "pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 0, "ram": [[512, 167], [513, 16], [16, 128]] },
+            "final": { "pc": 514, "s": 253, "a": 128, "x": 128, "y": 0, "p": 128, "ram": [[512, 167], [513, 16], [16, 128]] },
+            "cycles": [[512, 167, "read"], [513, 16, "read"], [16, 128, "read"]]
+        }"#).unwrap();
+        assert!(run_case(&case, true).is_empty());
+    }
+
+    #[test]
+    fn single_step_published_case() {
+        // The example case of LDA ($28),Y from the SingleStepTests 65x02 README, crossing a page. The
+        // fifth cycle is the dummy read from the wrong page.
+        let case: TestCase = serde_json::from_str(r#"{
+            "name": "b1 28 b5",
+            "initial": {
+                "pc": 59082, "s": 39, "a": 57, "x": 33, "y": 174, "p": 96,
+                "ram": [[59082, 177], [59083, 40], [59084, 181], [40, 160], [41, 233], [59726, 48], [59982, 119]]
+            },
+            "final": {
+                "pc": 59084, "s": 39, "a": 119, "x": 33, "y": 174, "p": 96,
+                "ram": [[40, 160], [41, 233], [59082, 177], [59083, 40], [59084, 181], [59726, 48], [59982, 119]]
+            },
+            "cycles": [
+                [59082, 177, "read"], [59083, 40, "read"], [40, 160, "read"], [41, 233, "read"],
+                [59726, 48, "read"], [59982, 119, "read"]
+            ]
+        }"#).unwrap();
+
+        assert!(run_case(&case, false).is_empty());
+        assert_eq!(run_case(&case, true), vec![
+            String::from("cycle 5 expected read 0xe94e=0x30 got read 0xea4e=0x77"),
+            String::from("cycle 6 expected read 0xea4e=0x77 got nothing"),
+        ]);
+    }
+}
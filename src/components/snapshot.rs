@@ -0,0 +1,236 @@
+use std::fmt;
+
+use crate::components::bus::Bus;
+use crate::components::cpu6502::CPU6502;
+
+// Layout: magic, format version (u16), CPU state, bus state. All values are little endian.
+pub const SNAPSHOT_MAGIC: [u8; 5] = *b"S6502";
+pub const SNAPSHOT_VERSION: u16 = 7;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    UnexpectedEnd,
+    // The snapshot was taken from a differently configured machine.
+    Mismatch(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            SnapshotError::InvalidMagic => write!(f, "not a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {}", version),
+            SnapshotError::UnexpectedEnd => write!(f, "snapshot is truncated"),
+            SnapshotError::Mismatch(reason) => write!(f, "snapshot does not match the machine: {}", reason),
+        };
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+// State that can be saved and restored exactly. Devices implement this alongside Addressable, restore
+// must read back exactly what save wrote.
+pub trait Snapshot {
+    fn save(&self, writer: &mut SnapshotWriter);
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError>;
+}
+
+#[derive(Debug, Default)]
+pub struct SnapshotWriter {
+    data: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    pub fn new() -> SnapshotWriter {
+        return SnapshotWriter { data: Vec::new() };
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    // Length prefixed, read back with SnapshotReader::read_bytes.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        return self.data;
+    }
+}
+
+#[derive(Debug)]
+pub struct SnapshotReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub fn new(data: &'a [u8]) -> SnapshotReader<'a> {
+        return SnapshotReader { data, position: 0 };
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self.position.checked_add(length).ok_or(SnapshotError::UnexpectedEnd)?;
+        let bytes = self.data.get(self.position..end).ok_or(SnapshotError::UnexpectedEnd)?;
+        self.position = end;
+        return Ok(bytes);
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SnapshotError> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SnapshotError> {
+        return Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()));
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SnapshotError> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SnapshotError> {
+        return Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()));
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SnapshotError> {
+        return Ok(self.read_u8()? != 0);
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let length = self.read_u32()? as usize;
+        return self.take(length);
+    }
+
+    // Reads a length prefixed block into the given buffer, which must have the same size.
+    pub fn read_into(&mut self, buffer: &mut [u8]) -> Result<(), SnapshotError> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(SnapshotError::Mismatch(format!("expected {} bytes, snapshot has {}", buffer.len(), bytes.len())));
+        }
+        buffer.copy_from_slice(bytes);
+        return Ok(());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.position >= self.data.len();
+    }
+}
+
+// Saves the whole machine into the versioned binary format.
+pub fn save(cpu: &CPU6502, bus: &Bus) -> Vec<u8> {
+    let mut writer = SnapshotWriter::new();
+    for byte in SNAPSHOT_MAGIC {
+        writer.write_u8(byte);
+    }
+    writer.write_u16(SNAPSHOT_VERSION);
+    cpu.save(&mut writer);
+    bus.save(&mut writer);
+    return writer.into_bytes();
+}
+
+// Restores a machine saved with `save`. The bus must have the same devices attached at the same
+// addresses as the machine the snapshot was taken from.
+pub fn restore(cpu: &mut CPU6502, bus: &mut Bus, data: &[u8]) -> Result<(), SnapshotError> {
+    let mut reader = SnapshotReader::new(data);
+    for byte in SNAPSHOT_MAGIC {
+        if reader.read_u8().map_err(|_| SnapshotError::InvalidMagic)? != byte {
+            return Err(SnapshotError::InvalidMagic);
+        }
+    }
+
+    let version = reader.read_u16()?;
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    cpu.restore(&mut reader)?;
+    bus.restore(&mut reader)?;
+    return Ok(());
+}
+
+#[cfg(feature = "serde")]
+pub mod json {
+    use serde::Serialize;
+
+    use crate::components::bus::Bus;
+    use crate::components::cpu6502::{CPU6502, Registers};
+    use crate::components::snapshot::SNAPSHOT_VERSION;
+
+    #[derive(Serialize)]
+    struct MachineState<'a> {
+        version: u16,
+        registers: &'a Registers,
+        cycles: u64,
+        devices: Vec<DeviceState>,
+    }
+
+    #[derive(Serialize)]
+    struct DeviceState {
+        start: u16,
+        end: u16,
+        state: Vec<u8>,
+    }
+
+    // Human readable export for debugging, it cannot be restored from.
+    pub fn export(cpu: &CPU6502, bus: &Bus) -> String {
+        let state = MachineState {
+            version: SNAPSHOT_VERSION,
+            registers: cpu.registers(),
+            cycles: cpu.cycles(),
+            devices: bus.device_states().into_iter()
+                .map(|((start, end), state)| DeviceState { start, end, state })
+                .collect(),
+        };
+        return serde_json::to_string_pretty(&state).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::memory::RandomAccessMemory;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let mut cpu = CPU6502::new();
+        let mut bus = Bus::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap());
+        bus.write(0x0000, 0x69); // ADC Immediate Mode
+        bus.write(0x0001, 0x01); // Value '0x01'
+        bus.write(0x1234, 0xAB);
+        cpu.tick(&mut bus);
+
+        let saved = save(&cpu, &bus);
+        let registers = cpu.registers().clone();
+
+        cpu.tick(&mut bus);
+        bus.write(0x1234, 0x00);
+        cpu.registers_mut().accumulator = 0xFF;
+
+        restore(&mut cpu, &mut bus, &saved).unwrap();
+        assert_eq!(*cpu.registers(), registers);
+        assert_eq!(cpu.cycles(), 2);
+        assert_eq!(bus.read(0x1234), 0xAB);
+
+        assert_eq!(restore(&mut cpu, &mut bus, b"garbage"), Err(SnapshotError::InvalidMagic));
+        assert_eq!(restore(&mut cpu, &mut bus, &saved[..saved.len() - 1]), Err(SnapshotError::UnexpectedEnd));
+    }
+}
@@ -0,0 +1,70 @@
+// Helpers shared by the tests of the components and the machines.
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::components::bus::Bus;
+
+// A file or directory in the temporary directory, named after the test and the process so parallel
+// runs do not collide. It is removed again when dropped.
+pub struct TempPath {
+    path: PathBuf,
+}
+
+impl TempPath {
+    fn new(name: &str) -> TempPath {
+        return TempPath { path: std::env::temp_dir().join(format!("scotty_rust_{}_{}", name, std::process::id())) };
+    }
+
+    pub fn file(name: &str, contents: &[u8]) -> TempPath {
+        let file = TempPath::new(name);
+        std::fs::write(&file.path, contents).unwrap();
+        return file;
+    }
+
+    pub fn directory(name: &str) -> TempPath {
+        let directory = TempPath::new(name);
+        std::fs::create_dir_all(&directory.path).unwrap();
+        return directory;
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = if self.path.is_dir() { std::fs::remove_dir_all(&self.path) } else { std::fs::remove_file(&self.path) };
+    }
+}
+
+// A sink for devices writing to the host, the test keeps a clone to look at what was written.
+#[derive(Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    pub fn take(&self) -> Vec<u8> {
+        return std::mem::take(&mut self.0.borrow_mut());
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        return Ok(data.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+// Writes the bytes through the bus, starting at the address.
+pub fn load(bus: &mut Bus, address: u16, bytes: &[u8]) {
+    for (offset, byte) in bytes.iter().enumerate() {
+        bus.write(address.wrapping_add(offset as u16), *byte);
+    }
+}
@@ -0,0 +1,243 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::components::device::{Addressable, SharedMemory};
+use crate::components::framebuffer::EASY6502_PALETTE;
+use crate::components::memory::MemoryError;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// Closest Unicode characters for the graphics half of the C64 upper case character set, screen codes
+// $40-$7F.
+const PETSCII_GRAPHICS: [char; 64] = [
+    '─', '♠', '│', '─', '─', '─', '─', '│', '│', '╮', '╰', '╯', '▙', '╲', '╱', '▛',
+    '▜', '●', '─', '♥', '│', '╭', '╳', '○', '♣', '│', '♦', '┼', '▒', '│', 'π', '◥',
+    ' ', '▌', '▄', '▔', '▁', '▏', '▒', '▕', '▒', '◤', '▕', '├', '▗', '└', '┐', '▂',
+    '┌', '┴', '┬', '┤', '▎', '▍', '▐', '▔', '▀', '▃', '✓', '▖', '▝', '┘', '▘', '▚',
+];
+
+// How the bytes in screen RAM turn into characters.
+#[derive(Clone)]
+pub enum Charset {
+    // Printable ASCII as is, anything else blank.
+    Ascii,
+    // C64 screen codes of the upper case/graphics set, with bit 7 selecting reverse video.
+    Petscii,
+    // One character per byte value.
+    Custom(Rc<[char; 256]>),
+}
+
+impl Charset {
+    // The character and whether it shows in reverse video.
+    fn decode(&self, code: u8) -> (char, bool) {
+        return match self {
+            Charset::Ascii => (if (0x20..0x7F).contains(&code) { code as char } else { ' ' }, false),
+            Charset::Petscii => {
+                let character = match code & 0x7F {
+                    0x00 => '@',
+                    letter @ 0x01..=0x1A => (b'A' + letter - 1) as char,
+                    0x1B => '[',
+                    0x1C => '£',
+                    0x1D => ']',
+                    0x1E => '↑',
+                    0x1F => '←',
+                    ascii @ 0x20..=0x3F => ascii as char,
+                    graphic => PETSCII_GRAPHICS[(graphic - 0x40) as usize],
+                };
+                (character, code & 0x80 != 0)
+            }
+            Charset::Custom(table) => (table[code as usize], false),
+        };
+    }
+}
+
+// Screen RAM or colour RAM of a TextVideo, attached to the bus like any memory.
+pub struct VideoRam {
+    address: u16,
+    memory: SharedMemory,
+    name: &'static str,
+}
+
+impl Addressable for VideoRam {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (self.address, (self.address as usize + self.memory.len() - 1) as u16);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        return self.memory[address as usize].get();
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.memory[address as usize].set(data);
+    }
+
+    fn memory(&self) -> Option<SharedMemory> {
+        return Some(self.memory.clone());
+    }
+
+    fn name(&self) -> &str {
+        return self.name;
+    }
+}
+
+impl Snapshot for VideoRam {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        let data: Vec<u8> = self.memory.iter().map(Cell::get).collect();
+        writer.write_bytes(&data);
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        let mut data = vec![0; self.memory.len()];
+        reader.read_into(&mut data)?;
+        for (cell, byte) in self.memory.iter().zip(data) {
+            cell.set(byte);
+        }
+        return Ok(());
+    }
+}
+
+// A character cell display: one byte per cell in screen RAM and its colour, the low four bits as an
+// index into the C64 palette, in colour RAM. There is no hardware cursor, programs draw their own.
+// Clones share the same display, so the host keeps one to show what the machine attached:
+//
+//     let video = TextVideo::new(40, 25, Charset::Petscii)?;
+//     let builder = BusBuilder::new()
+//         .attach(Box::new(video.screen_ram(0x0400)?))
+//         .attach(Box::new(video.colour_ram(0xD800)?));
+#[derive(Clone)]
+pub struct TextVideo {
+    columns: usize,
+    rows: usize,
+    charset: Charset,
+    screen: SharedMemory,
+    colours: SharedMemory,
+    background: Rc<Cell<u8>>,
+}
+
+impl TextVideo {
+    pub fn new(columns: usize, rows: usize, charset: Charset) -> Result<TextVideo, MemoryError> {
+        let size = columns * rows;
+        if size == 0 || size > 0x10000 {
+            return Err(MemoryError::InvalidSize(size));
+        }
+        return Ok(TextVideo {
+            columns,
+            rows,
+            charset,
+            screen: Rc::from(vec![Cell::new(0); size]),
+            // Light blue on black until a program sets its own colours.
+            colours: Rc::from(vec![Cell::new(0x0E); size]),
+            background: Rc::new(Cell::new(0x00)),
+        });
+    }
+
+    pub fn screen_ram(&self, address: u16) -> Result<VideoRam, MemoryError> {
+        return self.ram(address, &self.screen, "Screen RAM");
+    }
+
+    pub fn colour_ram(&self, address: u16) -> Result<VideoRam, MemoryError> {
+        return self.ram(address, &self.colours, "Colour RAM");
+    }
+
+    fn ram(&self, address: u16, memory: &SharedMemory, name: &'static str) -> Result<VideoRam, MemoryError> {
+        if address as usize + memory.len() > 0x10000 {
+            return Err(MemoryError::OutOfAddressSpace { address, size: memory.len() });
+        }
+        return Ok(VideoRam { address, memory: memory.clone(), name });
+    }
+
+    // Palette index of the colour behind all cells.
+    pub fn set_background(&self, colour: u8) {
+        self.background.set(colour & 0x0F);
+    }
+
+    pub fn character(&self, column: usize, row: usize) -> char {
+        return self.charset.decode(self.screen[row * self.columns + column].get()).0;
+    }
+
+    // The screen as plain text, one line per row without trailing blanks, for assertions in tests.
+    pub fn text(&self) -> String {
+        let mut lines = Vec::new();
+        for row in 0..self.rows {
+            let line: String = (0..self.columns).map(|column| self.character(column, row)).collect();
+            lines.push(String::from(line.trim_end()));
+        }
+        return lines.join("\n");
+    }
+
+    // The screen with colours and reverse video as ANSI true-colour escapes.
+    pub fn render(&self) -> String {
+        let background = EASY6502_PALETTE[self.background.get() as usize];
+        let mut frame = String::new();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let index = row * self.columns + column;
+                let (character, reverse) = self.charset.decode(self.screen[index].get());
+                let foreground = EASY6502_PALETTE[(self.colours[index].get() & 0x0F) as usize];
+                let (foreground, background) = if reverse { (background, foreground) } else { (foreground, background) };
+                frame.push_str(&format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m{}",
+                    foreground >> 16, (foreground >> 8) & 0xFF, foreground & 0xFF,
+                    background >> 16, (background >> 8) & 0xFF, background & 0xFF, character));
+            }
+            frame.push_str("\x1b[0m\n");
+        }
+        return frame;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::BusBuilder;
+    use crate::components::device::Addressable;
+    use crate::components::memory::{MemoryError, RandomAccessMemory};
+
+    #[test]
+    fn test_text_video() {
+        let video = TextVideo::new(40, 25, Charset::Petscii).unwrap();
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap()))
+            .map_with_priority(0x0400, 0x07E7, Box::new(video.screen_ram(0x0400).unwrap()), 1)
+            .map_with_priority(0xD800, 0xDBE7, Box::new(video.colour_ram(0xD800).unwrap()), 1)
+            .build()
+            .unwrap();
+        // The C64 clears the screen with spaces, 0 is @.
+        for address in 0x0400..0x07E8 {
+            bus.write(address, 0x20);
+        }
+        bus.write(0x0400, 0x00);
+        for (offset, code) in [0x08, 0x09, 0x20, 0x31, 0x1C, 0x53].iter().enumerate() {
+            bus.write(0x0400 + 40 + offset as u16, *code);
+        }
+        bus.write(0x07E7, 0x81); // reverse A in the bottom right corner
+        bus.write(0xDBE7, 0x02);
+        video.set_background(0x06);
+
+        let text = video.text();
+        assert_eq!(text.lines().count(), 25);
+        assert_eq!(text.lines().nth(1), Some("HI 1£♥"));
+        assert_eq!(text.lines().next(), Some("@"));
+        assert_eq!(video.character(39, 24), 'A');
+        assert!(video.render().ends_with("\x1b[38;2;0;0;170m\x1b[48;2;136;0;0mA\x1b[0m\n"));
+
+        let ascii = TextVideo::new(4, 2, Charset::Ascii).unwrap();
+        let mut screen = ascii.screen_ram(0x8000).unwrap();
+        for (offset, byte) in b"ok\0!\x07".iter().enumerate() {
+            Addressable::write(&mut screen, offset as u16, *byte);
+        }
+        assert_eq!(ascii.text(), "ok !\n");
+        assert!(matches!(ascii.screen_ram(0xFFFF), Err(MemoryError::OutOfAddressSpace { .. })));
+
+        // Bytes map straight through the table, with no reverse video for bit 7.
+        let mut table = [' '; 256];
+        table[0x01] = 'a';
+        table[0x81] = 'Ω';
+        let custom = TextVideo::new(3, 1, Charset::Custom(Rc::new(table))).unwrap();
+        let mut screen = custom.screen_ram(0x0000).unwrap();
+        for (offset, byte) in [0x81, 0x01, 0x41].iter().enumerate() {
+            Addressable::write(&mut screen, offset as u16, *byte);
+        }
+        assert_eq!(custom.text(), "Ωa");
+        assert_eq!(custom.character(0, 0), 'Ω');
+        assert!(custom.render().starts_with("\x1b[38;2;0;136;255m\x1b[48;2;0;0;0mΩ"));
+    }
+}
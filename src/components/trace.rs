@@ -0,0 +1,278 @@
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use crate::components::bus::Bus;
+use crate::components::cpu6502::{IAM, IAMSubMode, Instruction, OperationCode, Registers};
+
+// Writes nestest.log formatted lines, e.g.
+// C000  69 01     ADC #$01                        A:00 X:00 Y:00 P:24 SP:FD CYC:7
+pub struct Tracer {
+    sink: Box<dyn Write>,
+    failed: bool,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str("Tracer");
+    }
+}
+
+impl Tracer {
+    pub fn new(sink: Box<dyn Write>) -> Tracer {
+        return Tracer { sink, failed: false };
+    }
+
+    pub fn trace(&mut self, opcode: &OperationCode, registers: &Registers, cycles: u64, bus: &Bus) {
+        let line = format_line(opcode, registers, cycles, bus);
+        self.write_line(&line);
+    }
+
+    // A trap shows up as TRAP in place of the routine it stands in for.
+    pub fn trace_trap(&mut self, registers: &Registers, cycles: u64) {
+        let line = format!("{:04X}  {:<8}  {:<32}{}", registers.program_counter, "", "TRAP", format_state(registers, cycles));
+        self.write_line(&line);
+    }
+
+    // The first failed write is reported on stderr and ends the trace, the lines after it would leave
+    // a gap anyway.
+    fn write_line(&mut self, line: &str) {
+        if self.failed {
+            return;
+        }
+        if let Err(error) = writeln!(self.sink, "{}", line) {
+            eprintln!("TraceWriteFailed - {}", error);
+            self.failed = true;
+        }
+    }
+}
+
+fn read_word(bus: &Bus, low: u16, high: u16) -> u16 {
+    return u16::from_le_bytes([bus.peek(low), bus.peek(high)]);
+}
+
+// Disassembles the instruction at the program counter, including the effective address and the
+// addressed value the way nestest.log shows them.
+pub fn disassemble(opcode: &OperationCode, registers: &Registers, bus: &Bus) -> String {
+    let pc = registers.program_counter;
+    let mnemonic = format!("{:?}", opcode.instruction());
+    let operand = bus.peek(pc.wrapping_add(1));
+    let word = read_word(bus, pc.wrapping_add(1), pc.wrapping_add(2));
+
+    return match opcode.mode() {
+        IAM::Implied => mnemonic,
+        IAM::Accumulator => format!("{} A", mnemonic),
+        IAM::Immediate => format!("{} #${:02X}", mnemonic, operand),
+        IAM::Relative => {
+            let target = pc.wrapping_add(2).wrapping_add(operand as i8 as u16);
+            format!("{} ${:04X}", mnemonic, target)
+        }
+        IAM::ZeroPage(IAMSubMode::N) => {
+            format!("{} ${:02X} = {:02X}", mnemonic, operand, bus.peek(operand as u16))
+        }
+        IAM::ZeroPage(sub_mode) => {
+            let (index, name) = match sub_mode {
+                IAMSubMode::Y => (registers.idx_y, "Y"),
+                _ => (registers.idx_x, "X"),
+            };
+            let address = operand.wrapping_add(index);
+            format!("{} ${:02X},{} @ {:02X} = {:02X}", mnemonic, operand, name, address, bus.peek(address as u16))
+        }
+        // Jumps do not access their operand.
+        IAM::Absolute(IAMSubMode::N) if matches!(opcode.instruction(), Instruction::JMP | Instruction::JSR) => {
+            format!("{} ${:04X}", mnemonic, word)
+        }
+        IAM::Absolute(IAMSubMode::N) => {
+            format!("{} ${:04X} = {:02X}", mnemonic, word, bus.peek(word))
+        }
+        IAM::Absolute(sub_mode) => {
+            let (index, name) = match sub_mode {
+                IAMSubMode::Y => (registers.idx_y, "Y"),
+                _ => (registers.idx_x, "X"),
+            };
+            let address = word.wrapping_add(index as u16);
+            format!("{} ${:04X},{} @ {:04X} = {:02X}", mnemonic, word, name, address, bus.peek(address))
+        }
+        IAM::Indirect(IAMSubMode::N) => {
+            // The 6502 does not carry into the high byte when the pointer sits on a page boundary.
+            let target = read_word(bus, word, (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF));
+            format!("{} (${:04X}) = {:04X}", mnemonic, word, target)
+        }
+        IAM::Indirect(IAMSubMode::X) => {
+            let pointer = operand.wrapping_add(registers.idx_x);
+            let address = read_word(bus, pointer as u16, pointer.wrapping_add(1) as u16);
+            format!("{} (${:02X},X) @ {:02X} = {:04X} = {:02X}", mnemonic, operand, pointer, address, bus.peek(address))
+        }
+        IAM::Indirect(IAMSubMode::Y) => {
+            let base = read_word(bus, operand as u16, operand.wrapping_add(1) as u16);
+            let address = base.wrapping_add(registers.idx_y as u16);
+            format!("{} (${:02X}),Y = {:04X} @ {:04X} = {:02X}", mnemonic, operand, base, address, bus.peek(address))
+        }
+    };
+}
+
+pub fn format_line(opcode: &OperationCode, registers: &Registers, cycles: u64, bus: &Bus) -> String {
+    let pc = registers.program_counter;
+    let raw: Vec<String> = (0..opcode.bytes() as u16)
+        .map(|offset| format!("{:02X}", bus.peek(pc.wrapping_add(offset))))
+        .collect();
+
+    // nestest.log marks undocumented opcodes with a star in front of the mnemonic.
+    let marker = if opcode.undocumented() { '*' } else { ' ' };
+    return format!("{:04X}  {:<8} {}{:<32}{}", pc, raw.join(" "), marker, disassemble(opcode, registers, bus), format_state(registers, cycles));
+}
+
+fn format_state(registers: &Registers, cycles: u64) -> String {
+    return format!("A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}", registers.accumulator, registers.idx_x,
+        registers.idx_y, registers.status_flags, registers.stack_pointer, cycles);
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    pub line: usize,
+    pub generated: Option<String>,
+    pub reference: Option<String>,
+    pub fields: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "first divergence at line {} ({})", self.line, self.fields.join(", "))?;
+        writeln!(f, "generated: {}", self.generated.as_deref().unwrap_or("<end of trace>"))?;
+        return write!(f, "reference: {}", self.reference.as_deref().unwrap_or("<end of trace>"));
+    }
+}
+
+// Drops the PPU column of NES logs, it has no meaning for a bare 6502.
+fn normalize(line: &str) -> String {
+    let line = line.trim_end();
+    return match (line.find(" PPU:"), line.find(" CYC:")) {
+        (Some(ppu), Some(cyc)) if ppu < cyc => format!("{}{}", &line[..ppu], &line[cyc..]),
+        _ => line.to_string(),
+    };
+}
+
+// Splits a normalized line into its named columns.
+fn fields(line: &str) -> Vec<(String, String)> {
+    let mut fields = vec![
+        (String::from("PC"), line.get(0..4).unwrap_or(line).to_string()),
+        (String::from("bytes"), line.get(6..14).unwrap_or("").trim().to_string()),
+        (String::from("instruction"), line.get(16..48).unwrap_or("").trim().to_string()),
+    ];
+    for register in line.get(48..).unwrap_or("").split_whitespace() {
+        if let Some((name, value)) = register.split_once(':') {
+            fields.push((name.to_string(), value.to_string()));
+        }
+    }
+    return fields;
+}
+
+// Compares a generated trace against a reference log line by line and returns the first line at
+// which they differ, ignoring the PPU column.
+pub fn diff(generated: impl BufRead, reference: impl BufRead) -> std::io::Result<Option<Divergence>> {
+    let mut generated = generated.lines();
+    let mut reference = reference.lines();
+    let mut line = 0;
+
+    loop {
+        line += 1;
+        let (actual, expected) = match (generated.next().transpose()?, reference.next().transpose()?) {
+            (None, None) => return Ok(None),
+            (actual, expected) => (actual.map(|l| normalize(&l)), expected.map(|l| normalize(&l))),
+        };
+
+        if actual == expected {
+            continue;
+        }
+
+        let differing = match (&actual, &expected) {
+            (Some(actual), Some(expected)) => {
+                let actual_fields = fields(actual);
+                fields(expected).into_iter()
+                    .filter(|(name, value)| {
+                        actual_fields.iter().find(|(actual_name, _)| actual_name == name).map(|(_, v)| v) != Some(value)
+                    })
+                    .map(|(name, _)| name)
+                    .collect()
+            }
+            _ => vec![String::from("length")],
+        };
+
+        return Ok(Some(Divergence { line, generated: actual, reference: expected, fields: differing }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::cpu6502::CPU6502;
+    use crate::components::memory::RandomAccessMemory;
+
+    #[test]
+    fn test_trace_write_failure() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct Broken(Rc<Cell<usize>>);
+
+        impl Write for Broken {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                self.0.set(self.0.get() + 1);
+                return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                return Ok(());
+            }
+        }
+
+        // Tracing stops after the first failed write.
+        let attempts = Rc::new(Cell::new(0));
+        let mut tracer = Tracer::new(Box::new(Broken(attempts.clone())));
+        let registers = CPU6502::new().registers().clone();
+        tracer.trace_trap(&registers, 0);
+        tracer.trace_trap(&registers, 6);
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn test_trace_format() {
+        let mut cpu = CPU6502::new();
+        let mut bus = Bus::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap());
+        bus.write(0x0000, 0x69); // ADC Immediate Mode
+        bus.write(0x0001, 0x01); // Value '0x01'
+        bus.write(0x0010, 0x0E); // ASL Absolute
+        bus.write(0x0011, 0x00);
+        bus.write(0x0012, 0x02);
+
+        let opcode = cpu.decode(0x69).unwrap();
+        assert_eq!(format_line(&opcode, cpu.registers(), 7, &bus),
+            "0000  69 01     ADC #$01                        A:00 X:00 Y:00 P:00 SP:00 CYC:7");
+
+        cpu.registers_mut().program_counter = 0x0010;
+        let opcode = cpu.decode(0x0E).unwrap();
+        assert_eq!(format_line(&opcode, cpu.registers(), 9, &bus),
+            "0010  0E 00 02  ASL $0200 = 00                  A:00 X:00 Y:00 P:00 SP:00 CYC:9");
+
+        // Undocumented opcodes are marked like nestest.log does.
+        bus.write(0x0020, 0xA7); // LAX Zero Page
+        bus.write(0x0021, 0x10);
+        cpu.registers_mut().program_counter = 0x0020;
+        let opcode = cpu.decode(0xA7).unwrap();
+        assert_eq!(format_line(&opcode, cpu.registers(), 11, &bus),
+            "0020  A7 10    *LAX $10 = 0E                    A:00 X:00 Y:00 P:00 SP:00 CYC:11");
+    }
+
+    #[test]
+    fn test_trace_diff() {
+        let reference = "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7\n\
+                         C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10\n";
+        let generated = "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7\n\
+                         C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:26 SP:FD CYC:11\n";
+
+        let divergence = diff(generated.as_bytes(), reference.as_bytes()).unwrap().unwrap();
+        assert_eq!(divergence.line, 2);
+        assert_eq!(divergence.fields, vec![String::from("P"), String::from("CYC")]);
+
+        assert_eq!(diff(reference.as_bytes(), reference.as_bytes()).unwrap(), None);
+    }
+}
@@ -0,0 +1,916 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::components::device::{Addressable, Clocked};
+use crate::components::interrupt::InterruptSource;
+use crate::components::memory::MemoryError;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+// Register select, the VIA answers for 16 addresses.
+const ORB: u16 = 0x0;
+const ORA: u16 = 0x1;
+const DDRB: u16 = 0x2;
+const DDRA: u16 = 0x3;
+const T1C_L: u16 = 0x4;
+const T1C_H: u16 = 0x5;
+const T1L_L: u16 = 0x6;
+const T1L_H: u16 = 0x7;
+const T2C_L: u16 = 0x8;
+const T2C_H: u16 = 0x9;
+const SR: u16 = 0xA;
+const ACR: u16 = 0xB;
+const PCR: u16 = 0xC;
+const IFR: u16 = 0xD;
+const IER: u16 = 0xE;
+const ORA_NO_HANDSHAKE: u16 = 0xF;
+
+// Interrupt flag and enable bits.
+const CA2: u8 = 0x01;
+const CA1: u8 = 0x02;
+const SHIFT: u8 = 0x04;
+const CB2: u8 = 0x08;
+const CB1: u8 = 0x10;
+const TIMER2: u8 = 0x20;
+const TIMER1: u8 = 0x40;
+
+// Control line modes, bits 1-3 (CA2) and 5-7 (CB2) of the PCR.
+const INDEPENDENT_NEGATIVE: u8 = 1;
+const INDEPENDENT_POSITIVE: u8 = 3;
+const HANDSHAKE: u8 = 4;
+const PULSE: u8 = 5;
+const LOW: u8 = 6;
+const HIGH: u8 = 7;
+
+struct State {
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    // Levels driven onto the port pins from outside, pulled high when nothing is connected.
+    input_a: u8,
+    input_b: u8,
+    // Port contents captured on the active CA1/CB1 edge when latching is enabled in the ACR.
+    latch_a: u8,
+    latch_b: u8,
+
+    t1_counter: u16,
+    t1_latch: u16,
+    // Whether the next underflow sets the interrupt flag, one-shot mode disarms after the first.
+    t1_armed: bool,
+    // Free-running mode reloads the counter from the latch the cycle after the underflow.
+    t1_reload: bool,
+    pb7: bool,
+    t2_counter: u16,
+    t2_latch_low: u8,
+    t2_armed: bool,
+
+    sr: u8,
+    // Bits left to shift, 0 when the shift register is idle.
+    sr_bits: u8,
+    // Cycles until the next bit is shifted in the timed modes.
+    sr_countdown: u64,
+
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+    // The control line is pulsed low for a single cycle.
+    ca2_pulse: bool,
+    cb2_pulse: bool,
+
+    irq: InterruptSource,
+}
+
+impl State {
+    fn new(irq: InterruptSource) -> State {
+        return State {
+            ora: 0, orb: 0, ddra: 0, ddrb: 0,
+            input_a: 0xFF, input_b: 0xFF, latch_a: 0, latch_b: 0,
+            t1_counter: 0, t1_latch: 0, t1_armed: false, t1_reload: false, pb7: true,
+            t2_counter: 0, t2_latch_low: 0, t2_armed: false,
+            sr: 0, sr_bits: 0, sr_countdown: 0,
+            acr: 0, pcr: 0, ifr: 0, ier: 0,
+            ca1: true, ca2: true, cb1: true, cb2: true, ca2_pulse: false, cb2_pulse: false,
+            irq,
+        };
+    }
+
+    fn set_flags(&mut self, flags: u8) {
+        self.ifr |= flags;
+        self.update_irq();
+    }
+
+    fn clear_flags(&mut self, flags: u8) {
+        self.ifr &= !flags;
+        self.update_irq();
+    }
+
+    fn update_irq(&mut self) {
+        self.irq.set_irq(self.ifr & self.ier & 0x7F != 0);
+    }
+
+    fn ca2_mode(&self) -> u8 {
+        return (self.pcr >> 1) & 0x07;
+    }
+
+    fn cb2_mode(&self) -> u8 {
+        return (self.pcr >> 5) & 0x07;
+    }
+
+    fn sr_mode(&self) -> u8 {
+        return (self.acr >> 2) & 0x07;
+    }
+
+    fn port_a(&self) -> u8 {
+        return (self.ora & self.ddra) | (self.input_a & !self.ddra);
+    }
+
+    fn port_b(&self) -> u8 {
+        let pins = (self.orb & self.ddrb) | (self.input_b & !self.ddrb);
+        if self.acr & 0x80 != 0 {
+            return (pins & 0x7F) | (self.pb7 as u8) << 7;
+        }
+        return pins;
+    }
+
+    // IRA reads the pins, IRB reads the output register for output bits.
+    fn input_register_a(&self) -> u8 {
+        return if self.acr & 0x01 != 0 { self.latch_a } else { self.port_a() };
+    }
+
+    fn input_register_b(&self) -> u8 {
+        let inputs = if self.acr & 0x02 != 0 { self.latch_b } else { self.port_b() };
+        return (self.orb & self.ddrb) | (inputs & !self.ddrb);
+    }
+
+    // Reading or writing a port clears its control line flags, CA2/CB2 only when not independent.
+    fn port_a_access(&mut self) {
+        let mode = self.ca2_mode();
+        let independent = mode == INDEPENDENT_NEGATIVE || mode == INDEPENDENT_POSITIVE;
+        self.clear_flags(if independent { CA1 } else { CA1 | CA2 });
+        match mode {
+            HANDSHAKE => self.ca2 = false,
+            PULSE => {
+                self.ca2 = false;
+                self.ca2_pulse = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn port_b_access(&mut self, write: bool) {
+        let mode = self.cb2_mode();
+        let independent = mode == INDEPENDENT_NEGATIVE || mode == INDEPENDENT_POSITIVE;
+        self.clear_flags(if independent { CB1 } else { CB1 | CB2 });
+        // Only writes to port B start a handshake.
+        if write && self.sr_mode() < 4 {
+            match mode {
+                HANDSHAKE => self.cb2 = false,
+                PULSE => {
+                    self.cb2 = false;
+                    self.cb2_pulse = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn peek(&self, register: u16) -> u8 {
+        return match register {
+            ORB => self.input_register_b(),
+            ORA | ORA_NO_HANDSHAKE => self.input_register_a(),
+            DDRB => self.ddrb,
+            DDRA => self.ddra,
+            T1C_L => self.t1_counter as u8,
+            T1C_H => (self.t1_counter >> 8) as u8,
+            T1L_L => self.t1_latch as u8,
+            T1L_H => (self.t1_latch >> 8) as u8,
+            T2C_L => self.t2_counter as u8,
+            T2C_H => (self.t2_counter >> 8) as u8,
+            SR => self.sr,
+            ACR => self.acr,
+            PCR => self.pcr,
+            IFR => self.ifr | if self.ifr & self.ier & 0x7F != 0 { 0x80 } else { 0x00 },
+            IER => self.ier | 0x80,
+            _ => unreachable!(),
+        };
+    }
+
+    fn read(&mut self, register: u16) -> u8 {
+        let data = self.peek(register);
+        match register {
+            ORB => self.port_b_access(false),
+            ORA => self.port_a_access(),
+            T1C_L => self.clear_flags(TIMER1),
+            T2C_L => self.clear_flags(TIMER2),
+            SR => self.start_shift(),
+            _ => {}
+        }
+        return data;
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            ORB => {
+                self.orb = data;
+                self.port_b_access(true);
+            }
+            ORA => {
+                self.ora = data;
+                self.port_a_access();
+            }
+            ORA_NO_HANDSHAKE => self.ora = data,
+            DDRB => self.ddrb = data,
+            DDRA => self.ddra = data,
+            T1C_L | T1L_L => self.t1_latch = (self.t1_latch & 0xFF00) | data as u16,
+            T1C_H => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | (data as u16) << 8;
+                self.t1_counter = self.t1_latch;
+                self.t1_armed = true;
+                self.t1_reload = false;
+                if self.acr & 0x80 != 0 {
+                    self.pb7 = false;
+                }
+                self.clear_flags(TIMER1);
+            }
+            T1L_H => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | (data as u16) << 8;
+                self.clear_flags(TIMER1);
+            }
+            T2C_L => self.t2_latch_low = data,
+            T2C_H => {
+                self.t2_counter = (data as u16) << 8 | self.t2_latch_low as u16;
+                self.t2_armed = true;
+                self.clear_flags(TIMER2);
+            }
+            SR => {
+                self.sr = data;
+                self.start_shift();
+            }
+            ACR => self.acr = data,
+            PCR => {
+                self.pcr = data;
+                match self.ca2_mode() {
+                    LOW => self.ca2 = false,
+                    HIGH | HANDSHAKE | PULSE => self.ca2 = true,
+                    _ => {}
+                }
+                match self.cb2_mode() {
+                    LOW => self.cb2 = false,
+                    HIGH | HANDSHAKE | PULSE => self.cb2 = true,
+                    _ => {}
+                }
+            }
+            IFR => self.clear_flags(data & 0x7F),
+            IER => {
+                if data & 0x80 != 0 {
+                    self.ier |= data & 0x7F;
+                } else {
+                    self.ier &= !data;
+                }
+                self.update_irq();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Accessing the shift register clears its flag and starts shifting eight bits.
+    fn start_shift(&mut self) {
+        self.clear_flags(SHIFT);
+        if self.sr_mode() != 0 {
+            self.sr_bits = 8;
+            self.sr_countdown = self.shift_period();
+        }
+    }
+
+    // Cycles per bit: the shift clock on CB1 toggles whenever the low byte of T2 times out, or every
+    // cycle in the modes clocked by the system clock.
+    fn shift_period(&self) -> u64 {
+        return match self.sr_mode() {
+            1 | 4 | 5 => 2 * (self.t2_latch_low as u64 + 2),
+            _ => 2,
+        };
+    }
+
+    fn shift(&mut self) {
+        if self.sr_mode() >= 4 {
+            let bit = self.sr >> 7;
+            self.sr = self.sr << 1 | bit;
+            self.cb2 = bit != 0;
+        } else {
+            self.sr = self.sr << 1 | self.cb2 as u8;
+        }
+
+        self.sr_bits -= 1;
+        if self.sr_bits == 0 {
+            // Free-running output keeps recirculating the same byte without interrupting.
+            if self.sr_mode() == 4 {
+                self.sr_bits = 8;
+            } else {
+                self.set_flags(SHIFT);
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if cycles == 0 {
+            return;
+        }
+        if self.ca2_pulse {
+            self.ca2 = true;
+            self.ca2_pulse = false;
+        }
+        if self.cb2_pulse {
+            self.cb2 = true;
+            self.cb2_pulse = false;
+        }
+
+        self.tick_timer1(cycles);
+        if self.acr & 0x20 == 0 {
+            if cycles > self.t2_counter as u64 && self.t2_armed {
+                self.t2_armed = false;
+                self.set_flags(TIMER2);
+            }
+            // Long catch-ups can wrap the counter around several times.
+            self.t2_counter = self.t2_counter.wrapping_sub((cycles % 0x10000) as u16);
+        }
+
+        // Modes 3 and 7 are clocked by CB1 edges instead.
+        if self.sr_bits > 0 && !matches!(self.sr_mode(), 0 | 3 | 7) {
+            let mut remaining = cycles;
+            while self.sr_bits > 0 && remaining >= self.sr_countdown {
+                remaining -= self.sr_countdown;
+                self.shift();
+                self.sr_countdown = self.shift_period();
+            }
+            if self.sr_bits > 0 {
+                self.sr_countdown -= remaining;
+            }
+        }
+    }
+
+    fn tick_timer1(&mut self, cycles: u64) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            if self.t1_reload {
+                self.t1_counter = self.t1_latch;
+                self.t1_reload = false;
+                remaining -= 1;
+                continue;
+            }
+
+            // The counter underflows when it passes from 0 to $FFFF.
+            let underflow = self.t1_counter as u64 + 1;
+            if remaining < underflow {
+                self.t1_counter -= remaining as u16;
+                return;
+            }
+            remaining -= underflow;
+            self.t1_counter = 0xFFFF;
+
+            let continuous = self.acr & 0x40 != 0;
+            if self.t1_armed {
+                self.set_flags(TIMER1);
+                self.t1_armed = continuous;
+                if self.acr & 0x80 != 0 {
+                    self.pb7 = if continuous { !self.pb7 } else { true };
+                }
+            }
+            self.t1_reload = continuous;
+        }
+    }
+
+    // Cycles until the next timer interrupt, shifted bit or end of a pulse.
+    fn next_event(&self) -> Option<u64> {
+        let mut events = Vec::new();
+        if self.ca2_pulse || self.cb2_pulse {
+            events.push(1);
+        }
+        if self.t1_armed {
+            events.push(if self.t1_reload { self.t1_latch as u64 + 2 } else { self.t1_counter as u64 + 1 });
+        }
+        if self.t2_armed && self.acr & 0x20 == 0 {
+            events.push(self.t2_counter as u64 + 1);
+        }
+        if self.sr_bits > 0 && !matches!(self.sr_mode(), 0 | 3 | 7) {
+            events.push(self.sr_countdown.max(1));
+        }
+        return events.into_iter().min();
+    }
+
+    fn set_ca1(&mut self, level: bool) {
+        if level != self.ca1 {
+            self.ca1 = level;
+            if level == (self.pcr & 0x01 != 0) {
+                self.latch_a = self.port_a();
+                if self.ca2_mode() == HANDSHAKE {
+                    self.ca2 = true;
+                }
+                self.set_flags(CA1);
+            }
+        }
+    }
+
+    fn set_cb1(&mut self, level: bool) {
+        if level != self.cb1 {
+            self.cb1 = level;
+            if level && self.sr_bits > 0 && matches!(self.sr_mode(), 3 | 7) {
+                self.shift();
+            }
+            if level == (self.pcr & 0x10 != 0) {
+                self.latch_b = self.port_b();
+                if self.cb2_mode() == HANDSHAKE {
+                    self.cb2 = true;
+                }
+                self.set_flags(CB1);
+            }
+        }
+    }
+
+    // CA2 and CB2 are only inputs in the PCR modes 0 to 3, bit 1 of the mode selects the active edge.
+    fn set_ca2(&mut self, level: bool) {
+        let mode = self.ca2_mode();
+        if mode < 4 && level != self.ca2 {
+            self.ca2 = level;
+            if level == (mode & 0x02 != 0) {
+                self.set_flags(CA2);
+            }
+        }
+    }
+
+    fn set_cb2(&mut self, level: bool) {
+        let mode = self.cb2_mode();
+        if mode < 4 && self.sr_mode() < 4 && level != self.cb2 {
+            self.cb2 = level;
+            if level == (mode & 0x02 != 0) {
+                self.set_flags(CB2);
+            }
+        }
+    }
+
+    fn set_input_b(&mut self, levels: u8) {
+        // In pulse counting mode T2 counts falling edges on PB6.
+        let falling = self.input_b & !levels & 0x40 != 0;
+        self.input_b = levels;
+        if falling && self.acr & 0x20 != 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+            if self.t2_counter == 0 && self.t2_armed {
+                self.t2_armed = false;
+                self.set_flags(TIMER2);
+            }
+        }
+    }
+
+    // Everything a peripheral can observe, to tell whether it has to be updated.
+    fn outputs(&self) -> (u8, u8, u8, u8, bool, bool) {
+        return (self.port_a(), self.port_b(), self.ddra, self.ddrb, self.ca2, self.cb2);
+    }
+}
+
+// The port and control line pins of a Via6522, for host code emulating what is wired to them. Clones
+// share the same VIA. Inputs are pulled high until driven.
+#[derive(Clone)]
+pub struct ViaPins {
+    state: Rc<RefCell<State>>,
+    peripherals: Rc<RefCell<Vec<Box<dyn Peripheral>>>>,
+}
+
+impl ViaPins {
+    // Runs a change and updates the peripherals if it changed what they can observe. A peripheral
+    // driving the pins from its own update is not updated again.
+    fn change<T>(&self, change: impl FnOnce(&mut State) -> T) -> T {
+        let outputs = self.state.borrow().outputs();
+        let result = change(&mut self.state.borrow_mut());
+        if self.state.borrow().outputs() != outputs {
+            self.notify();
+        }
+        return result;
+    }
+
+    fn notify(&self) {
+        if let Ok(mut peripherals) = self.peripherals.try_borrow_mut() {
+            for peripheral in peripherals.iter_mut() {
+                peripheral.update(self);
+            }
+        }
+    }
+
+    // Pin levels, output pins show what the VIA drives, input pins what is driven onto them.
+    pub fn port_a(&self) -> u8 {
+        return self.state.borrow().port_a();
+    }
+
+    pub fn port_b(&self) -> u8 {
+        return self.state.borrow().port_b();
+    }
+
+    // Drives the input pins of a port, bits configured as outputs are unaffected.
+    pub fn set_port_a(&self, levels: u8) {
+        self.change(|state| state.input_a = levels);
+    }
+
+    pub fn set_port_b(&self, levels: u8) {
+        self.change(|state| state.set_input_b(levels));
+    }
+
+    pub fn set_ca1(&self, level: bool) {
+        self.change(|state| state.set_ca1(level));
+    }
+
+    pub fn set_cb1(&self, level: bool) {
+        self.change(|state| state.set_cb1(level));
+    }
+
+    // Ignored while the line is an output.
+    pub fn set_ca2(&self, level: bool) {
+        self.change(|state| state.set_ca2(level));
+    }
+
+    pub fn set_cb2(&self, level: bool) {
+        self.change(|state| state.set_cb2(level));
+    }
+
+    pub fn ca2(&self) -> bool {
+        return self.state.borrow().ca2;
+    }
+
+    pub fn cb2(&self) -> bool {
+        return self.state.borrow().cb2;
+    }
+}
+
+// Something wired to the pins of a VIA rather than to the bus, e.g. an LCD or a keypad. It is updated
+// whenever the levels driven by the VIA change and can drive the input pins in response.
+pub trait Peripheral: Snapshot {
+    fn update(&mut self, pins: &ViaPins);
+
+    // Advanced together with the VIA, in its clock cycles.
+    fn tick(&mut self, _cycles: u64, _pins: &ViaPins) {}
+}
+
+// MOS 6522 Versatile Interface Adapter: two 8 bit ports with handshake lines, two 16 bit timers and a
+// shift register, answering for 16 registers mirrored throughout the range it is mapped at.
+pub struct Via6522 {
+    address: u16,
+    pins: ViaPins,
+}
+
+impl Via6522 {
+    // The 16 registers have to fit below $FFFF.
+    pub fn new(address: u16, irq: InterruptSource) -> Result<Via6522, MemoryError> {
+        if address > 0xFFF0 {
+            return Err(MemoryError::OutOfAddressSpace { address, size: 0x10 });
+        }
+        let pins = ViaPins { state: Rc::new(RefCell::new(State::new(irq))), peripherals: Rc::new(RefCell::new(Vec::new())) };
+        return Ok(Via6522 { address, pins });
+    }
+
+    pub fn pins(&self) -> ViaPins {
+        return self.pins.clone();
+    }
+
+    pub fn connect(&mut self, peripheral: Box<dyn Peripheral>) {
+        self.pins.peripherals.borrow_mut().push(peripheral);
+        self.pins.notify();
+    }
+}
+
+impl Addressable for Via6522 {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (self.address, self.address + 0x0F);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        return self.pins.state.borrow().peek(address & 0x0F);
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        return self.pins.change(|state| state.read(address & 0x0F));
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.pins.change(|state| state.write(address & 0x0F, data));
+    }
+
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        return Some(self);
+    }
+
+    fn name(&self) -> &str {
+        return "VIA";
+    }
+}
+
+impl Clocked for Via6522 {
+    fn tick(&mut self, cycles: u64) {
+        self.pins.change(|state| state.tick(cycles));
+        for peripheral in self.pins.peripherals.borrow_mut().iter_mut() {
+            peripheral.tick(cycles, &self.pins);
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        return self.pins.state.borrow().next_event();
+    }
+}
+
+impl Snapshot for Via6522 {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        let state = self.pins.state.borrow();
+        for byte in [state.ora, state.orb, state.ddra, state.ddrb, state.input_a, state.input_b, state.latch_a, state.latch_b] {
+            writer.write_u8(byte);
+        }
+        writer.write_u16(state.t1_counter);
+        writer.write_u16(state.t1_latch);
+        writer.write_bool(state.t1_armed);
+        writer.write_bool(state.t1_reload);
+        writer.write_bool(state.pb7);
+        writer.write_u16(state.t2_counter);
+        writer.write_u8(state.t2_latch_low);
+        writer.write_bool(state.t2_armed);
+        writer.write_u8(state.sr);
+        writer.write_u8(state.sr_bits);
+        writer.write_u64(state.sr_countdown);
+        for byte in [state.acr, state.pcr, state.ifr, state.ier] {
+            writer.write_u8(byte);
+        }
+        for level in [state.ca1, state.ca2, state.cb1, state.cb2, state.ca2_pulse, state.cb2_pulse] {
+            writer.write_bool(level);
+        }
+        for peripheral in self.pins.peripherals.borrow().iter() {
+            peripheral.save(writer);
+        }
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        {
+            let mut state = self.pins.state.borrow_mut();
+            state.ora = reader.read_u8()?;
+            state.orb = reader.read_u8()?;
+            state.ddra = reader.read_u8()?;
+            state.ddrb = reader.read_u8()?;
+            state.input_a = reader.read_u8()?;
+            state.input_b = reader.read_u8()?;
+            state.latch_a = reader.read_u8()?;
+            state.latch_b = reader.read_u8()?;
+            state.t1_counter = reader.read_u16()?;
+            state.t1_latch = reader.read_u16()?;
+            state.t1_armed = reader.read_bool()?;
+            state.t1_reload = reader.read_bool()?;
+            state.pb7 = reader.read_bool()?;
+            state.t2_counter = reader.read_u16()?;
+            state.t2_latch_low = reader.read_u8()?;
+            state.t2_armed = reader.read_bool()?;
+            state.sr = reader.read_u8()?;
+            state.sr_bits = reader.read_u8()?;
+            state.sr_countdown = reader.read_u64()?;
+            state.acr = reader.read_u8()?;
+            state.pcr = reader.read_u8()?;
+            state.ifr = reader.read_u8()?;
+            state.ier = reader.read_u8()?;
+            state.ca1 = reader.read_bool()?;
+            state.ca2 = reader.read_bool()?;
+            state.cb1 = reader.read_bool()?;
+            state.cb2 = reader.read_bool()?;
+            state.ca2_pulse = reader.read_bool()?;
+            state.cb2_pulse = reader.read_bool()?;
+            state.update_irq();
+        }
+        for peripheral in self.pins.peripherals.borrow_mut().iter_mut() {
+            peripheral.restore(reader)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::BusBuilder;
+    use crate::components::interrupt::InterruptLines;
+
+    #[test]
+    fn test_via6522() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let builder = BusBuilder::new();
+        let mut via = Via6522::new(0x6000, builder.interrupts().source("VIA")).unwrap();
+        let pins = via.pins();
+
+        // Ports: outputs show on the pins, inputs read what the host drives.
+        via.write(0x3, 0xF0); // DDRA
+        via.write(0x1, 0xA5); // ORA
+        pins.set_port_a(0x0C);
+        assert_eq!(pins.port_a(), 0xAC);
+        assert_eq!(via.read(0x1), 0xAC);
+
+        // T1 one-shot interrupts once, N + 1.5 cycles after it was started.
+        via.write(0xE, 0xC0); // IER: enable T1
+        via.write(0x4, 0x05);
+        via.write(0x5, 0x00);
+        via.tick(5);
+        assert!(!builder.interrupts().irq());
+        via.tick(1);
+        assert!(builder.interrupts().irq());
+        assert_eq!(via.peek(0xD), 0xC0);
+        via.read(0x4);
+        assert!(!builder.interrupts().irq());
+        via.tick(0x10000);
+        assert!(!builder.interrupts().irq());
+
+        // Free-running with PB7 output toggles every N + 2 cycles.
+        via.write(0xB, 0xC0); // ACR
+        via.write(0x4, 0x02);
+        via.write(0x5, 0x00);
+        assert_eq!(pins.port_b() & 0x80, 0x00);
+        via.tick(3);
+        assert_eq!(pins.port_b() & 0x80, 0x80);
+        via.tick(4);
+        assert_eq!(pins.port_b() & 0x80, 0x00);
+        assert_eq!(via.next_event(), Some(4));
+
+        // Shift register out under the system clock, eight bits then an interrupt.
+        via.write(0xB, 0x18); // ACR: shift out under phi2
+        via.write(0xE, 0x84); // IER: enable SR
+        via.write(0xA, 0x81);
+        via.tick(15);
+        assert_eq!(via.peek(0xD) & 0x04, 0x00);
+        via.tick(1);
+        assert_eq!(via.peek(0xD) & 0x04, 0x04);
+        assert_eq!(via.peek(0xA), 0x81);
+
+        // CA1 edge with CA2 handshake output.
+        via.write(0xC, 0x09); // PCR: CA1 positive edge, CA2 handshake
+        via.write(0xE, 0x82);
+        pins.set_ca1(false);
+        via.write(0x1, 0x00);
+        assert!(!pins.ca2());
+        pins.set_ca1(true);
+        assert!(pins.ca2());
+        assert_eq!(via.peek(0xD) & 0x02, 0x02);
+
+        // Peripherals see pins driven by the host as well as by the VIA.
+        struct Probe(Rc<Cell<u8>>);
+
+        impl Peripheral for Probe {
+            fn update(&mut self, pins: &ViaPins) {
+                self.0.set(pins.port_b());
+            }
+        }
+
+        impl Snapshot for Probe {
+            fn save(&self, _writer: &mut SnapshotWriter) {}
+
+            fn restore(&mut self, _reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+                return Ok(());
+            }
+        }
+
+        let levels = Rc::new(Cell::new(0x00));
+        via.connect(Box::new(Probe(levels.clone())));
+        via.write(0x2, 0x0F); // DDRB
+        via.write(0x0, 0x05);
+        assert_eq!(levels.get(), 0xF5);
+        pins.set_port_b(0x3F);
+        assert_eq!(levels.get(), 0x35);
+
+        assert_eq!(Via6522::new(0xFFF8, builder.interrupts().source("VIA")).err(),
+            Some(MemoryError::OutOfAddressSpace { address: 0xFFF8, size: 0x10 }));
+    }
+
+    #[test]
+    fn test_via_control_lines() {
+        let lines = InterruptLines::new();
+        let mut via = Via6522::new(0x6000, lines.source("VIA")).unwrap();
+        let pins = via.pins();
+
+        // CB1 interrupts on the edge selected in the PCR and latches port B when enabled in the ACR.
+        via.write(0xB, 0x02); // ACR: latch port B
+        via.write(0xE, 0x90); // IER: enable CB1
+        pins.set_port_b(0x5A);
+        pins.set_cb1(true);
+        assert_eq!(via.peek(0xD) & 0x10, 0x00);
+        pins.set_cb1(false);
+        assert_eq!(via.peek(0xD), 0x90);
+        assert!(lines.irq());
+        pins.set_port_b(0xFF);
+        assert_eq!(via.read(0x0), 0x5A);
+        assert_eq!(via.peek(0xD) & 0x10, 0x00);
+        assert!(!lines.irq());
+
+        // CA2 as an input: mode 1 is an independent negative edge, port accesses leave its flag alone.
+        via.write(0xC, 0x02); // PCR: CA2 independent negative edge
+        pins.set_ca2(false);
+        assert_eq!(via.peek(0xD) & 0x01, 0x01);
+        via.read(0x1);
+        assert_eq!(via.peek(0xD) & 0x01, 0x01);
+        via.write(0xD, 0x01);
+        pins.set_ca2(true);
+        assert_eq!(via.peek(0xD) & 0x01, 0x00);
+
+        // Mode 2 is a positive edge cleared by port accesses.
+        via.write(0xC, 0x04); // PCR: CA2 positive edge
+        pins.set_ca2(false);
+        assert_eq!(via.peek(0xD) & 0x01, 0x00);
+        pins.set_ca2(true);
+        assert_eq!(via.peek(0xD) & 0x01, 0x01);
+        via.read(0x1);
+        assert_eq!(via.peek(0xD) & 0x01, 0x00);
+
+        // CB2 as an input, and ignored while it is an output.
+        via.write(0xC, 0x60); // PCR: CB2 independent positive edge
+        pins.set_cb2(false);
+        assert_eq!(via.peek(0xD) & 0x08, 0x00);
+        pins.set_cb2(true);
+        assert_eq!(via.peek(0xD) & 0x08, 0x08);
+        via.read(0x0);
+        assert_eq!(via.peek(0xD) & 0x08, 0x08);
+        via.write(0xC, 0xC0); // PCR: CB2 low
+        pins.set_cb2(true);
+        assert!(!pins.cb2());
+        via.write(0xC, 0xE0); // PCR: CB2 high
+        assert!(pins.cb2());
+
+        // CB2 handshake: a write to port B pulls it low until the next active CB1 edge, reads do not.
+        via.write(0xC, 0x80); // PCR: CB2 handshake, CB1 negative edge
+        via.read(0x0);
+        assert!(pins.cb2());
+        via.write(0x0, 0x00);
+        assert!(!pins.cb2());
+        pins.set_cb1(true);
+        assert!(!pins.cb2());
+        pins.set_cb1(false);
+        assert!(pins.cb2());
+
+        // Pulse modes go low for a single cycle after the port access.
+        via.write(0xC, 0xA0); // PCR: CB2 pulse
+        via.write(0x0, 0x00);
+        assert!(!pins.cb2());
+        via.tick(1);
+        assert!(pins.cb2());
+        via.write(0xC, 0x0A); // PCR: CA2 pulse
+        via.read(0x1);
+        assert!(!pins.ca2());
+        via.tick(1);
+        assert!(pins.ca2());
+    }
+
+    #[test]
+    fn test_via_external_shift() {
+        let lines = InterruptLines::new();
+        let mut via = Via6522::new(0x6000, lines.source("VIA")).unwrap();
+        let pins = via.pins();
+
+        // Mode 3 shifts CB2 in on rising CB1 edges, time alone does not shift.
+        via.write(0xB, 0x0C); // ACR: shift in under CB1
+        via.read(0xA);
+        via.tick(100);
+        assert_eq!(via.peek(0xA), 0x00);
+        for (index, bit) in [true, false, true, true, false, false, true, false].into_iter().enumerate() {
+            assert_eq!(via.peek(0xD) & 0x04, 0x00, "bit {}", index);
+            pins.set_cb2(bit);
+            pins.set_cb1(false);
+            pins.set_cb1(true);
+        }
+        assert_eq!(via.peek(0xA), 0xB2);
+        assert_eq!(via.peek(0xD) & 0x04, 0x04);
+
+        // Once eight bits are in, further edges are ignored.
+        pins.set_cb1(false);
+        pins.set_cb1(true);
+        assert_eq!(via.peek(0xA), 0xB2);
+
+        // Mode 7 shifts out on CB2, one bit per rising CB1 edge.
+        via.write(0xB, 0x1C); // ACR: shift out under CB1
+        via.write(0xA, 0xA5);
+        assert_eq!(via.peek(0xD) & 0x04, 0x00);
+        let mut bits = Vec::new();
+        for _ in 0..8 {
+            pins.set_cb1(false);
+            pins.set_cb1(true);
+            bits.push(pins.cb2());
+        }
+        assert_eq!(bits, [true, false, true, false, false, true, false, true]);
+        assert_eq!(via.peek(0xD) & 0x04, 0x04);
+        assert_eq!(via.peek(0xA), 0xA5);
+    }
+
+    #[test]
+    fn test_via_timer2_catch_up() {
+        let lines = InterruptLines::new();
+        let mut via = Via6522::new(0x6000, lines.source("VIA")).unwrap();
+
+        // A catch-up longer than the counter wraps around however often it takes.
+        via.write(0x8, 0x10);
+        via.write(0x9, 0x00);
+        via.tick(0x30005);
+        assert_eq!((via.peek(0x8), via.peek(0x9)), (0x0B, 0x00));
+        assert_eq!(via.peek(0xD) & 0x20, 0x20);
+    }
+}
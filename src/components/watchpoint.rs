@@ -0,0 +1,114 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    // Opcode fetches, which are reads as well.
+    Execute,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Equal(u8),
+    NotEqual(u8),
+    // The bits selected by the mask have the given value, e.g. Masked(0x80, 0x80) for bit 7 set.
+    Masked(u8, u8),
+}
+
+impl Condition {
+    fn matches(&self, value: u8) -> bool {
+        return match *self {
+            Condition::Equal(expected) => value == expected,
+            Condition::NotEqual(expected) => value != expected,
+            Condition::Masked(mask, expected) => value & mask == expected & mask,
+        };
+    }
+}
+
+// Stops execution when the CPU accesses an inclusive range of bus addresses in one of the given ways,
+// optionally only when the value read, written or executed matches a condition. Reads and writes stop
+// after the instruction making them, execution stops in front of the instruction.
+//
+//     bus.add_watchpoint(Watchpoint::new(0x0200, 0x02FF, &[Access::Write]).when(Condition::Equal(0x00)));
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    start: u16,
+    end: u16,
+    accesses: Vec<Access>,
+    condition: Option<Condition>,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, accesses: &[Access]) -> Watchpoint {
+        return Watchpoint { start, end, accesses: accesses.to_vec(), condition: None };
+    }
+
+    pub fn when(mut self, condition: Condition) -> Watchpoint {
+        self.condition = Some(condition);
+        return self;
+    }
+
+    pub fn matches(&self, address: u16, value: u8, access: Access) -> bool {
+        return self.start <= address && address <= self.end
+            && self.accesses.contains(&access)
+            && self.condition.is_none_or(|condition| condition.matches(value));
+    }
+}
+
+// The access which triggered a watchpoint.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WatchpointHit {
+    // As returned by Bus::add_watchpoint.
+    pub watchpoint: usize,
+    pub address: u16,
+    pub value: u8,
+    pub access: Access,
+    // Address of the instruction which made the access.
+    pub program_counter: u16,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::bus::Bus;
+    use crate::components::cpu6502::CPU6502;
+    use crate::components::memory::RandomAccessMemory;
+
+    #[test]
+    fn test_watchpoints() {
+        let mut cpu = CPU6502::new();
+        let mut bus = Bus::new(RandomAccessMemory::new(0x0000, 0x8000).unwrap());
+        bus.write(0x0000, 0x18); // CLC
+        bus.write(0x0001, 0x06); // ASL $10
+        bus.write(0x0002, 0x10);
+        bus.write(0x0003, 0x06); // ASL $10
+        bus.write(0x0004, 0x10);
+        bus.write(0x0005, 0x18); // CLC
+        bus.write(0x0010, 0x21);
+
+        // Only the second shift writes a value with bit 7 set.
+        let write = bus.add_watchpoint(Watchpoint::new(0x0010, 0x001F, &[Access::Write]).when(Condition::Masked(0x80, 0x80)));
+        assert_eq!(cpu.run(&mut bus, 10), Some(WatchpointHit {
+            watchpoint: write,
+            address: 0x0010,
+            value: 0x84,
+            access: Access::Write,
+            program_counter: 0x0003,
+        }));
+        assert_eq!(cpu.registers().program_counter, 0x0005);
+
+        // Execution stops in front of the instruction, running on executes it.
+        assert!(bus.remove_watchpoint(write));
+        let execute = bus.add_watchpoint(Watchpoint::new(0x0005, 0x0005, &[Access::Execute]).when(Condition::Equal(0x18)));
+        assert_eq!(cpu.run(&mut bus, 10).map(|hit| (hit.watchpoint, hit.value, hit.program_counter)), Some((execute, 0x18, 0x0005)));
+        assert_eq!(cpu.registers().program_counter, 0x0005);
+        assert_eq!(cpu.run(&mut bus, 1), None);
+        assert_eq!(cpu.registers().program_counter, 0x0006);
+
+        // The first shift reads 0x84 and is let through, the second one reads 0x08.
+        assert!(bus.remove_watchpoint(execute));
+        let read = bus.add_watchpoint(Watchpoint::new(0x0010, 0x0010, &[Access::Read]).when(Condition::NotEqual(0x84)));
+        cpu.registers_mut().program_counter = 0x0001;
+        assert_eq!(cpu.run(&mut bus, 1), None);
+        assert_eq!(cpu.run(&mut bus, 1).map(|hit| (hit.watchpoint, hit.value, hit.program_counter)), Some((read, 0x08, 0x0003)));
+    }
+}
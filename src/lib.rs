@@ -0,0 +1,3 @@
+pub mod components;
+pub mod assembler;
+pub mod machines;
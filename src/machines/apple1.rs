@@ -0,0 +1,325 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::components::bus::{Bus, BusBuilder};
+use crate::components::cpu6502::CPU6502;
+use crate::components::interrupt::InterruptLines;
+use crate::components::memory::{RandomAccessMemory, ReadOnlyMemory};
+use crate::components::observer::{AccessKind, CpuObserver};
+use crate::components::pia6821::{self, Pia6821, PiaPins};
+use crate::components::serial::SerialBackend;
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::machines::MachineError;
+
+pub const PIA_ADDRESS: u16 = 0xD010;
+pub const MONITOR_ADDRESS: u16 = 0xFF00;
+// As much as most replicas ship with, the original board took 4K or 8K.
+pub const RAM_SIZE: usize = 0x8000;
+
+pub const COLUMNS: usize = 40;
+pub const ROWS: usize = 24;
+
+// Ends the session when typed on the host, the Apple 1 has no use for it.
+const QUIT: u8 = 0x03;
+
+type Console = Rc<RefCell<Box<dyn SerialBackend>>>;
+
+// The text the Apple 1 terminal section shows: 40 columns of upper case characters that scroll up
+// one line at a time. There is no cursor addressing, only carriage returns.
+struct Screen {
+    lines: VecDeque<String>,
+}
+
+impl Screen {
+    fn new() -> Screen {
+        let mut lines = VecDeque::new();
+        lines.push_back(String::new());
+        return Screen { lines };
+    }
+
+    fn new_line(&mut self) {
+        self.lines.push_back(String::new());
+        if self.lines.len() > ROWS {
+            self.lines.pop_front();
+        }
+    }
+
+    // Returns what to echo to the host terminal.
+    fn put(&mut self, character: u8) -> Option<u8> {
+        let character = match character {
+            0x0D => {
+                self.new_line();
+                return Some(b'\n');
+            }
+            0x20..=0x5F => character,
+            // The character generator only decodes six bits, lower case shows as upper case.
+            0x60..=0x7E => character - 0x20,
+            _ => return None,
+        };
+        if self.lines.back().map_or(0, |line| line.len()) == COLUMNS {
+            self.new_line();
+        }
+        self.lines.back_mut().unwrap().push(character as char);
+        return Some(character);
+    }
+}
+
+// The display side of the terminal, on port B. The monitor writes a character, which pulls DA (CB2)
+// low, the terminal takes it and acknowledges on RDA (CB1). PB7 tells the CPU the terminal is busy,
+// this one never is.
+struct Display {
+    console: Console,
+    screen: Rc<RefCell<Screen>>,
+}
+
+impl pia6821::Peripheral for Display {
+    fn update(&mut self, pins: &PiaPins) {
+        if pins.cb2() {
+            return;
+        }
+        if let Some(byte) = self.screen.borrow_mut().put(pins.port_b() & 0x7F) {
+            self.console.borrow_mut().transmit(byte);
+        }
+        pins.set_cb1(false);
+        pins.set_cb1(true);
+    }
+}
+
+// Notes when the CPU looks at the keyboard control register, which is how a program waits for a key.
+struct KeyboardPoll(Rc<Cell<bool>>);
+
+impl CpuObserver for KeyboardPoll {
+    fn read(&mut self, address: u16, _data: u8, _kind: AccessKind) {
+        if address == PIA_ADDRESS + 1 {
+            self.0.set(true);
+        }
+    }
+}
+
+// What is on screen is not part of the machine state.
+impl Snapshot for Display {
+    fn save(&self, _writer: &mut SnapshotWriter) {}
+
+    fn restore(&mut self, _reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        return Ok(());
+    }
+}
+
+// An Apple 1: RAM from $0000, the PIA at $D010-$D013 with the keyboard on port A and the terminal on
+// port B, and the Woz Monitor ROM at $FF00. Keys come from and characters go to a serial backend,
+// usually the host terminal:
+//
+//     let mut apple1 = Apple1::new("wozmon.rom", Box::new(TerminalBackend::new()))?;
+//     while apple1.running() {
+//         apple1.step();
+//     }
+pub struct Apple1 {
+    cpu: CPU6502,
+    bus: Bus,
+    keyboard: PiaPins,
+    console: Console,
+    screen: Rc<RefCell<Screen>>,
+    polled: Rc<Cell<bool>>,
+    running: bool,
+}
+
+impl Apple1 {
+    pub fn new(monitor: impl AsRef<Path>, console: Box<dyn SerialBackend>) -> Result<Apple1, MachineError> {
+        let console: Console = Rc::new(RefCell::new(console));
+        let screen = Rc::new(RefCell::new(Screen::new()));
+
+        let builder = BusBuilder::new();
+        // IRQA and IRQB are not connected on the board, the monitor polls and runs with interrupts
+        // enabled.
+        let mut pia = Pia6821::new(PIA_ADDRESS, InterruptLines::new().source("PIA"));
+        let keyboard = pia.pins();
+        // PB7 low: the display is ready for the next character.
+        keyboard.set_port_b(0x7F);
+        pia.connect(Box::new(Display { console: console.clone(), screen: screen.clone() }));
+
+        let mut bus = builder
+            .attach(Box::new(RandomAccessMemory::new(0x0000, RAM_SIZE)?))
+            .attach(Box::new(pia))
+            .attach(Box::new(ReadOnlyMemory::from_file(MONITOR_ADDRESS, monitor)?))
+            .build()?;
+
+        let mut cpu = CPU6502::new();
+        let polled = Rc::new(Cell::new(false));
+        cpu.attach_observer(Box::new(KeyboardPoll(polled.clone())));
+        cpu.reset(&mut bus);
+        return Ok(Apple1 { cpu, bus, keyboard, console, screen, polled, running: true });
+    }
+
+    // Passes a waiting key to the keyboard port, then executes one instruction.
+    pub fn step(&mut self) {
+        self.poll_keyboard();
+        self.polled.set(false);
+        self.cpu.tick(&mut self.bus);
+        // Once the input has ended, e.g. piped stdin, the session ends when the program waits for the
+        // next key.
+        if self.polled.get() && !self.keyboard.ca1_flag() && self.console.borrow().closed() {
+            self.running = false;
+        }
+    }
+
+    // False once the host asked to quit with Ctrl-C, or the input ended and nothing is left to do.
+    pub fn running(&self) -> bool {
+        return self.running;
+    }
+
+    // The lines on screen, oldest first.
+    pub fn screen(&self) -> String {
+        return self.screen.borrow().lines.iter().map(String::as_str).collect::<Vec<_>>().join("\n");
+    }
+
+    pub fn cpu(&mut self) -> &mut CPU6502 {
+        return &mut self.cpu;
+    }
+
+    pub fn bus(&mut self) -> &mut Bus {
+        return &mut self.bus;
+    }
+
+    // A key is strobed in with a rising edge on CA1 and waits until the monitor reads port A.
+    fn poll_keyboard(&mut self) {
+        if self.keyboard.ca1_flag() {
+            return;
+        }
+        let key = match self.console.borrow_mut().receive() {
+            Some(QUIT) => {
+                self.running = false;
+                return;
+            }
+            Some(key) => key,
+            None => return,
+        };
+        let key = match key {
+            b'\n' => 0x0D,
+            // Backspace and delete rub out, which the monitor expects as an underscore.
+            0x08 | 0x7F => b'_',
+            b'a'..=b'z' => key.to_ascii_uppercase(),
+            _ => key,
+        };
+        // Bit 7 is always set on the keyboard data lines.
+        self.keyboard.set_port_a(key | 0x80);
+        self.keyboard.set_ca1(false);
+        self.keyboard.set_ca1(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::serial::BufferBackend;
+    use crate::components::testing::TempPath;
+
+    // The Woz Monitor as shipped in the Apple 1 ROM.
+    const WOZMON: [u8; 0x100] = [
+        0xD8, 0x58, 0xA0, 0x7F, 0x8C, 0x12, 0xD0, 0xA9, 0xA7, 0x8D, 0x11, 0xD0, 0x8D, 0x13, 0xD0, 0xC9,
+        0xDF, 0xF0, 0x13, 0xC9, 0x9B, 0xF0, 0x03, 0xC8, 0x10, 0x0F, 0xA9, 0xDC, 0x20, 0xEF, 0xFF, 0xA9,
+        0x8D, 0x20, 0xEF, 0xFF, 0xA0, 0x01, 0x88, 0x30, 0xF6, 0xAD, 0x11, 0xD0, 0x10, 0xFB, 0xAD, 0x10,
+        0xD0, 0x99, 0x00, 0x02, 0x20, 0xEF, 0xFF, 0xC9, 0x8D, 0xD0, 0xD4, 0xA0, 0xFF, 0xA9, 0x00, 0xAA,
+        0x0A, 0x85, 0x2B, 0xC8, 0xB9, 0x00, 0x02, 0xC9, 0x8D, 0xF0, 0xD4, 0xC9, 0xAE, 0x90, 0xF4, 0xF0,
+        0xF0, 0xC9, 0xBA, 0xF0, 0xEB, 0xC9, 0xD2, 0xF0, 0x3B, 0x86, 0x28, 0x86, 0x29, 0x84, 0x2A, 0xB9,
+        0x00, 0x02, 0x49, 0xB0, 0xC9, 0x0A, 0x90, 0x06, 0x69, 0x88, 0xC9, 0xFA, 0x90, 0x11, 0x0A, 0x0A,
+        0x0A, 0x0A, 0xA2, 0x04, 0x0A, 0x26, 0x28, 0x26, 0x29, 0xCA, 0xD0, 0xF8, 0xC8, 0xD0, 0xE0, 0xC4,
+        0x2A, 0xF0, 0x97, 0x24, 0x2B, 0x50, 0x10, 0xA5, 0x28, 0x81, 0x26, 0xE6, 0x26, 0xD0, 0xB5, 0xE6,
+        0x27, 0x4C, 0x44, 0xFF, 0x6C, 0x24, 0x00, 0x30, 0x2B, 0xA2, 0x02, 0xB5, 0x27, 0x95, 0x25, 0x95,
+        0x23, 0xCA, 0xD0, 0xF7, 0xD0, 0x14, 0xA9, 0x8D, 0x20, 0xEF, 0xFF, 0xA5, 0x25, 0x20, 0xDC, 0xFF,
+        0xA5, 0x24, 0x20, 0xDC, 0xFF, 0xA9, 0xBA, 0x20, 0xEF, 0xFF, 0xA9, 0xA0, 0x20, 0xEF, 0xFF, 0xA1,
+        0x24, 0x20, 0xDC, 0xFF, 0x86, 0x2B, 0xA5, 0x24, 0xC5, 0x28, 0xA5, 0x25, 0xE5, 0x29, 0xB0, 0xC1,
+        0xE6, 0x24, 0xD0, 0x02, 0xE6, 0x25, 0xA5, 0x24, 0x29, 0x07, 0x10, 0xC8, 0x48, 0x4A, 0x4A, 0x4A,
+        0x4A, 0x20, 0xE5, 0xFF, 0x68, 0x29, 0x0F, 0x09, 0xB0, 0xC9, 0xBA, 0x90, 0x02, 0x69, 0x06, 0x2C,
+        0x12, 0xD0, 0x30, 0xFB, 0x8D, 0x12, 0xD0, 0x60, 0x00, 0x00, 0x00, 0x0F, 0x00, 0xFF, 0x00, 0x00,
+    ];
+
+    fn run(apple1: &mut Apple1, instructions: usize) {
+        for _ in 0..instructions {
+            apple1.step();
+        }
+    }
+
+    #[test]
+    fn test_apple1() {
+        // A monitor which only spins, the PIA is driven the way the Woz Monitor sets it up.
+        let mut monitor = vec![0x18, 0x90, 0xFD]; // CLC, BCC -3
+        monitor.resize(0x100, 0x00);
+        monitor[0xFC] = 0x00;
+        monitor[0xFD] = 0xFF;
+        let file = TempPath::file("apple1.bin", &monitor);
+        let console = BufferBackend::new();
+        let mut apple1 = Apple1::new(file.path(), Box::new(console.clone())).unwrap();
+        assert_eq!(apple1.cpu().registers().program_counter, 0xFF00);
+
+        apple1.bus().write(0xD012, 0x7F); // DDRB
+        apple1.bus().write(0xD011, 0xA7);
+        apple1.bus().write(0xD013, 0xA7);
+
+        console.send(b"a\n");
+        apple1.step();
+        assert_eq!(apple1.bus().read(0xD011) & 0x80, 0x80);
+        assert_eq!(apple1.bus().read(0xD010), 0xC1);
+        assert_eq!(apple1.bus().read(0xD011) & 0x80, 0x00);
+        apple1.step();
+        assert_eq!(apple1.bus().read(0xD010), 0x8D);
+
+        // The display is always ready and echoes to the console.
+        assert_eq!(apple1.bus().read(0xD012) & 0x80, 0x00);
+        for character in b"\\\rhi" {
+            apple1.bus().write(0xD012, character | 0x80);
+        }
+        assert_eq!(console.take_output(), b"\\\nHI");
+        for _ in 0..40 {
+            apple1.bus().write(0xD012, b'X' | 0x80);
+        }
+        assert_eq!(apple1.screen(), format!("\\\nHI{}\nXX", "X".repeat(38)));
+
+        console.send(&[0x03]);
+        apple1.step();
+        assert!(!apple1.running());
+    }
+
+    #[test]
+    fn test_wozmon() {
+        let file = TempPath::file("wozmon.bin", &WOZMON);
+        let console = BufferBackend::new();
+        let mut apple1 = Apple1::new(file.path(), Box::new(console.clone())).unwrap();
+
+        // The monitor sets up the PIA, greets with a backslash on a line of its own and waits for a key.
+        run(&mut apple1, 1_000);
+        assert_eq!(console.take_output(), b"\\\n");
+
+        // Examining an address prints it with the byte stored there, CLD is the first instruction.
+        console.send(b"FF00\r");
+        run(&mut apple1, 10_000);
+        assert_eq!(console.take_output(), b"FF00\n\nFF00: D8\n");
+
+        // A range prints eight bytes per line, a store writes the bytes after the colon.
+        console.send(b"\r0300: A9 01\r300.301\r");
+        run(&mut apple1, 50_000);
+        assert_eq!(apple1.bus().peek(0x0300), 0xA9);
+        assert_eq!(apple1.bus().peek(0x0301), 0x01);
+        assert!(apple1.screen().ends_with("300.301\n\n0300: A9 01\n"), "{}", apple1.screen());
+    }
+
+    #[test]
+    fn test_piped_input() {
+        let file = TempPath::file("wozmon.bin", &WOZMON);
+        let console = BufferBackend::new();
+        let mut apple1 = Apple1::new(file.path(), Box::new(console.clone())).unwrap();
+
+        // Once the input is used up the session ends when the monitor waits for the next key, after
+        // it printed the answer to the last line.
+        console.send(b"FF00\r");
+        for _ in 0..100_000 {
+            if !apple1.running() {
+                break;
+            }
+            apple1.step();
+        }
+        assert!(!apple1.running());
+        assert_eq!(console.take_output(), b"\\\nFF00\n\nFF00: D8\n");
+    }
+}
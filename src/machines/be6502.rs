@@ -0,0 +1,256 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::components::acia6551::Acia6551;
+use crate::components::bus::{Bus, BusBuilder};
+use crate::components::cpu6502::CPU6502;
+use crate::components::device::Addressable;
+use crate::components::hd44780::{Hd44780, LcdDisplay, Wiring};
+use crate::components::memory::{RandomAccessMemory, ReadOnlyMemory};
+use crate::components::serial::SerialBackend;
+use crate::components::via6522::{Via6522, ViaPins};
+use crate::machines::MachineError;
+
+pub const RAM_SIZE: usize = 0x4000;
+pub const ACIA_ADDRESS: u16 = 0x5000;
+pub const VIA_ADDRESS: u16 = 0x6000;
+pub const ROM_ADDRESS: u16 = 0x8000;
+pub const ROM_SIZE: usize = 0x8000;
+// The kit's oscillator, which also times the LCD and the serial port.
+pub const CLOCK: u64 = 1_000_000;
+
+// How often a paced machine compares emulated with wall clock time, in cycles.
+const PACING_INTERVAL: u64 = 10_000;
+
+// Ben Eater's BE6502 breadboard computer: 16K RAM at $0000, the 6551 ACIA at $5000, the 6522 VIA at
+// $6000 with a 16x2 LCD on its ports and the 32K EEPROM at $8000. Like the board's address decoding
+// the ACIA and VIA repeat throughout $5000-$5FFF and $6000-$7FFF. The push button of the interrupt
+// videos pulls CA1 low while pressed.
+//
+//     let mut be6502 = Be6502::new("a.out", Box::new(TerminalBackend::new()), Wiring::eight_bit())?;
+//     be6502.set_paced(true);
+//     loop {
+//         be6502.step();
+//     }
+pub struct Be6502 {
+    cpu: CPU6502,
+    bus: Bus,
+    lcd: LcdDisplay,
+    pins: ViaPins,
+    // Wall clock time and cycle count pacing started at, None while running as fast as possible.
+    pacing: Option<(Instant, u64)>,
+    next_check: u64,
+}
+
+impl Be6502 {
+    // The ROM image has to be the full 32K the EEPROM holds, as the usual `vasm -Fbin -dotdir` output is.
+    pub fn new(rom: impl AsRef<Path>, serial: Box<dyn SerialBackend>, wiring: Wiring) -> Result<Be6502, MachineError> {
+        return Be6502::build(ReadOnlyMemory::from_file(ROM_ADDRESS, rom)?, serial, wiring);
+    }
+
+    // Burns an image already in memory into the EEPROM, e.g. one from the assembler.
+    pub fn with_rom(rom: &[u8], serial: Box<dyn SerialBackend>, wiring: Wiring) -> Result<Be6502, MachineError> {
+        return Be6502::build(ReadOnlyMemory::new(ROM_ADDRESS, rom)?, serial, wiring);
+    }
+
+    fn build(rom: ReadOnlyMemory, serial: Box<dyn SerialBackend>, wiring: Wiring) -> Result<Be6502, MachineError> {
+        let builder = BusBuilder::new();
+
+        let mut via = Via6522::new(VIA_ADDRESS, builder.interrupts().source("VIA"))?;
+        let pins = via.pins();
+        let lcd = Hd44780::new(16, 2, wiring, CLOCK);
+        let display = lcd.display();
+        via.connect(Box::new(lcd));
+        let acia = Acia6551::new(ACIA_ADDRESS, builder.interrupts().source("ACIA"), serial, CLOCK);
+
+        let (start, end) = rom.get_address_space();
+        let size = (end - start) as usize + 1;
+        if size != ROM_SIZE {
+            return Err(MachineError::RomSize { expected: ROM_SIZE, actual: size });
+        }
+
+        let mut bus = builder
+            .attach(Box::new(RandomAccessMemory::new(0x0000, RAM_SIZE)?))
+            .attach(Box::new(acia))
+            .mirror(ACIA_ADDRESS + 0x04, 0x5FFF, ACIA_ADDRESS, ACIA_ADDRESS + 0x03)
+            .attach(Box::new(via))
+            .mirror(VIA_ADDRESS + 0x10, 0x7FFF, VIA_ADDRESS, VIA_ADDRESS + 0x0F)
+            .attach(Box::new(rom))
+            .build()?;
+
+        let mut cpu = CPU6502::new();
+        cpu.reset(&mut bus);
+        return Ok(Be6502 { cpu, bus, lcd: display, pins, pacing: None, next_check: 0 });
+    }
+
+    // Runs at the kit's 1 MHz in real time instead of as fast as the host allows.
+    pub fn set_paced(&mut self, paced: bool) {
+        self.pacing = if paced { Some((Instant::now(), self.cpu.cycles())) } else { None };
+        self.next_check = self.cpu.cycles() + PACING_INTERVAL;
+    }
+
+    pub fn step(&mut self) {
+        self.cpu.tick(&mut self.bus);
+
+        if let Some((start, cycles)) = self.pacing {
+            if self.cpu.cycles() >= self.next_check {
+                self.next_check = self.cpu.cycles() + PACING_INTERVAL;
+                let emulated = Duration::from_nanos((self.cpu.cycles() - cycles) * 1_000_000_000 / CLOCK);
+                if let Some(ahead) = emulated.checked_sub(start.elapsed()) {
+                    std::thread::sleep(ahead);
+                }
+            }
+        }
+    }
+
+    // Presses and releases the button on CA1.
+    pub fn press_button(&self) {
+        self.pins.set_ca1(false);
+        self.pins.set_ca1(true);
+    }
+
+    pub fn lcd(&self) -> &LcdDisplay {
+        return &self.lcd;
+    }
+
+    pub fn cpu(&mut self) -> &mut CPU6502 {
+        return &mut self.cpu;
+    }
+
+    pub fn bus(&mut self) -> &mut Bus {
+        return &mut self.bus;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::components::serial::BufferBackend;
+    use crate::components::testing::TempPath;
+
+    // The "hello world" of the videos, waiting on the LCD's busy flag.
+    const HELLO: &str = "
+        PORTB = $6000
+        PORTA = $6001
+        DDRB = $6002
+        DDRA = $6003
+
+        E  = %10000000
+        RW = %01000000
+        RS = %00100000
+
+          .org $8000
+
+        reset:
+          ldx #$ff
+          txs
+
+          lda #%11111111 ; Set all pins on port B to output
+          sta DDRB
+          lda #%11100000 ; Set top 3 pins on port A to output
+          sta DDRA
+
+          lda #%00111000 ; Set 8-bit mode; 2-line display; 5x8 font
+          jsr lcd_instruction
+          lda #%00001110 ; Display on; cursor on; blink off
+          jsr lcd_instruction
+          lda #%00000110 ; Increment and shift cursor; don't shift display
+          jsr lcd_instruction
+          lda #$00000001 ; Clear display
+          jsr lcd_instruction
+
+          ldx #0
+        print:
+          lda message,x
+          beq loop
+          jsr print_char
+          inx
+          jmp print
+
+        loop:
+          jmp loop
+
+        message: .asciiz \"Hello, world!\"
+
+        lcd_wait:
+          pha
+          lda #%00000000  ; Port B is input
+          sta DDRB
+        lcdbusy:
+          lda #RW
+          sta PORTA
+          lda #(RW | E)
+          sta PORTA
+          lda PORTB
+          and #%10000000
+          bne lcdbusy
+
+          lda #RW
+          sta PORTA
+          lda #%11111111  ; Port B is output
+          sta DDRB
+          pla
+          rts
+
+        lcd_instruction:
+          jsr lcd_wait
+          sta PORTB
+          lda #0         ; Clear RS/RW/E bits
+          sta PORTA
+          lda #E         ; Set E bit to send instruction
+          sta PORTA
+          lda #0         ; Clear RS/RW/E bits
+          sta PORTA
+          rts
+
+        print_char:
+          jsr lcd_wait
+          sta PORTB
+          lda #RS         ; Set RS; Clear RW/E bits
+          sta PORTA
+          lda #(RS | E)   ; Set E bit to send instruction
+          sta PORTA
+          lda #RS         ; Clear E bits
+          sta PORTA
+          rts
+
+          .org $fffc
+          .word reset
+          .word $0000
+    ";
+
+    #[test]
+    fn test_hello_world() {
+        let image = assemble(HELLO).unwrap().image(ROM_ADDRESS, ROM_SIZE, 0x00).unwrap();
+        let mut be6502 = Be6502::with_rom(&image, Box::new(BufferBackend::new()), Wiring::eight_bit()).unwrap();
+        for _ in 0..5_000 {
+            be6502.step();
+        }
+        assert_eq!(be6502.lcd().text(), "Hello, world!   \n                ");
+        assert!(be6502.lcd().render().contains("Hello, world!"));
+    }
+
+    #[test]
+    fn test_be6502() {
+        let mut rom = vec![0xEA; 0x8000];
+        rom[0x7FFC] = 0x00; // Reset vector $8000
+        rom[0x7FFD] = 0x80;
+        let short = TempPath::file("be6502_short.bin", &rom[..0x4000]);
+        let short = Be6502::new(short.path(), Box::new(BufferBackend::new()), Wiring::eight_bit());
+        assert!(matches!(short, Err(MachineError::RomSize { expected: 0x8000, actual: 0x4000 })));
+
+        let file = TempPath::file("be6502.bin", &rom);
+        let console = BufferBackend::new();
+        let mut be6502 = Be6502::new(file.path(), Box::new(console.clone()), Wiring::eight_bit()).unwrap();
+        assert_eq!(be6502.cpu().registers().program_counter, 0x8000);
+
+        // The ACIA repeats through $5FFF and sends through the console.
+        let bus = be6502.bus();
+        bus.write(0x5003, 0x1F);
+        bus.write(0x5FF6, 0x0B); // command, through the mirror
+        bus.write(0x5000, b'x');
+        bus.tick(520);
+        assert_eq!(console.take_output(), b"x");
+    }
+}
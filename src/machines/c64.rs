@@ -0,0 +1,805 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::components::bus::{Bus, BusBuilder};
+use crate::components::cpu6502::{Flags, Registers, CPU6502};
+use crate::components::memory::{MemoryError, RandomAccessMemory};
+use crate::components::serial::SerialBackend;
+use crate::components::text_video::{Charset, TextVideo};
+use crate::machines::MachineError;
+
+pub const SCREEN_ADDRESS: u16 = 0x0400;
+pub const COLOUR_ADDRESS: u16 = 0xD800;
+pub const BASIC_START: u16 = 0x0801;
+// BASIC's warm start, where programs end up when they return. Running into it ends the run.
+pub const READY: u16 = 0xA474;
+
+// KERNAL jump table entries.
+pub const READST: u16 = 0xFFB7;
+pub const SETLFS: u16 = 0xFFBA;
+pub const SETNAM: u16 = 0xFFBD;
+pub const OPEN: u16 = 0xFFC0;
+pub const CLOSE: u16 = 0xFFC3;
+pub const CHKIN: u16 = 0xFFC6;
+pub const CHKOUT: u16 = 0xFFC9;
+pub const CLRCHN: u16 = 0xFFCC;
+pub const CHRIN: u16 = 0xFFCF;
+pub const CHROUT: u16 = 0xFFD2;
+pub const LOAD: u16 = 0xFFD5;
+pub const STOP: u16 = 0xFFE1;
+pub const GETIN: u16 = 0xFFE4;
+pub const CLALL: u16 = 0xFFE7;
+pub const PLOT: u16 = 0xFFF0;
+
+// Zero page and system variables the KERNAL keeps.
+const STATUS: u16 = 0x90;
+const COLUMN: u16 = 0xD3;
+const ROW: u16 = 0xD6;
+const COLOUR: u16 = 0x0286;
+
+// KERNAL error codes, returned in A with carry set.
+const FILE_NOT_OPEN: u8 = 3;
+const FILE_NOT_FOUND: u8 = 4;
+
+// Status bits.
+const END_OF_FILE: u8 = 0x40;
+
+const COLUMNS: u16 = 40;
+const ROWS: u16 = 25;
+
+// An open file, read into memory on OPEN or collected until CLOSE writes it.
+struct Channel {
+    data: Vec<u8>,
+    position: usize,
+    write: Option<PathBuf>,
+}
+
+struct Kernal {
+    console: Box<dyn SerialBackend>,
+    // Where LOAD and OPEN look for files, the directory of the program.
+    directory: PathBuf,
+    // Switched by CHROUT $0E and $8E, decides how letters show on the console.
+    lowercase: bool,
+    reverse: bool,
+    // The rest of the line typed for CHRIN.
+    line: VecDeque<u8>,
+    logical_file: u8,
+    device: u8,
+    secondary: u8,
+    name: Vec<u8>,
+    files: HashMap<u8, Channel>,
+    input: u8,
+    output: u8,
+}
+
+impl Kernal {
+    fn set_status(&self, bus: &mut Bus, bits: u8) {
+        let status = bus.peek(STATUS);
+        bus.write(STATUS, status | bits);
+    }
+
+    fn file_name(&self) -> String {
+        let name: String = self.name.iter().map(|byte| match *byte {
+            letter @ 0x41..=0x5A => (letter + 0x20) as char,
+            letter @ 0xC1..=0xDA => (letter - 0x80) as char,
+            byte => byte as char,
+        }).collect();
+        // Drive prefixes like "0:" and "@0:" mean nothing on the host.
+        let name = name.trim_start_matches('@');
+        let name = name.split_once(':').map_or(name, |(_, name)| name);
+        return String::from(name.split(',').next().unwrap_or(""));
+    }
+
+    // Matches without regard to case, C64 names usually come in upper case.
+    fn find(&self, name: &str) -> Option<PathBuf> {
+        let exact = self.directory.join(name);
+        if exact.is_file() {
+            return Some(exact);
+        }
+        let entries = std::fs::read_dir(&self.directory).ok()?;
+        return entries.flatten()
+            .find(|entry| entry.file_name().to_string_lossy().eq_ignore_ascii_case(name))
+            .map(|entry| entry.path());
+    }
+
+    fn chrout(&mut self, bus: &mut Bus, character: u8) {
+        if self.output != 0 && self.output != 3 {
+            if let Some(channel) = self.files.get_mut(&self.output) {
+                channel.data.push(character);
+            }
+            return;
+        }
+
+        let mut column = bus.peek(COLUMN) as u16 % COLUMNS;
+        let mut row = bus.peek(ROW) as u16 % ROWS;
+        match character {
+            0x0D | 0x8D => {
+                column = 0;
+                row += 1;
+                self.reverse = false;
+                self.console.transmit(b'\n');
+            }
+            0x93 => {
+                for offset in 0..COLUMNS * ROWS {
+                    bus.write(SCREEN_ADDRESS + offset, 0x20);
+                }
+                column = 0;
+                row = 0;
+                for byte in b"\x1b[2J\x1b[H" {
+                    self.console.transmit(*byte);
+                }
+            }
+            0x13 => {
+                column = 0;
+                row = 0;
+            }
+            0x11 => row += 1,
+            0x91 => row = row.saturating_sub(1),
+            0x1D => column += 1,
+            0x9D => column = column.saturating_sub(1),
+            0x14 => {
+                if column > 0 {
+                    column -= 1;
+                    bus.write(SCREEN_ADDRESS + row * COLUMNS + column, 0x20);
+                    for byte in b"\x08 \x08" {
+                        self.console.transmit(*byte);
+                    }
+                }
+            }
+            0x12 => self.reverse = true,
+            0x92 => self.reverse = false,
+            0x0E => self.lowercase = true,
+            0x8E => self.lowercase = false,
+            _ => {
+                let code = match screen_code(character) {
+                    Some(code) => code,
+                    // Colours and the other control codes.
+                    None => {
+                        if let Some(colour) = colour(character) {
+                            bus.write(COLOUR, colour);
+                        }
+                        return;
+                    }
+                };
+                let offset = row * COLUMNS + column;
+                bus.write(SCREEN_ADDRESS + offset, if self.reverse { code | 0x80 } else { code });
+                bus.write(COLOUR_ADDRESS + offset, bus.peek(COLOUR));
+                self.console.transmit(to_ascii(character, self.lowercase));
+                column += 1;
+                if column == COLUMNS {
+                    column = 0;
+                    row += 1;
+                }
+            }
+        }
+
+        if row == ROWS {
+            // Scroll up a line.
+            for offset in 0..COLUMNS * (ROWS - 1) {
+                let below = SCREEN_ADDRESS + offset + COLUMNS;
+                bus.write(SCREEN_ADDRESS + offset, bus.peek(below));
+                bus.write(COLOUR_ADDRESS + offset, bus.peek(COLOUR_ADDRESS + offset + COLUMNS));
+            }
+            for offset in COLUMNS * (ROWS - 1)..COLUMNS * ROWS {
+                bus.write(SCREEN_ADDRESS + offset, 0x20);
+            }
+            row = ROWS - 1;
+        }
+        bus.write(COLUMN, column as u8);
+        bus.write(ROW, row as u8);
+    }
+
+    // Waits for a key, None once the console is closed.
+    fn key(&mut self) -> Option<u8> {
+        loop {
+            if let Some(key) = self.console.receive() {
+                return Some(from_ascii(key));
+            }
+            if self.console.closed() {
+                return None;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    fn chrin(&mut self, bus: &mut Bus) -> u8 {
+        if self.input != 0 {
+            let channel = match self.files.get_mut(&self.input) {
+                Some(channel) => channel,
+                None => return 0x0D,
+            };
+            let byte = channel.data.get(channel.position).copied().unwrap_or(0x0D);
+            channel.position += 1;
+            if channel.position >= channel.data.len() {
+                self.set_status(bus, END_OF_FILE);
+            }
+            return byte;
+        }
+
+        // The screen editor echoes a whole line before handing it out a character at a time. A closed
+        // console ends the line and sets the end of file status.
+        if self.line.is_empty() {
+            loop {
+                let key = match self.key() {
+                    Some(key) => key,
+                    None => {
+                        self.set_status(bus, END_OF_FILE);
+                        break;
+                    }
+                };
+                match key {
+                    0x0D => break,
+                    0x14 if self.line.pop_back().is_some() => self.chrout(bus, 0x14),
+                    0x14 => {}
+                    _ => {
+                        self.line.push_back(key);
+                        self.chrout(bus, key);
+                    }
+                }
+            }
+            self.line.push_back(0x0D);
+            self.chrout(bus, 0x0D);
+        }
+        return self.line.pop_front().unwrap();
+    }
+
+    // 0 while no key is waiting, None with the end of file status once the console is closed.
+    fn getin(&mut self, bus: &mut Bus) -> Option<u8> {
+        if self.input != 0 {
+            return Some(self.chrin(bus));
+        }
+        if let Some(key) = self.console.receive() {
+            return Some(from_ascii(key));
+        }
+        if self.console.closed() {
+            self.set_status(bus, END_OF_FILE);
+            return None;
+        }
+        return Some(0);
+    }
+
+    fn open(&mut self) -> Result<(), u8> {
+        if self.device < 8 {
+            return Ok(());
+        }
+        let name = self.file_name();
+        let options = String::from_utf8_lossy(&self.name).to_uppercase();
+        let channel = if self.secondary == 1 || options.contains(",W") {
+            let path = self.find(&name).unwrap_or_else(|| self.directory.join(&name));
+            Channel { data: Vec::new(), position: 0, write: Some(path) }
+        } else {
+            let path = self.find(&name).ok_or(FILE_NOT_FOUND)?;
+            let data = std::fs::read(path).map_err(|_| FILE_NOT_FOUND)?;
+            Channel { data, position: 0, write: None }
+        };
+        self.files.insert(self.logical_file, channel);
+        return Ok(());
+    }
+
+    fn close(&mut self, logical_file: u8) {
+        if let Some(Channel { data, write: Some(path), .. }) = self.files.remove(&logical_file) {
+            let _ = std::fs::write(path, data);
+        }
+        if self.input == logical_file {
+            self.input = 0;
+        }
+        if self.output == logical_file {
+            self.output = 0;
+        }
+    }
+
+    // Returns the address after the last byte loaded.
+    fn load(&mut self, bus: &mut Bus, address: u16) -> Result<u16, u8> {
+        let path = self.find(&self.file_name()).ok_or(FILE_NOT_FOUND)?;
+        let data = std::fs::read(path).map_err(|_| FILE_NOT_FOUND)?;
+        if data.len() < 2 {
+            return Err(FILE_NOT_FOUND);
+        }
+        let start = if self.secondary == 0 { address } else { u16::from_le_bytes([data[0], data[1]]) };
+        let mut end = start;
+        for byte in &data[2..] {
+            bus.write(end, *byte);
+            end = end.wrapping_add(1);
+        }
+        return Ok(end);
+    }
+}
+
+// PETSCII to the screen codes of the character set, None for control codes.
+fn screen_code(character: u8) -> Option<u8> {
+    return match character {
+        0x20..=0x3F => Some(character),
+        0x40..=0x5F => Some(character - 0x40),
+        0x60..=0x7F => Some(character - 0x20),
+        0xA0..=0xBF => Some(character - 0x40),
+        0xC0..=0xFE => Some(character - 0x80),
+        0xFF => Some(0x5E),
+        _ => None,
+    };
+}
+
+// Palette index of a colour control code.
+fn colour(character: u8) -> Option<u8> {
+    const CODES: [u8; 16] = [0x90, 0x05, 0x1C, 0x9F, 0x9C, 0x1E, 0x1F, 0x9E, 0x81, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0x9B];
+    return CODES.iter().position(|code| *code == character).map(|index| index as u8);
+}
+
+// How a printable PETSCII character shows on the host. Unshifted letters are upper case unless the
+// program switched to the lower case set, shifted letters are upper case then. Graphics show as #.
+fn to_ascii(character: u8, lowercase: bool) -> u8 {
+    return match character {
+        0x41..=0x5A if lowercase => character + 0x20,
+        0x41..=0x5A => character,
+        0xC1..=0xDA => character - 0x80,
+        0x5C => b'\\',
+        0x20..=0x5F => character,
+        0xA0 => b' ',
+        _ => b'#',
+    };
+}
+
+// A key typed on the host as the C64 keyboard would send it.
+fn from_ascii(key: u8) -> u8 {
+    return match key {
+        b'\r' | b'\n' => 0x0D,
+        0x08 | 0x7F => 0x14,
+        b'a'..=b'z' => key - 0x20,
+        b'A'..=b'Z' => key + 0x80,
+        _ => key,
+    };
+}
+
+// Where a program starts: the address of a `SYS` in the first line of a BASIC stub at $0801, else
+// the load address.
+fn start_address(load_address: u16, data: &[u8]) -> u16 {
+    if load_address != BASIC_START || data.len() < 5 {
+        return load_address;
+    }
+    // Skip the link to the next line and the line number.
+    let mut bytes = data[4..].iter().copied().take_while(|byte| *byte != 0).skip_while(|byte| *byte == b' ');
+    if bytes.next() != Some(0x9E) {
+        return load_address;
+    }
+    let digits: String = bytes.skip_while(|byte| *byte == b' ' || *byte == b'(')
+        .take_while(u8::is_ascii_digit)
+        .map(char::from)
+        .collect();
+    return digits.parse().unwrap_or(load_address);
+}
+
+// Runs C64 programs in a text terminal: no VIC, SID or CIAs, but RAM set up the way BASIC leaves it
+// and the common KERNAL routines done in Rust, with CHROUT writing both to the console and to the
+// screen RAM at $0400. The run ends when the program returns to BASIC.
+pub struct C64 {
+    cpu: CPU6502,
+    bus: Bus,
+    screen: TextVideo,
+    start: u16,
+    finished: Rc<Cell<bool>>,
+}
+
+impl C64 {
+    pub fn new(program: impl AsRef<Path>, console: Box<dyn SerialBackend>) -> Result<C64, MachineError> {
+        let program = program.as_ref();
+        let data = std::fs::read(program).map_err(|error| MemoryError::Io(format!("{}: {}", program.display(), error)))?;
+        if data.len() < 2 {
+            return Err(MemoryError::InvalidSize(data.len()).into());
+        }
+        let load_address = u16::from_le_bytes([data[0], data[1]]);
+        let body = &data[2..];
+        if load_address as usize + body.len() > 0x10000 {
+            return Err(MemoryError::OutOfAddressSpace { address: load_address, size: body.len() }.into());
+        }
+
+        let screen = TextVideo::new(COLUMNS as usize, ROWS as usize, Charset::Petscii)?;
+        screen.set_background(0x06);
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000)?))
+            .map_with_priority(SCREEN_ADDRESS, SCREEN_ADDRESS + 999, Box::new(screen.screen_ram(SCREEN_ADDRESS)?), 1)
+            .map_with_priority(COLOUR_ADDRESS, COLOUR_ADDRESS + 999, Box::new(screen.colour_ram(COLOUR_ADDRESS)?), 1)
+            .build()?;
+
+        for (offset, byte) in body.iter().enumerate() {
+            bus.write(load_address.wrapping_add(offset as u16), *byte);
+        }
+        // A program reaching $FFFF ends at $0000.
+        let end = load_address.wrapping_add(body.len() as u16);
+        setup(&mut bus, end);
+
+        let directory = program.parent().map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        let kernal = Rc::new(RefCell::new(Kernal {
+            console,
+            directory,
+            lowercase: false,
+            reverse: false,
+            line: VecDeque::new(),
+            logical_file: 0,
+            device: 0,
+            secondary: 0,
+            name: Vec::new(),
+            files: HashMap::new(),
+            input: 0,
+            output: 0,
+        }));
+
+        let mut cpu = CPU6502::new();
+        let finished = Rc::new(Cell::new(false));
+        install(&mut cpu, &kernal, &finished);
+
+        // As if BASIC had called the program with SYS: returning lands in READY.
+        let start = start_address(load_address, body);
+        let [low, high] = (READY - 1).to_le_bytes();
+        bus.write(0x01FA, low);
+        bus.write(0x01FB, high);
+        let registers = cpu.registers_mut();
+        registers.stack_pointer = 0xF9;
+        registers.program_counter = start;
+        return Ok(C64 { cpu, bus, screen, start, finished });
+    }
+
+    pub fn step(&mut self) {
+        self.cpu.tick(&mut self.bus);
+    }
+
+    // False once the program returned to BASIC, or polled for a key after the console was closed.
+    pub fn running(&self) -> bool {
+        return !self.finished.get();
+    }
+
+    // Where the program was started, from its SYS line or load address.
+    pub fn start(&self) -> u16 {
+        return self.start;
+    }
+
+    pub fn screen(&self) -> &TextVideo {
+        return &self.screen;
+    }
+
+    pub fn cpu(&mut self) -> &mut CPU6502 {
+        return &mut self.cpu;
+    }
+
+    pub fn bus(&mut self) -> &mut Bus {
+        return &mut self.bus;
+    }
+}
+
+// Memory the way the KERNAL and BASIC leave it after power-on and loading a program.
+fn setup(bus: &mut Bus, end: u16) {
+    let words: [(u16, u16); 10] = [
+        (0x2B, BASIC_START), // start of BASIC
+        (0x2D, end),         // start of variables
+        (0x2F, end),         // start of arrays
+        (0x31, end),         // end of arrays
+        (0x33, 0xA000),      // bottom of strings
+        (0x37, 0xA000),      // top of BASIC memory
+        (0x0281, 0x0800),    // bottom of memory
+        (0x0283, 0xA000),    // top of memory
+        (0x0314, 0xEA31),    // IRQ vector
+        (0xFFFE, READY),     // BRK ends the run
+    ];
+    for (address, value) in words {
+        let [low, high] = value.to_le_bytes();
+        bus.write(address, low);
+        bus.write(address + 1, high);
+    }
+
+    // Processor port: BASIC, KERNAL and I/O banked in.
+    bus.write(0x00, 0x2F);
+    bus.write(0x01, 0x37);
+    bus.write(0x0288, (SCREEN_ADDRESS >> 8) as u8);
+    bus.write(COLOUR, 0x0E);
+    bus.write(0xD020, 0x0E);
+    bus.write(0xD021, 0x06);
+    for offset in 0..COLUMNS * ROWS {
+        bus.write(SCREEN_ADDRESS + offset, 0x20);
+        bus.write(COLOUR_ADDRESS + offset, 0x0E);
+    }
+    // RTS at the jump table entries, for programs which look at them.
+    for address in [READST, SETLFS, SETNAM, OPEN, CLOSE, CHKIN, CHKOUT, CLRCHN, CHRIN, CHROUT, LOAD, STOP, GETIN, CLALL, PLOT] {
+        bus.write(address, 0x60);
+    }
+}
+
+fn succeed(registers: &mut Registers, result: Result<(), u8>) {
+    match result {
+        Ok(()) => registers.set_flag(Flags::Carry, false),
+        Err(error) => {
+            registers.accumulator = error;
+            registers.set_flag(Flags::Carry, true);
+        }
+    }
+}
+
+fn install(cpu: &mut CPU6502, kernal: &Rc<RefCell<Kernal>>, finished: &Rc<Cell<bool>>) {
+    let routine = |cpu: &mut CPU6502, address: u16, routine: fn(&mut Kernal, &mut Registers, &mut Bus)| {
+        let kernal = kernal.clone();
+        cpu.add_trap(address, move |registers, bus| routine(&mut kernal.borrow_mut(), registers, bus));
+    };
+
+    routine(cpu, CHROUT, |kernal, registers, bus| {
+        kernal.chrout(bus, registers.accumulator);
+        registers.set_flag(Flags::Carry, false);
+    });
+    routine(cpu, CHRIN, |kernal, registers, bus| {
+        registers.accumulator = kernal.chrin(bus);
+        registers.set_flag(Flags::Carry, false);
+    });
+    routine(cpu, PLOT, |_, registers, bus| {
+        if registers.get_flag(Flags::Carry) {
+            registers.idx_x = bus.peek(ROW);
+            registers.idx_y = bus.peek(COLUMN);
+        } else {
+            bus.write(ROW, registers.idx_x % ROWS as u8);
+            bus.write(COLUMN, registers.idx_y % COLUMNS as u8);
+        }
+    });
+    routine(cpu, SETLFS, |kernal, registers, _| {
+        kernal.logical_file = registers.accumulator;
+        kernal.device = registers.idx_x;
+        kernal.secondary = registers.idx_y;
+    });
+    routine(cpu, SETNAM, |kernal, registers, bus| {
+        let address = u16::from_le_bytes([registers.idx_x, registers.idx_y]);
+        kernal.name = (0..registers.accumulator as u16).map(|offset| bus.peek(address.wrapping_add(offset))).collect();
+    });
+    routine(cpu, OPEN, |kernal, registers, _| {
+        let result = kernal.open();
+        succeed(registers, result);
+    });
+    routine(cpu, CLOSE, |kernal, registers, _| {
+        kernal.close(registers.accumulator);
+        registers.set_flag(Flags::Carry, false);
+    });
+    routine(cpu, CHKIN, |kernal, registers, _| {
+        let result = if kernal.files.contains_key(&registers.idx_x) { Ok(()) } else { Err(FILE_NOT_OPEN) };
+        if result.is_ok() {
+            kernal.input = registers.idx_x;
+        }
+        succeed(registers, result);
+    });
+    routine(cpu, CHKOUT, |kernal, registers, _| {
+        let result = if registers.idx_x == 3 || kernal.files.contains_key(&registers.idx_x) { Ok(()) } else { Err(FILE_NOT_OPEN) };
+        if result.is_ok() {
+            kernal.output = registers.idx_x;
+        }
+        succeed(registers, result);
+    });
+    routine(cpu, CLRCHN, |kernal, _, _| {
+        kernal.input = 0;
+        kernal.output = 0;
+    });
+    routine(cpu, CLALL, |kernal, _, _| {
+        for logical_file in kernal.files.keys().copied().collect::<Vec<u8>>() {
+            kernal.close(logical_file);
+        }
+    });
+    routine(cpu, READST, |_, registers, bus| {
+        registers.accumulator = bus.peek(STATUS);
+    });
+    // The STOP key is never down.
+    routine(cpu, STOP, |_, registers, _| {
+        registers.set_flag(Flags::Zero, false);
+    });
+    routine(cpu, LOAD, |kernal, registers, bus| {
+        let address = u16::from_le_bytes([registers.idx_x, registers.idx_y]);
+        match kernal.load(bus, address) {
+            Ok(end) => {
+                [registers.idx_x, registers.idx_y] = end.to_le_bytes();
+                bus.write(0xAE, registers.idx_x);
+                bus.write(0xAF, registers.idx_y);
+                registers.set_flag(Flags::Carry, false);
+            }
+            Err(error) => succeed(registers, Err(error)),
+        }
+    });
+
+    // Programs poll GETIN until a key arrives, which never happens once the console is closed, so that
+    // ends the run.
+    let getin = kernal.clone();
+    let closed = finished.clone();
+    cpu.add_trap(GETIN, move |registers, bus| {
+        let key = getin.borrow_mut().getin(bus);
+        if key.is_none() {
+            closed.set(true);
+        }
+        registers.accumulator = key.unwrap_or(0);
+        // The KERNAL returns the key with TYA, so the usual JSR GETIN, BEQ loop works.
+        registers.set_flag(Flags::Zero, registers.accumulator == 0);
+        registers.set_flag(Flags::Negative, registers.accumulator & 0x80 != 0);
+        registers.set_flag(Flags::Carry, false);
+    });
+
+    let finished = finished.clone();
+    cpu.add_jump_trap(READY, move |_, _| finished.set(true));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::components::serial::BufferBackend;
+    use crate::components::testing::{load, TempPath};
+
+    // Calls a KERNAL routine as if by JSR from BASIC.
+    fn call(c64: &mut C64, address: u16, a: u8, x: u8, y: u8) {
+        let registers = c64.cpu().registers_mut();
+        (registers.accumulator, registers.idx_x, registers.idx_y) = (a, x, y);
+        registers.stack_pointer = 0xF9;
+        registers.program_counter = address;
+        c64.step();
+    }
+
+    #[test]
+    fn test_c64() {
+        let directory = TempPath::directory("c64");
+        let directory = directory.path();
+        // 10 SYS 2061, then the program.
+        let program = [0x01, 0x08, 0x0B, 0x08, 0x0A, 0x00, 0x9E, b'2', b'0', b'6', b'1', 0x00, 0x00, 0x00, 0x18];
+        std::fs::write(directory.join("hello.prg"), program).unwrap();
+        std::fs::write(directory.join("data"), [0x00, 0xC0, 0xAA, 0xBB]).unwrap();
+
+        let console = BufferBackend::new();
+        let mut c64 = C64::new(directory.join("hello.prg"), Box::new(console.clone())).unwrap();
+        assert_eq!(c64.start(), 2061);
+        assert_eq!(c64.bus().peek(0x080D), 0x18);
+        assert_eq!((c64.bus().peek(0x01), c64.bus().peek(0x2D), c64.bus().peek(0x2E)), (0x37, 0x0E, 0x08));
+
+        for character in b"HI\rA" {
+            call(&mut c64, CHROUT, *character, 0, 0);
+        }
+        assert_eq!(console.take_output(), b"HI\nA");
+        assert!(c64.screen().text().starts_with("HI\nA\n"));
+        c64.cpu().registers_mut().set_flag(Flags::Carry, true);
+        call(&mut c64, PLOT, 0, 0, 0);
+        assert_eq!((c64.cpu().registers().idx_x, c64.cpu().registers().idx_y), (1, 1));
+
+        // Typed lines come back a character at a time, shown as they are typed.
+        console.send(b"ok\r");
+        let mut line = Vec::new();
+        for _ in 0..3 {
+            call(&mut c64, CHRIN, 0, 0, 0);
+            line.push(c64.cpu().registers().accumulator);
+        }
+        assert_eq!(line, [b'O', b'K', 0x0D]);
+        assert_eq!(console.take_output(), b"OK\n");
+
+        // LOAD "DATA",8,1 goes to the address in the file, case does not matter on the host.
+        load(c64.bus(), 0x0900, b"DATA");
+        call(&mut c64, SETNAM, 4, 0x00, 0x09);
+        call(&mut c64, SETLFS, 1, 8, 1);
+        call(&mut c64, LOAD, 0, 0, 0);
+        assert!(!c64.cpu().registers_mut().get_flag(Flags::Carry));
+        assert_eq!((c64.bus().peek(0xC000), c64.bus().peek(0xC001)), (0xAA, 0xBB));
+        assert_eq!((c64.cpu().registers().idx_x, c64.cpu().registers().idx_y), (0x02, 0xC0));
+
+        // OPEN 2,8,2,"DATA" and read it through CHKIN.
+        call(&mut c64, SETLFS, 2, 8, 2);
+        call(&mut c64, OPEN, 0, 0, 0);
+        call(&mut c64, CHKIN, 0, 2, 0);
+        assert!(!c64.cpu().registers_mut().get_flag(Flags::Carry));
+        call(&mut c64, CHRIN, 0, 0, 0);
+        assert_eq!(c64.cpu().registers().accumulator, 0x00);
+        call(&mut c64, CHKIN, 0, 5, 0);
+        assert!(c64.cpu().registers_mut().get_flag(Flags::Carry));
+
+        call(&mut c64, SETNAM, 7, 0x00, 0x09);
+        call(&mut c64, SETLFS, 1, 8, 1);
+        call(&mut c64, LOAD, 0, 0, 0);
+        assert!(c64.cpu().registers_mut().get_flag(Flags::Carry));
+        assert_eq!(c64.cpu().registers().accumulator, 4);
+
+        // Returning to BASIC ends the run.
+        assert!(c64.running());
+        c64.step();
+        assert!(!c64.running());
+    }
+
+    #[test]
+    fn test_c64_program() {
+        // Asks for a name and reads it into $C000 until RETURN, then returns to BASIC.
+        let source = "
+            CHROUT = $FFD2
+            CHRIN  = $FFCF
+            READST = $FFB7
+
+                .org $0801
+                .word end, 10
+                .byte $9E, \"2061\", 0
+            end:
+                .word 0
+                ldx #0
+            print:
+                lda message,x
+                beq read
+                jsr CHROUT
+                inx
+                bne print
+            read:
+                ldx #0
+            key:
+                jsr CHRIN
+                sta $C000,x
+                inx
+                cmp #$0D
+                bne key
+                jsr READST
+                sta $C100
+                rts
+            message:
+                .asciiz \"NAME?\"
+        ";
+        let image = assembler::assemble(source).unwrap().image(0x0801, 0x40, 0x00).unwrap();
+        let directory = TempPath::directory("c64-program");
+        let program = directory.path().join("name.prg");
+        std::fs::write(&program, [&[0x01, 0x08][..], &image].concat()).unwrap();
+
+        // The input ends without RETURN, which ends the line and sets the end of file status.
+        let console = BufferBackend::new();
+        console.send(b"bob");
+        let mut c64 = C64::new(&program, Box::new(console.clone())).unwrap();
+        assert_eq!(c64.start(), 2061);
+        for _ in 0..1000 {
+            if !c64.running() {
+                break;
+            }
+            c64.step();
+        }
+        assert!(!c64.running());
+        assert_eq!(console.take_output(), b"NAME?BOB\n");
+        assert_eq!((0xC000..0xC004).map(|address| c64.bus().peek(address)).collect::<Vec<u8>>(), [b'B', b'O', b'B', 0x0D]);
+        assert_eq!(c64.bus().peek(0xC100), END_OF_FILE);
+        assert!(c64.screen().text().starts_with("NAME?BOB\n"));
+
+        // READY is jumped to, nothing is pulled off the stack there.
+        c64.step();
+        assert_eq!((c64.cpu().registers().program_counter, c64.cpu().registers().stack_pointer), (READY, 0xFB));
+
+        // A program up to $FFFF ends at $0000.
+        std::fs::write(&program, [0xFE, 0xFF, 0xEA, 0xEA]).unwrap();
+        let mut c64 = C64::new(&program, Box::new(BufferBackend::new())).unwrap();
+        assert_eq!((c64.bus().peek(0x2D), c64.bus().peek(0x2E)), (0x00, 0x00));
+    }
+
+    #[test]
+    fn test_getin_closed() {
+        // Stores keys at $C000 as they are polled, forever.
+        let source = "
+            GETIN = $FFE4
+
+                .org $0801
+                .word end, 10
+                .byte $9E, \"2061\", 0
+            end:
+                .word 0
+                ldx #0
+            poll:
+                jsr GETIN
+                beq poll
+                sta $C000,x
+                inx
+                bne poll
+        ";
+        let image = assembler::assemble(source).unwrap().image(0x0801, 0x20, 0x00).unwrap();
+        let directory = TempPath::directory("c64-getin");
+        let program = directory.path().join("keys.prg");
+        std::fs::write(&program, [&[0x01, 0x08][..], &image].concat()).unwrap();
+
+        // Once the input is used up the next poll ends the run with the end of file status.
+        let console = BufferBackend::new();
+        console.send(b"ab");
+        let mut c64 = C64::new(&program, Box::new(console.clone())).unwrap();
+        for _ in 0..1000 {
+            if !c64.running() {
+                break;
+            }
+            c64.step();
+        }
+        assert!(!c64.running());
+        assert_eq!((c64.bus().peek(0xC000), c64.bus().peek(0xC001), c64.bus().peek(0xC002)), (b'A', b'B', 0x00));
+        assert_eq!(c64.bus().peek(STATUS), END_OF_FILE);
+        assert_eq!(c64.cpu().registers().accumulator, 0x00);
+    }
+}
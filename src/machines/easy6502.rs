@@ -0,0 +1,185 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::components::bus::{Bus, BusBuilder};
+use crate::components::cpu6502::CPU6502;
+use crate::components::device::Addressable;
+use crate::components::framebuffer::{Framebuffer, FramebufferView};
+use crate::components::memory::{MemoryError, RandomAccessMemory};
+use crate::components::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+use crate::machines::MachineError;
+
+pub const SCREEN_ADDRESS: u16 = 0x0200;
+pub const RANDOM_ADDRESS: u16 = 0x00FE;
+pub const KEY_ADDRESS: u16 = 0x00FF;
+pub const PROGRAM_ADDRESS: u16 = 0x0600;
+
+const BRK: u8 = 0x00;
+
+// $FE reads a new random byte every time, $FF holds the last key pressed until the program clears it.
+struct Inputs {
+    random: u32,
+    key: Rc<Cell<u8>>,
+}
+
+impl Inputs {
+    // Xorshift, so runs with the same seed see the same numbers.
+    fn next_random(&mut self) -> u8 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        return self.random as u8;
+    }
+}
+
+impl Addressable for Inputs {
+    fn get_address_space(&self) -> (u16, u16) {
+        return (RANDOM_ADDRESS, KEY_ADDRESS);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        return if address == 0 { self.random as u8 } else { self.key.get() };
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        return if address == 0 { self.next_random() } else { self.key.get() };
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        if address == 1 {
+            self.key.set(data);
+        }
+    }
+
+    fn name(&self) -> &str {
+        return "Inputs";
+    }
+}
+
+impl Snapshot for Inputs {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.random);
+        writer.write_u8(self.key.get());
+    }
+
+    fn restore(&mut self, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+        self.random = reader.read_u32()?;
+        self.key.set(reader.read_u8()?);
+        return Ok(());
+    }
+}
+
+// The machine of the easy6502 tutorial: 64K RAM with a 32x32 pixel screen at $0200-$05FF, a random
+// byte at $FE and the last key at $FF on top, and the program loaded and started at $0600. Like in the
+// browser, the program ends at a BRK instead of jumping through the empty IRQ vector.
+pub struct Easy6502 {
+    cpu: CPU6502,
+    bus: Bus,
+    screen: FramebufferView,
+    key: Rc<Cell<u8>>,
+}
+
+impl Easy6502 {
+    // The seed must not be 0.
+    pub fn new(program: &[u8], seed: u32) -> Result<Easy6502, MachineError> {
+        assert!(seed != 0, "the random number generator needs a non-zero seed");
+        let key = Rc::new(Cell::new(0));
+        let framebuffer = Framebuffer::new(SCREEN_ADDRESS, 32, 32)?;
+        let screen = framebuffer.view();
+
+        let mut bus = BusBuilder::new()
+            .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000)?))
+            .map_with_priority(SCREEN_ADDRESS, 0x05FF, Box::new(framebuffer), 1)
+            .map_with_priority(RANDOM_ADDRESS, KEY_ADDRESS, Box::new(Inputs { random: seed, key: key.clone() }), 1)
+            .build()?;
+        if PROGRAM_ADDRESS as usize + program.len() > 0x10000 {
+            return Err(MemoryError::OutOfAddressSpace { address: PROGRAM_ADDRESS, size: program.len() }.into());
+        }
+        for (offset, byte) in program.iter().enumerate() {
+            bus.write(PROGRAM_ADDRESS + offset as u16, *byte);
+        }
+
+        let mut cpu = CPU6502::new();
+        cpu.registers_mut().program_counter = PROGRAM_ADDRESS;
+        return Ok(Easy6502 { cpu, bus, screen, key });
+    }
+
+    // Does nothing once the program reached a BRK.
+    pub fn step(&mut self) {
+        if self.halted() {
+            return;
+        }
+        self.cpu.tick(&mut self.bus);
+    }
+
+    pub fn halted(&self) -> bool {
+        return self.bus.peek(self.cpu.registers().program_counter) == BRK;
+    }
+
+    // Keys are ASCII codes, as the browser version passes them.
+    pub fn press(&self, key: u8) {
+        self.key.set(key);
+    }
+
+    pub fn screen(&self) -> &FramebufferView {
+        return &self.screen;
+    }
+
+    pub fn cpu(&mut self) -> &mut CPU6502 {
+        return &mut self.cpu;
+    }
+
+    pub fn bus(&mut self) -> &mut Bus {
+        return &mut self.bus;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framebuffer() {
+        let mut easy6502 = Easy6502::new(&[0x18], 1).unwrap();
+        let bus = easy6502.bus();
+        bus.write(0x0200, 0x01); // white in the top left corner
+        bus.write(0x0221, 0x12); // red one row down, colours only use the low nibble
+        bus.write(0x05FF, 0x0E);
+        assert_ne!(bus.read(0x00FE), bus.read(0x00FE));
+        easy6502.press(b'w');
+        assert_eq!(easy6502.bus().read(0x00FF), b'w');
+        easy6502.step();
+        assert_eq!(easy6502.cpu().registers().program_counter, 0x0601);
+
+        let screen = easy6502.screen();
+        assert_eq!((screen.pixel(0, 0), screen.pixel(1, 1), screen.pixel(31, 31)), (0xFFFFFF, 0x880000, 0x0088FF));
+        assert!(screen.render().starts_with("\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m▀"));
+        assert_eq!(screen.render().lines().count(), 16);
+
+        let mut ppm = Vec::new();
+        screen.write_ppm(&mut ppm).unwrap();
+        assert_eq!(&ppm[..13], b"P6\n32 32\n255\n");
+        assert_eq!(&ppm[13..16], &[0xFF, 0xFF, 0xFF]);
+        assert_eq!(ppm.len(), 13 + 32 * 32 * 3);
+    }
+
+    #[test]
+    fn test_brk() {
+        let program = [
+            0xA9, 0x01,       // LDA #$01
+            0x8D, 0x00, 0x02, // STA $0200
+            0x00,             // BRK
+            0x8D, 0x01, 0x02, // STA $0201
+        ];
+        let mut easy6502 = Easy6502::new(&program, 1).unwrap();
+        let stack_pointer = easy6502.cpu().registers().stack_pointer;
+        for _ in 0..10 {
+            easy6502.step();
+        }
+        assert!(easy6502.halted());
+        assert_eq!(easy6502.cpu().registers().program_counter, 0x0605);
+        assert_eq!((easy6502.screen().pixel(0, 0), easy6502.screen().pixel(1, 0)), (0xFFFFFF, 0x000000));
+        // BRK was not executed, nothing went onto the stack.
+        assert_eq!(easy6502.cpu().registers().stack_pointer, stack_pointer);
+    }
+}
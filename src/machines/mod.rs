@@ -0,0 +1,42 @@
+use std::fmt;
+
+use crate::components::bus::MapError;
+use crate::components::memory::MemoryError;
+
+pub mod apple1;
+pub mod be6502;
+pub mod c64;
+pub mod easy6502;
+
+// Why a machine preset could not be put together, usually a missing or oversized ROM image.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MachineError {
+    Memory(MemoryError),
+    Map(MapError),
+    // The ROM image does not fill the chip it is burned into.
+    RomSize { expected: usize, actual: usize },
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            MachineError::Memory(error) => write!(f, "{}", error),
+            MachineError::Map(error) => write!(f, "{}", error),
+            MachineError::RomSize { expected, actual } => write!(f, "ROM image has {:#x} bytes, expected {:#x}", actual, expected),
+        };
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+impl From<MemoryError> for MachineError {
+    fn from(error: MemoryError) -> MachineError {
+        return MachineError::Memory(error);
+    }
+}
+
+impl From<MapError> for MachineError {
+    fn from(error: MapError) -> MachineError {
+        return MachineError::Map(error);
+    }
+}
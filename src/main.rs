@@ -1,16 +1,376 @@
 //#![allow(warnings)]
 
-mod components;
-mod assembler;
+use scotty_rust::{assembler, components, machines};
+use scotty_rust::components::bus::Bus;
+use scotty_rust::components::cpu6502::CPU6502;
+use scotty_rust::components::memory::RandomAccessMemory;
+#[cfg(unix)]
+use scotty_rust::components::serial::PtyBackend;
+use scotty_rust::components::serial::{SerialBackend, TcpBackend, TerminalBackend};
 
-use crate::components::bus::Bus;
-use crate::components::cpu6502::CPU6502;
-use crate::components::memory::RandomAccessMemory;
+// Compares a generated trace against a reference log, e.g. `scotty_rust trace-diff cpu.log nestest.log`.
+fn trace_diff(generated: &str, reference: &str) {
+    let open = |path: &str| std::io::BufReader::new(std::fs::File::open(path)
+        .unwrap_or_else(|error| panic!("could not open {}: {}", path, error)));
+
+    match components::trace::diff(open(generated), open(reference)) {
+        Ok(None) => println!("traces match"),
+        Ok(Some(divergence)) => {
+            println!("{}", divergence);
+            std::process::exit(1);
+        }
+        Err(error) => panic!("could not read traces: {}", error),
+    }
+}
+
+// Writes a nestest.log formatted trace to stdout, e.g. `scotty_rust trace nestest.nes 8991 > cpu.log` for
+// trace-diff. An iNES file is run like the automated nestest: its PRG ROM at $8000 and $C000, 2K RAM
+// mirrored up to $1FFF, started at $C000. Any other image is a ROM ending at $FFFF, started through
+// its reset vector. Writes to the ROM are logged on stderr, with --strict the trace stops at the first.
+fn trace(image: &str, instructions: u64, strict: bool) {
+    use components::memory::{ReadOnlyMemory, WritePolicy};
+
+    let policy = if strict { WritePolicy::Error } else { WritePolicy::Log };
+    let fault;
+
+    let data = std::fs::read(image).unwrap_or_else(|error| panic!("could not open {}: {}", image, error));
+    let mut cpu = CPU6502::new();
+    let mut bus = if data.starts_with(b"NES\x1A") {
+        let size = *data.get(4).unwrap_or_else(|| panic!("{} is truncated", image)) as usize * 0x4000;
+        // A 512 byte trainer sits between the header and the PRG ROM when bit 2 of flags 6 is set.
+        let start = if data.get(6).is_some_and(|flags| flags & 0b00000100 != 0) { 16 + 512 } else { 16 };
+        let prg = data.get(start..start + size).unwrap_or_else(|| panic!("{} is truncated", image));
+        let rom = ReadOnlyMemory::new(0x8000, prg).and_then(|rom| rom.with_window(0x8000))
+            .unwrap_or_else(|error| panic!("invalid PRG ROM in {}: {}", image, error))
+            .with_write_policy(policy);
+        fault = rom.fault();
+        let mut bus = components::bus::BusBuilder::new()
+            .map(0x0000, 0x07FF, Box::new(RandomAccessMemory::new(0x0000, 0x0800).unwrap()))
+            .mirror(0x0800, 0x1FFF, 0x0000, 0x07FF)
+            .attach(Box::new(rom))
+            .build()
+            .unwrap();
+        cpu.reset(&mut bus);
+        cpu.registers_mut().program_counter = 0xC000;
+        cpu.registers_mut().status_flags = 0x24;
+        bus
+    } else {
+        if data.is_empty() || data.len() > 0x10000 {
+            panic!("{} is not a ROM image of up to 64K", image);
+        }
+        let address = 0x10000 - data.len();
+        let mut builder = components::bus::BusBuilder::new();
+        if address > 0 {
+            builder = builder.attach(Box::new(RandomAccessMemory::new(0x0000, address).unwrap()));
+        }
+        let rom = ReadOnlyMemory::new(address as u16, &data).unwrap().with_write_policy(policy);
+        fault = rom.fault();
+        let mut bus = builder.attach(Box::new(rom)).build().unwrap();
+        cpu.reset(&mut bus);
+        bus
+    };
+
+    cpu.set_trace(Some(Box::new(std::io::BufWriter::new(std::io::stdout()))));
+    for _ in 0..instructions {
+        cpu.tick(&mut bus);
+        if let Some((address, data)) = fault.take() {
+            eprintln!("stopped after a write of {:#04x} to ROM at {:#06x}", data, address);
+            break;
+        }
+    }
+    cpu.set_trace(None);
+}
+
+// Measures emulation speed in emulated MHz, e.g. `scotty_rust bench 10000000`.
+fn bench(instructions: u64) {
+    let mut cpu : CPU6502 = CPU6502::new();
+    let mut bus : Bus = Bus::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap());
+
+    let program: [u8; 11] = [
+        0x69, 0x01, // ADC #$01
+        0x29, 0x7F, // AND #$7F
+        0x06, 0x80, // ASL $80
+        0x16, 0x81, // ASL $81,X
+        0x75, 0x82, // ADC $82,X
+        0x18,       // CLC
+    ];
+    for (address, byte) in program.iter().enumerate() {
+        bus.write(address as u16, *byte);
+    }
+
+    let start = std::time::Instant::now();
+    for _ in 0..instructions {
+        if cpu.registers().program_counter as usize >= program.len() {
+            cpu.registers_mut().program_counter = 0x0000;
+        }
+        cpu.tick(&mut bus);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!("{} instructions, {} cycles in {:.3}s: {:.2} MHz", instructions, cpu.cycles(), elapsed,
+        cpu.cycles() as f64 / elapsed / 1_000_000.0);
+}
+
+// Runs a program as a command-line tool through the semihosting device, e.g.
+// `scotty_rust semihost sort.bin input.txt`. The image is loaded and started at $0200, the device sits
+// at $FFF0 and the exit code the program writes becomes the exit status.
+fn semihost(program: &str, arguments: &[String]) {
+    use components::semihosting::{Semihosting, DEFAULT_ADDRESS};
+
+    let image = std::fs::read(program).unwrap_or_else(|error| panic!("could not open {}: {}", program, error));
+    if image.len() > DEFAULT_ADDRESS as usize - 0x0200 {
+        eprintln!("{} does not fit below ${:04X}", program, DEFAULT_ADDRESS);
+        std::process::exit(1);
+    }
+
+    let mut arguments = arguments.to_vec();
+    arguments.insert(0, String::from(program));
+    let semihosting = Semihosting::new(DEFAULT_ADDRESS, arguments).unwrap();
+    let host = semihosting.host();
+    let mut bus = components::bus::BusBuilder::new()
+        .attach(Box::new(RandomAccessMemory::new(0x0000, 0x10000).unwrap()))
+        .map_with_priority(DEFAULT_ADDRESS, DEFAULT_ADDRESS + 0x07, Box::new(semihosting), 1)
+        .build()
+        .unwrap();
+    for (offset, byte) in image.iter().enumerate() {
+        bus.write(0x0200 + offset as u16, *byte);
+    }
+
+    let mut cpu = CPU6502::new();
+    cpu.registers_mut().program_counter = 0x0200;
+    loop {
+        cpu.tick(&mut bus);
+        if let Some(code) = host.service(&mut bus) {
+            std::process::exit(code as i32);
+        }
+    }
+}
+
+// Runs an Apple 1 in the host terminal, e.g. `scotty_rust apple1 wozmon.rom`, or on another serial port
+// with `--serial`. Ctrl-C quits, piped input ends the run once the monitor waits for more.
+fn apple1(monitor: &str, options: &[String]) -> Result<(), machines::MachineError> {
+    let console = serial_port(options).unwrap_or_else(|| Box::new(TerminalBackend::new()));
+    let mut apple1 = machines::apple1::Apple1::new(monitor, console)?;
+    while apple1.running() {
+        apple1.step();
+    }
+    return Ok(());
+}
+
+// Runs a C64 text-mode program, e.g. `scotty_rust c64 hello.prg`, printing through the KERNAL to the
+// terminal. Files the program loads or opens come from the directory of the program. Ctrl-] quits.
+// `--screen` prints the screen RAM at the end of the run, in colour on a terminal and as plain text
+// otherwise.
+fn c64(program: &str, options: &[String]) {
+    let quit = std::rc::Rc::new(std::cell::Cell::new(false));
+    let console = HostConsole { terminal: TerminalBackend::new(), quit: quit.clone(), button: None };
+    let mut c64 = machines::c64::C64::new(program, Box::new(console))
+        .unwrap_or_else(|error| panic!("could not start {}: {}", program, error));
+    while c64.running() && !quit.get() {
+        c64.step();
+    }
+    if options.iter().any(|option| option == "--screen") {
+        if std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+            print!("\n{}", c64.screen().render());
+        } else {
+            println!("{}", c64.screen().text());
+        }
+    }
+}
+
+// The serial port selected with `--serial pty` or `--serial tcp:<port>`, None for the host terminal.
+// Where to attach is printed on stderr.
+fn serial_port(options: &[String]) -> Option<Box<dyn SerialBackend>> {
+    let position = options.iter().position(|option| option == "--serial")?;
+    let port = options.get(position + 1).map(String::as_str).unwrap_or_default();
+    #[cfg(unix)]
+    if port == "pty" {
+        let pty = PtyBackend::new().unwrap_or_else(|error| panic!("could not open a pseudo-terminal: {}", error));
+        eprintln!("serial port on {}", pty.path().display());
+        return Some(Box::new(pty));
+    }
+    if let Some(number) = port.strip_prefix("tcp:") {
+        let number = number.parse().unwrap_or_else(|_| panic!("invalid TCP port {}", number));
+        let tcp = TcpBackend::new(number).unwrap_or_else(|error| panic!("could not listen on port {}: {}", number, error));
+        eprintln!("serial port on {}", tcp.local_addr().map_or(String::from("?"), |address| address.to_string()));
+        return Some(Box::new(tcp));
+    }
+    panic!("--serial takes pty or tcp:<port>, not '{}'", port);
+}
+
+// The host terminal as a serial port, stopping the emulation on Ctrl-] the way telnet does. Ctrl-B is
+// taken as a press of the machine's button when it has one.
+struct HostConsole {
+    terminal: TerminalBackend,
+    quit: std::rc::Rc<std::cell::Cell<bool>>,
+    button: Option<std::rc::Rc<std::cell::Cell<bool>>>,
+}
+
+impl SerialBackend for HostConsole {
+    fn receive(&mut self) -> Option<u8> {
+        return match self.terminal.receive() {
+            Some(0x1D) => {
+                self.quit.set(true);
+                None
+            }
+            Some(0x02) if self.button.is_some() => {
+                self.button.as_ref().unwrap().set(true);
+                None
+            }
+            byte => byte,
+        };
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        self.terminal.transmit(byte);
+    }
+
+    fn closed(&self) -> bool {
+        return self.quit.get() || self.terminal.closed();
+    }
+}
+
+// Runs a BE6502 with its serial port on the terminal, e.g. `scotty_rust be6502 a.out --1mhz`. Pass
+// `--lcd-4bit` for the LCD wiring of the later videos. The LCD is redrawn below the serial output
+// whenever it changes. Ctrl-B presses the button on CA1, Ctrl-] quits. With `--serial pty` or
+// `--serial tcp:<port>` the ACIA is on that port instead and Ctrl-C quits. With `--asm` the file is
+// assembly source, e.g. `scotty_rust be6502 hello.s --asm`, which is assembled into the EEPROM first.
+fn be6502(rom: &str, options: &[String]) -> Result<(), machines::MachineError> {
+    use components::hd44780::Wiring;
+
+    let quit = std::rc::Rc::new(std::cell::Cell::new(false));
+    let button = std::rc::Rc::new(std::cell::Cell::new(false));
+    let serial = serial_port(options).unwrap_or_else(|| {
+        Box::new(HostConsole { terminal: TerminalBackend::new(), quit: quit.clone(), button: Some(button.clone()) })
+    });
+    let wiring = if options.iter().any(|option| option == "--lcd-4bit") { Wiring::four_bit() } else { Wiring::eight_bit() };
+    let mut be6502 = if options.iter().any(|option| option == "--asm") {
+        use machines::be6502::{Be6502, ROM_ADDRESS, ROM_SIZE};
+
+        let source = std::fs::read_to_string(rom).unwrap_or_else(|error| panic!("could not open {}: {}", rom, error));
+        let image = assembler::assemble(&source).and_then(|program| program.image(ROM_ADDRESS, ROM_SIZE, 0x00));
+        match image {
+            Ok(image) => Be6502::with_rom(&image, serial, wiring)?,
+            Err(error) => {
+                eprintln!("{}: {}", rom, error);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        machines::be6502::Be6502::new(rom, serial, wiring)?
+    };
+    be6502.set_paced(options.iter().any(|option| option == "--1mhz"));
+
+    let mut steps: u64 = 0;
+    while !quit.get() {
+        be6502.step();
+        if button.take() {
+            be6502.press_button();
+        }
+        steps += 1;
+        if steps.is_multiple_of(10_000) && be6502.lcd().take_changed() {
+            print!("\n{}", be6502.lcd().render());
+        }
+    }
+    return Ok(());
+}
+
+// Runs an easy6502 program, e.g. `scotty_rust easy6502 snake.bin`, drawing the screen in the terminal
+// with keys going to $FF. Ctrl-] quits, as does a BRK. `--headless <instructions> <frame.png|frame.ppm>`
+// runs up to that many instructions without a terminal and writes the final frame instead.
+fn easy6502(program: &str, options: &[String]) {
+    let program = std::fs::read(program).unwrap_or_else(|error| panic!("could not open {}: {}", program, error));
+    let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(1, |time| time.subsec_nanos() | 1);
+    let mut easy6502 = machines::easy6502::Easy6502::new(&program, seed)
+        .unwrap_or_else(|error| panic!("could not start easy6502: {}", error));
+
+    if options.len() == 3 && options[0] == "--headless" {
+        let instructions: u64 = options[1].parse().unwrap_or_else(|_| panic!("invalid instruction count {}", options[1]));
+        for _ in 0..instructions {
+            if easy6502.halted() {
+                break;
+            }
+            easy6502.step();
+        }
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&options[2])
+            .unwrap_or_else(|error| panic!("could not create {}: {}", options[2], error)));
+        let result = if options[2].ends_with(".ppm") { easy6502.screen().write_ppm(&mut file) } else { easy6502.screen().write_png(&mut file) };
+        result.unwrap_or_else(|error| panic!("could not write {}: {}", options[2], error));
+        return;
+    }
+
+    let mut terminal = TerminalBackend::new();
+    let mut frame = Vec::new();
+    let mut steps: u64 = 0;
+    loop {
+        easy6502.step();
+        steps += 1;
+        if !steps.is_multiple_of(10_000) && !easy6502.halted() {
+            continue;
+        }
+        match terminal.receive() {
+            Some(0x1D) => return,
+            Some(key) => easy6502.press(key),
+            None => {}
+        }
+        if easy6502.screen().frame() != frame {
+            frame = easy6502.screen().frame();
+            print!("\x1b[H\x1b[2J{}", easy6502.screen().render());
+        }
+        if easy6502.halted() {
+            return;
+        }
+    }
+}
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 4 && args[1] == "trace-diff" {
+        trace_diff(&args[2], &args[3]);
+        return;
+    }
+    if args.len() >= 3 && args[1] == "trace" {
+        let strict = args[3..].iter().any(|arg| arg == "--strict");
+        trace(&args[2], args.get(3).and_then(|count| count.parse().ok()).unwrap_or(10_000), strict);
+        return;
+    }
+    if args.len() >= 2 && args[1] == "bench" {
+        bench(args.get(2).and_then(|count| count.parse().ok()).unwrap_or(10_000_000));
+        return;
+    }
+    if args.len() >= 3 && args[1] == "be6502" {
+        if let Err(error) = be6502(&args[2], &args[3..]) {
+            eprintln!("could not start the BE6502: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.len() >= 3 && args[1] == "semihost" {
+        semihost(&args[2], &args[3..]);
+        return;
+    }
+    if args.len() >= 3 && args[1] == "easy6502" {
+        easy6502(&args[2], &args[3..]);
+        return;
+    }
+    if args.len() >= 3 && args[1] == "apple1" {
+        if let Err(error) = apple1(&args[2], &args[3..]) {
+            eprintln!("could not start the Apple 1: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.len() >= 3 && args[1] == "c64" {
+        c64(&args[2], &args[3..]);
+        return;
+    }
+
     let mut cpu : CPU6502 = CPU6502::new();
-    let memory : RandomAccessMemory = RandomAccessMemory::new(0x0000);
+    let memory : RandomAccessMemory = RandomAccessMemory::new(0x0000, 0x8000).unwrap();
     let mut bus : Bus = Bus::new(memory);
+    if args.iter().any(|arg| arg == "--memory-map") {
+        print!("{}", bus.memory_map());
+    }
 
     bus.write(0x00FF, 0x01); // Write '0x01' to memory location '0x0100'
 